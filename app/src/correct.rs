@@ -0,0 +1,158 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+use clap::{Subcommand, ValueEnum};
+use rxtrack_model::{
+    corrections::{
+        amend_event, amend_fill_request_step, list_corrections, void_event, void_fill_request_step,
+        Correction,
+    },
+    Error, EventId, EventType, FillRequestId, RxId,
+};
+use sea_orm::{ConnectionTrait, TransactionTrait};
+use time::Date;
+
+use crate::{dates::parse_date, rx::require_rx};
+
+/// A step of a fill request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Step {
+    Requested,
+    Filled,
+    PickedUp,
+    Cancelled,
+}
+
+impl From<Step> for EventType {
+    fn from(value: Step) -> Self {
+        match value {
+            Step::Requested => EventType::RequestFill,
+            Step::Filled => EventType::Fill,
+            Step::PickedUp => EventType::PickUp,
+            Step::Cancelled => EventType::RefillCancel,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CorrectCommand {
+    /// Change the date of an event, and the matching fill request step
+    AmendEvent {
+        /// Event ID, as shown by `rxtrack events`
+        event: i32,
+        /// The correct date
+        #[arg(value_parser = parse_date)]
+        date: Date,
+        /// Why the date was wrong
+        #[arg(long, default_value = "")]
+        reason: String,
+    },
+    /// Void an event recorded by mistake, and the matching fill request step
+    VoidEvent {
+        /// Event ID, as shown by `rxtrack events`
+        event: i32,
+        /// Why the event is void
+        #[arg(long, default_value = "")]
+        reason: String,
+    },
+    /// Change the date of a step of a fill request, and the matching event
+    AmendStep {
+        /// Fill request ID, as shown by `rxtrack history`
+        request: i32,
+        #[arg(value_enum)]
+        step: Step,
+        /// The correct date
+        #[arg(value_parser = parse_date)]
+        date: Date,
+        /// Why the date was wrong
+        #[arg(long, default_value = "")]
+        reason: String,
+    },
+    /// Void a step of a fill request recorded by mistake, and the matching event
+    VoidStep {
+        /// Fill request ID, as shown by `rxtrack history`
+        request: i32,
+        #[arg(value_enum)]
+        step: Step,
+        /// Why the step is void
+        #[arg(long, default_value = "")]
+        reason: String,
+    },
+    /// List the corrections made to a prescription
+    List {
+        /// Prescription ID
+        rx: i32,
+    },
+}
+
+fn print_correction(correction: &Correction) {
+    let target = match (correction.event_id, correction.fill_request_id) {
+        (Some(event), Some(request)) => format!("{} and {}", event, request),
+        (Some(event), None) => event.to_string(),
+        (None, Some(request)) => request.to_string(),
+        (None, None) => "-".to_owned(),
+    };
+    let change = match correction.new_date {
+        Some(date) => format!("{} -> {}", correction.original_date, date),
+        None => format!("{} voided", correction.original_date),
+    };
+    println!(
+        "{}\t{}\t{:?}\t{}\t{}",
+        i32::from(correction.id),
+        target,
+        correction.event_type,
+        change,
+        correction.reason
+    );
+}
+
+pub async fn run(db: &impl TransactionTrait, command: CorrectCommand) -> Result<(), Error> {
+    let txn = db.begin().await?;
+    let id = match command {
+        CorrectCommand::AmendEvent {
+            event,
+            date,
+            reason,
+        } => amend_event(&txn, EventId::from(event), date, &reason).await?,
+        CorrectCommand::VoidEvent { event, reason } => {
+            void_event(&txn, EventId::from(event), &reason).await?
+        }
+        CorrectCommand::AmendStep {
+            request,
+            step,
+            date,
+            reason,
+        } => {
+            amend_fill_request_step(
+                &txn,
+                FillRequestId::from(request),
+                step.into(),
+                date,
+                &reason,
+            )
+            .await?
+        }
+        CorrectCommand::VoidStep {
+            request,
+            step,
+            reason,
+        } => {
+            void_fill_request_step(&txn, FillRequestId::from(request), step.into(), &reason).await?
+        }
+        CorrectCommand::List { rx } => {
+            list(&txn, RxId::from(rx)).await?;
+            return Ok(());
+        }
+    };
+    txn.commit().await?;
+    println!("Recorded {}", id);
+    Ok(())
+}
+
+async fn list(db: &impl ConnectionTrait, rx: RxId) -> Result<(), Error> {
+    require_rx(db, rx).await?;
+    for correction in list_corrections(db, rx).await? {
+        print_correction(&correction);
+    }
+    Ok(())
+}
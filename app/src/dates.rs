@@ -0,0 +1,17 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+use time::{macros::format_description, Date, OffsetDateTime};
+
+/// Today's date, in local time if it can be determined, otherwise UTC.
+pub fn today() -> Date {
+    OffsetDateTime::now_local()
+        .unwrap_or_else(|_| OffsetDateTime::now_utc())
+        .date()
+}
+
+/// Parse a date in ISO 8601 format, e.g. 2023-01-31
+pub fn parse_date(s: &str) -> Result<Date, String> {
+    Date::parse(s, format_description!("[year]-[month]-[day]"))
+        .map_err(|e| format!("expected a date like 2023-01-31: {}", e))
+}
@@ -0,0 +1,132 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+use clap::Args;
+use rxtrack_model::{
+    due::{list_due_by_pharmacy, DueSummary},
+    Error, PersonId,
+};
+use sea_orm::ConnectionTrait;
+use time::Date;
+
+use crate::{
+    dates::{parse_date, today},
+    inventory::describe_on_hand,
+    pharmacy::describe_pharmacy,
+    renewal::describe_renewal,
+};
+
+#[derive(Debug, Args)]
+pub struct DueArgs {
+    /// Only include prescriptions of this person
+    #[arg(long)]
+    person: Option<i32>,
+    /// Check what is due as of this date, defaults to today
+    #[arg(long, value_parser = parse_date)]
+    date: Option<Date>,
+}
+
+pub async fn run(db: &impl ConnectionTrait, args: DueArgs) -> Result<(), Error> {
+    let today = args.date.unwrap_or_else(today);
+    let groups = list_due_by_pharmacy(db, args.person.map(PersonId::from), today).await?;
+    if groups.is_empty() {
+        println!("Nothing due");
+        return Ok(());
+    }
+
+    // Without any pharmacies set up, there is nothing to group by
+    if let [(None, summary)] = groups.as_slice() {
+        print_summary(summary, today, "");
+        return Ok(());
+    }
+    for (i, (pharmacy, summary)) in groups.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        match pharmacy {
+            Some(pharmacy) => println!("{}:", describe_pharmacy(&pharmacy.pharmacy)),
+            None => println!("No pharmacy:"),
+        }
+        print_summary(summary, today, "  ");
+    }
+    Ok(())
+}
+
+fn print_summary(summary: &DueSummary, today: Date, indent: &str) {
+    if !summary.low_on_hand.is_empty() {
+        println!("{}Running low on hand:", indent);
+        for (rx, supply) in &summary.low_on_hand {
+            println!(
+                "{}  {}\t{}\t{}",
+                indent,
+                i32::from(rx.id),
+                rx.name,
+                describe_on_hand(supply)
+            );
+        }
+    }
+    if !summary.needs_renewal.is_empty() {
+        println!("{}Renew the script first:", indent);
+        for (rx, problem) in &summary.needs_renewal {
+            println!("{}  {}\t{}\t{}", indent, i32::from(rx.id), rx.name, problem);
+        }
+    }
+    if !summary.renewals.is_empty() {
+        println!("{}Waiting on a renewal:", indent);
+        for (rx, renewal) in &summary.renewals {
+            let follow_up_now = if renewal.follow_up.is_some_and(|date| date <= today) {
+                " - follow up now"
+            } else {
+                ""
+            };
+            println!(
+                "{}  {}\t{}\t{}{}",
+                indent,
+                i32::from(rx.id),
+                rx.name,
+                describe_renewal(renewal),
+                follow_up_now
+            );
+        }
+    }
+    if !summary.request_now.is_empty() {
+        println!("{}Request a refill now:", indent);
+        for (rx, reminder) in &summary.request_now {
+            println!(
+                "{}  {}\t{}\t{} (since {})",
+                indent,
+                i32::from(rx.id),
+                rx.name,
+                reminder.description,
+                reminder.date
+            );
+        }
+    }
+    if !summary.waiting_on_pharmacy.is_empty() {
+        println!("{}Waiting on the pharmacy:", indent);
+        for (rx, date_requested) in &summary.waiting_on_pharmacy {
+            match date_requested {
+                Some(date) => println!(
+                    "{}  {}\t{}\trequested {}",
+                    indent,
+                    i32::from(rx.id),
+                    rx.name,
+                    date
+                ),
+                None => println!("{}  {}\t{}", indent, i32::from(rx.id), rx.name),
+            }
+        }
+    }
+    if !summary.ready_to_pick_up.is_empty() {
+        println!("{}Ready to pick up:", indent);
+        for (rx, date_filled) in &summary.ready_to_pick_up {
+            println!(
+                "{}  {}\t{}\tfilled {}",
+                indent,
+                i32::from(rx.id),
+                rx.name,
+                date_filled
+            );
+        }
+    }
+}
@@ -0,0 +1,45 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+use std::path::PathBuf;
+
+use rxtrack_model::Error;
+use sea_orm::DbErr;
+
+/// Errors from the command-line interface, beyond those of the model.
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error(transparent)]
+    Model(#[from] Error),
+
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("{}: {message}", path.display())]
+    Mapping { path: PathBuf, message: String },
+
+    #[error("{}, line {line}: {message}", path.display())]
+    Csv {
+        path: PathBuf,
+        line: u64,
+        message: String,
+    },
+}
+
+impl From<DbErr> for AppError {
+    fn from(value: DbErr) -> Self {
+        AppError::Model(value.into())
+    }
+}
+
+impl AppError {
+    pub fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        AppError::Io {
+            path: path.into(),
+            source,
+        }
+    }
+}
@@ -0,0 +1,90 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+use std::path::PathBuf;
+
+use clap::{Args, Subcommand};
+use rxtrack_model::{
+    backup::export_backup,
+    ics::{export_ics, IcsComponent},
+    Error, PersonId,
+};
+use sea_orm::ConnectionTrait;
+use time::OffsetDateTime;
+
+use crate::{dates::today, error::AppError};
+
+#[derive(Debug, Subcommand)]
+pub enum ExportCommand {
+    /// Export upcoming reminders and run-out dates as an iCalendar file
+    Ics(IcsArgs),
+    /// Export every prescription, fill request, event, and reminder as a JSON backup
+    Json(JsonArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct JsonArgs {
+    /// File to write, defaults to standard output
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct IcsArgs {
+    /// Only include prescriptions of this person
+    #[arg(long)]
+    person: Option<i32>,
+    /// Export to-do items instead of all-day events
+    #[arg(long)]
+    todo: bool,
+    /// File to write, defaults to standard output
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+impl IcsArgs {
+    pub fn component(&self) -> IcsComponent {
+        if self.todo {
+            IcsComponent::Todo
+        } else {
+            IcsComponent::Event
+        }
+    }
+}
+
+/// Render the iCalendar feed for the given options as of now,
+/// leaving out anything dated before today.
+pub async fn render_feed(
+    db: &impl ConnectionTrait,
+    person: Option<i32>,
+    component: IcsComponent,
+) -> Result<String, Error> {
+    export_ics(
+        db,
+        person.map(PersonId::from),
+        today(),
+        component,
+        OffsetDateTime::now_utc(),
+    )
+    .await
+}
+
+pub async fn run(db: &impl ConnectionTrait, command: ExportCommand) -> Result<(), AppError> {
+    match command {
+        ExportCommand::Ics(args) => {
+            let ics = render_feed(db, args.person, args.component()).await?;
+            match &args.output {
+                Some(path) => std::fs::write(path, ics).map_err(|e| AppError::io(path, e))?,
+                None => print!("{}", ics),
+            }
+        }
+        ExportCommand::Json(args) => {
+            let json = export_backup(db).await?.to_json();
+            match &args.output {
+                Some(path) => std::fs::write(path, json).map_err(|e| AppError::io(path, e))?,
+                None => println!("{}", json),
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,148 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+use clap::Subcommand;
+use rxtrack_model::{
+    dosing::set_pickup_quantity,
+    fill_request::{
+        cancel_fill_request, get_fill_state, record_fill, record_fill_request, record_pickup,
+        FillState,
+    },
+    pharmacy::set_fill_request_pharmacy,
+    Error, FillRequestId, PharmacyId, RxId,
+};
+use sea_orm::{ConnectionTrait, TransactionTrait};
+use time::Date;
+
+use crate::{
+    dates::{parse_date, today},
+    rx::require_rx,
+};
+
+#[derive(Debug, Subcommand)]
+pub enum FillCommand {
+    /// Record a refill request made to the pharmacy
+    Request {
+        /// Prescription ID
+        rx: i32,
+        /// Date of the request, defaults to today
+        #[arg(long, value_parser = parse_date)]
+        date: Option<Date>,
+        /// Pharmacy ID, defaults to the pharmacy of the prescription
+        #[arg(long)]
+        pharmacy: Option<i32>,
+    },
+    /// Record that the pharmacy has filled a prescription, ready to pick up
+    Fill {
+        /// Prescription ID
+        rx: i32,
+        /// Date of the fill, defaults to today
+        #[arg(long, value_parser = parse_date)]
+        date: Option<Date>,
+        /// Pharmacy ID, defaults to the pharmacy of the open request or of the prescription
+        #[arg(long)]
+        pharmacy: Option<i32>,
+    },
+    /// Record picking up a prescription
+    Pickup {
+        /// Prescription ID
+        rx: i32,
+        /// Date the pharmacy filled it, if not already recorded, defaults to the pick-up date
+        #[arg(long, value_parser = parse_date)]
+        filled: Option<Date>,
+        /// Date of the pick-up, defaults to today
+        #[arg(long, value_parser = parse_date)]
+        date: Option<Date>,
+        /// Pharmacy ID, defaults to the pharmacy of the open request or of the prescription
+        #[arg(long)]
+        pharmacy: Option<i32>,
+        /// Quantity dispensed, e.g. number of tablets
+        #[arg(long)]
+        quantity: Option<u32>,
+    },
+    /// Cancel the open refill request of a prescription
+    Cancel {
+        /// Prescription ID
+        rx: i32,
+        /// Why the request was cancelled
+        #[arg(long, default_value = "")]
+        reason: String,
+        /// Date of the cancellation, defaults to today
+        #[arg(long, value_parser = parse_date)]
+        date: Option<Date>,
+    },
+}
+
+pub fn describe_fill_state(state: &FillState) -> String {
+    match state {
+        FillState::Idle => "no open refill request".to_owned(),
+        FillState::Requested {
+            date_requested: Some(date),
+        } => format!("requested {}, waiting on the pharmacy", date),
+        FillState::Requested {
+            date_requested: None,
+        } => "waiting on the pharmacy".to_owned(),
+        FillState::ReadyForPickup { date_filled } => {
+            format!("filled {}, ready to pick up", date_filled)
+        }
+    }
+}
+
+pub async fn run(db: &impl TransactionTrait, command: FillCommand) -> Result<(), Error> {
+    let txn = db.begin().await?;
+    let (rx, action, id, pharmacy, quantity) = match command {
+        FillCommand::Request { rx, date, pharmacy } => {
+            let rx = RxId::from(rx);
+            require_rx(&txn, rx).await?;
+            let id = record_fill_request(&txn, rx, date.unwrap_or_else(today)).await?;
+            (rx, "Requested", id, pharmacy, None)
+        }
+        FillCommand::Fill { rx, date, pharmacy } => {
+            let rx = RxId::from(rx);
+            require_rx(&txn, rx).await?;
+            let id = record_fill(&txn, rx, date.unwrap_or_else(today)).await?;
+            (rx, "Filled", id, pharmacy, None)
+        }
+        FillCommand::Pickup {
+            rx,
+            filled,
+            date,
+            pharmacy,
+            quantity,
+        } => {
+            let rx = RxId::from(rx);
+            require_rx(&txn, rx).await?;
+            let date = date.unwrap_or_else(today);
+            let id = record_pickup(&txn, rx, filled, date).await?;
+            (rx, "Picked up", id, pharmacy, quantity)
+        }
+        FillCommand::Cancel { rx, reason, date } => {
+            let rx = RxId::from(rx);
+            require_rx(&txn, rx).await?;
+            let id = cancel_fill_request(&txn, rx, date.unwrap_or_else(today), &reason).await?;
+            (rx, "Cancelled", id, None, None)
+        }
+    };
+    if let Some(pharmacy) = pharmacy {
+        set_fill_request_pharmacy(&txn, id, Some(PharmacyId::from(pharmacy))).await?;
+    }
+    if quantity.is_some() {
+        set_pickup_quantity(&txn, id, quantity).await?;
+    }
+    print_new_state(&txn, rx, action, id).await?;
+    txn.commit().await?;
+    Ok(())
+}
+
+async fn print_new_state(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    action: &str,
+    id: FillRequestId,
+) -> Result<(), Error> {
+    let known = require_rx(db, rx).await?;
+    let state = get_fill_state(db, rx).await?;
+    println!("{}: {}", action, id);
+    println!("{}: {}", known.name, describe_fill_state(&state));
+    Ok(())
+}
@@ -0,0 +1,127 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+use clap::{Args, ValueEnum};
+use rxtrack_model::{
+    events::list_events,
+    fill_request::{FillRequest, FillRequestQuery},
+    pharmacy::list_pharmacies,
+    rx::{list_all_rx, GetRxId},
+    CloseReason, Error, PersonId, PharmacyId, RxId,
+};
+use sea_orm::ConnectionTrait;
+
+use crate::rx::require_rx;
+
+/// Which fill requests to list, by how they turned out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Status {
+    Open,
+    Completed,
+    Superseded,
+    Cancelled,
+}
+
+#[derive(Debug, Args)]
+pub struct HistoryArgs {
+    /// Prescription ID, defaults to all prescriptions
+    rx: Option<i32>,
+    /// Only include prescriptions of this person
+    #[arg(long, conflicts_with = "rx")]
+    person: Option<i32>,
+    /// Only include requests with this status
+    #[arg(long, value_enum)]
+    status: Option<Status>,
+}
+
+#[derive(Debug, Args)]
+pub struct EventsArgs {
+    /// Prescription ID
+    rx: i32,
+}
+
+fn describe_status(request: &dyn FillRequest) -> String {
+    match request.close_reason() {
+        None => "open".to_owned(),
+        Some(CloseReason::Completed) => "completed".to_owned(),
+        Some(CloseReason::Superseded) => "superseded".to_owned(),
+        Some(CloseReason::Cancelled) => {
+            let mut status = "cancelled".to_owned();
+            if let Some(date) = request.date_cancelled() {
+                status.push_str(&format!(" {}", date));
+            }
+            match request.cancel_reason() {
+                Some(reason) if !reason.is_empty() => format!("{}: {}", status, reason),
+                _ => status,
+            }
+        }
+    }
+}
+
+/// Look up pharmacy names by ID, for listing which pharmacy handled what.
+async fn pharmacy_names(
+    db: &impl ConnectionTrait,
+) -> Result<impl Fn(Option<PharmacyId>) -> String, Error> {
+    let pharmacies = list_pharmacies(db).await?;
+    Ok(move |id: Option<PharmacyId>| {
+        id.and_then(|id| pharmacies.iter().find(|pharmacy| pharmacy.id == id))
+            .map_or_else(|| "-".to_owned(), |pharmacy| pharmacy.pharmacy.name.clone())
+    })
+}
+
+fn describe_date(date: &Option<time::Date>) -> String {
+    date.map_or_else(|| "-".to_owned(), |date| date.to_string())
+}
+
+pub async fn run(db: &impl ConnectionTrait, args: HistoryArgs) -> Result<(), Error> {
+    let rxs = match args.rx {
+        Some(rx) => vec![require_rx(db, RxId::from(rx)).await?],
+        None => list_all_rx(db, args.person.map(PersonId::from)).await?,
+    };
+    let mut query = FillRequestQuery::new().rx(rxs.iter().map(|rx| rx.id));
+    query = match args.status {
+        None => query,
+        Some(Status::Open) => query.open(),
+        Some(Status::Completed) => query.close_reason(CloseReason::Completed),
+        Some(Status::Superseded) => query.close_reason(CloseReason::Superseded),
+        Some(Status::Cancelled) => query.close_reason(CloseReason::Cancelled),
+    };
+    let pharmacy_name = pharmacy_names(db).await?;
+    println!("Request\tRx\tRequested\tFilled\tPicked up\tPharmacy\tStatus");
+    for request in query.list(db).await? {
+        let name = rxs
+            .iter()
+            .find(|rx| rx.id == request.rx_id())
+            .map_or("", |rx| rx.name.as_str());
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            i32::from(request.fill_request_id()),
+            name,
+            describe_date(request.date_requested()),
+            describe_date(request.date_filled()),
+            describe_date(request.date_picked_up()),
+            pharmacy_name(request.pharmacy_id()),
+            describe_status(&request)
+        );
+    }
+    Ok(())
+}
+
+pub async fn run_events(db: &impl ConnectionTrait, args: EventsArgs) -> Result<(), Error> {
+    let rx = require_rx(db, RxId::from(args.rx)).await?;
+    let pharmacy_name = pharmacy_names(db).await?;
+    println!("Event\tDate\tType\tPharmacy\tQuantity");
+    for event in list_events(db, rx.id, ..).await? {
+        println!(
+            "{}\t{}\t{:?}\t{}\t{}",
+            i32::from(event.id),
+            event.date,
+            event.event_type,
+            pharmacy_name(event.pharmacy),
+            event
+                .quantity
+                .map_or_else(|| "-".to_owned(), |quantity| quantity.to_string())
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,244 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Subcommand};
+use rxtrack_model::{
+    backup::{restore_backup, Backup},
+    import::{import_dispenses, Dispense, ImportAction, ImportOutcome},
+    rx::RxAddOutcome,
+    PersonId,
+};
+use sea_orm::{DatabaseConnection, TransactionTrait};
+use serde::Deserialize;
+use time::{format_description::FormatItem, Date};
+
+use crate::error::AppError;
+
+#[derive(Debug, Subcommand)]
+pub enum ImportCommand {
+    /// Import dispensing history from a CSV file, e.g. from a pharmacy portal
+    Csv(CsvArgs),
+    /// Restore a JSON backup into an empty database
+    Json(JsonArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct JsonArgs {
+    /// JSON backup to restore
+    file: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct CsvArgs {
+    /// CSV file to import
+    file: PathBuf,
+    /// TOML file naming the columns to use and the date format
+    #[arg(long)]
+    mapping: PathBuf,
+    /// Add the prescriptions for this person
+    #[arg(long)]
+    person: Option<i32>,
+    /// Show what would be imported without changing anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+fn default_date_format() -> String {
+    "[year]-[month]-[day]".to_owned()
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+/// Which CSV columns hold each piece of a dispense, by header name.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Mapping {
+    rx_name: String,
+    rx_number: Option<String>,
+    date_filled: Option<String>,
+    date_picked_up: Option<String>,
+    /// Date format, in the format description syntax of the `time` crate
+    #[serde(default = "default_date_format")]
+    date_format: String,
+    #[serde(default = "default_delimiter")]
+    delimiter: char,
+}
+
+fn read_mapping(path: &Path) -> Result<Mapping, AppError> {
+    let contents = std::fs::read_to_string(path).map_err(|e| AppError::io(path, e))?;
+    let mapping: Mapping = toml::from_str(&contents).map_err(|e| AppError::Mapping {
+        path: path.to_owned(),
+        message: e.to_string(),
+    })?;
+    if mapping.date_filled.is_none() && mapping.date_picked_up.is_none() {
+        return Err(AppError::Mapping {
+            path: path.to_owned(),
+            message: "at least one of date_filled and date_picked_up is required".to_owned(),
+        });
+    }
+    Ok(mapping)
+}
+
+/// Column indices of each mapped column.
+struct Columns {
+    rx_name: usize,
+    rx_number: Option<usize>,
+    date_filled: Option<usize>,
+    date_picked_up: Option<usize>,
+}
+
+fn read_dispenses(path: &Path, mapping_path: &Path) -> Result<Vec<Dispense>, AppError> {
+    let mapping = read_mapping(mapping_path)?;
+    let mapping_error = |message: String| AppError::Mapping {
+        path: mapping_path.to_owned(),
+        message,
+    };
+    let date_format: Vec<FormatItem> =
+        time::format_description::parse_borrowed::<1>(&mapping.date_format)
+            .map_err(|e| mapping_error(format!("invalid date_format: {}", e)))?;
+    let delimiter = u8::try_from(mapping.delimiter)
+        .map_err(|_| mapping_error("delimiter must be a single-byte character".to_owned()))?;
+
+    let csv_error = |line: u64, message: String| AppError::Csv {
+        path: path.to_owned(),
+        line,
+        message,
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .map_err(|e| csv_error(0, e.to_string()))?;
+    let headers = reader
+        .headers()
+        .map_err(|e| csv_error(1, e.to_string()))?
+        .clone();
+    let find_column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header == name)
+            .ok_or_else(|| {
+                mapping_error(format!(
+                    "no column named \"{}\" in {}",
+                    name,
+                    path.display()
+                ))
+            })
+    };
+    let find_optional_column = |name: &Option<String>| name.as_deref().map(find_column).transpose();
+    let columns = Columns {
+        rx_name: find_column(&mapping.rx_name)?,
+        rx_number: find_optional_column(&mapping.rx_number)?,
+        date_filled: find_optional_column(&mapping.date_filled)?,
+        date_picked_up: find_optional_column(&mapping.date_picked_up)?,
+    };
+
+    let mut dispenses = vec![];
+    for record in reader.records() {
+        let record = record.map_err(|e| {
+            csv_error(
+                e.position().map(|p| p.line()).unwrap_or_default(),
+                e.to_string(),
+            )
+        })?;
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let cell = |column: Option<usize>| {
+            column
+                .and_then(|column| record.get(column))
+                .filter(|value| !value.is_empty())
+        };
+        let date = |column: Option<usize>| {
+            cell(column)
+                .map(|value| Date::parse(value, &date_format))
+                .transpose()
+                .map_err(|e| csv_error(line, format!("could not parse date: {}", e)))
+        };
+        let rx_name = cell(Some(columns.rx_name))
+            .ok_or_else(|| csv_error(line, "missing prescription name".to_owned()))?;
+        let dispense = Dispense {
+            rx_name: rx_name.to_owned(),
+            rx_number: cell(columns.rx_number).map(str::to_owned),
+            date_filled: date(columns.date_filled)?,
+            date_picked_up: date(columns.date_picked_up)?,
+        };
+        if dispense.date().is_none() {
+            return Err(csv_error(
+                line,
+                "missing both fill and pick-up dates".to_owned(),
+            ));
+        }
+        dispenses.push(dispense);
+    }
+    Ok(dispenses)
+}
+
+fn print_outcome(outcome: &ImportOutcome) {
+    let dispense = &outcome.dispense;
+    if let RxAddOutcome::Created(id) = outcome.rx {
+        println!("+ new prescription {}\t{}", i32::from(id), dispense.rx_name);
+    }
+    let date = dispense.date().expect("dispenses have a date");
+    match outcome.action {
+        ImportAction::AlreadyImported => {
+            println!("= {}\t{}\talready imported", date, dispense.rx_name)
+        }
+        ImportAction::Filled => println!("+ {}\t{}\tfilled", date, dispense.rx_name),
+        ImportAction::PickedUp => match dispense.date_filled {
+            Some(date_filled) if date_filled != date => println!(
+                "+ {}\t{}\tpicked up (filled {})",
+                date, dispense.rx_name, date_filled
+            ),
+            _ => println!("+ {}\t{}\tpicked up", date, dispense.rx_name),
+        },
+    }
+}
+
+pub async fn run(db: &DatabaseConnection, command: ImportCommand) -> Result<(), AppError> {
+    match command {
+        ImportCommand::Csv(args) => {
+            let dispenses = read_dispenses(&args.file, &args.mapping)?;
+            let txn = db.begin().await?;
+            let outcomes =
+                import_dispenses(&txn, args.person.map(PersonId::from), dispenses).await?;
+            for outcome in &outcomes {
+                print_outcome(outcome);
+            }
+            let skipped = outcomes
+                .iter()
+                .filter(|outcome| outcome.action == ImportAction::AlreadyImported)
+                .count();
+            let imported = outcomes.len() - skipped;
+            if args.dry_run {
+                txn.rollback().await?;
+                println!(
+                    "Dry run: would import {}, skipping {} already imported",
+                    imported, skipped
+                );
+            } else {
+                txn.commit().await?;
+                println!(
+                    "Imported {}, skipped {} already imported",
+                    imported, skipped
+                );
+            }
+        }
+        ImportCommand::Json(args) => {
+            let json =
+                std::fs::read_to_string(&args.file).map_err(|e| AppError::io(&args.file, e))?;
+            let backup = Backup::from_json(&json)?;
+            restore_backup(db, &backup).await?;
+            println!(
+                "Restored {} prescriptions, {} fill requests, {} events, and {} reminders",
+                backup.rx.len(),
+                backup.fill_requests.len(),
+                backup.events.len(),
+                backup.reminder_policies.len()
+            );
+        }
+    }
+    Ok(())
+}
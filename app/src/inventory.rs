@@ -0,0 +1,92 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+use clap::Subcommand;
+use rxtrack_model::{
+    inventory::{list_pill_counts, on_hand, record_pill_count, OnHand, PillCount},
+    Error, RxId,
+};
+use sea_orm::ConnectionTrait;
+use time::Date;
+
+use crate::{
+    dates::{parse_date, today},
+    rx::require_rx,
+};
+
+#[derive(Debug, Subcommand)]
+pub enum InventoryCommand {
+    /// Record counting the units of a prescription on hand, resetting the estimate
+    Count {
+        /// Prescription ID
+        rx: i32,
+        /// Units counted, e.g. tablets
+        units: u32,
+        /// Date of the count, defaults to today
+        #[arg(long, value_parser = parse_date)]
+        date: Option<Date>,
+    },
+    /// Show the estimated supply on hand of a prescription, and its past counts
+    Show {
+        /// Prescription ID
+        rx: i32,
+        /// Estimate as of this date, defaults to today
+        #[arg(long, value_parser = parse_date)]
+        date: Option<Date>,
+    },
+}
+
+/// The units on hand and how long they last, e.g. "20 (10 days)".
+pub fn describe_on_hand(supply: &OnHand) -> String {
+    format!("{} ({} days)", supply.units, supply.days)
+}
+
+/// The discrepancy of a count from the estimate, e.g. "10 fewer than expected".
+fn describe_discrepancy(count: &PillCount) -> String {
+    match count.discrepancy() {
+        None => "no estimate".to_owned(),
+        Some(0) => "as expected".to_owned(),
+        Some(more) if more > 0 => format!("{} more than expected", more),
+        Some(fewer) => format!("{} fewer than expected", -fewer),
+    }
+}
+
+pub async fn run(db: &impl ConnectionTrait, command: InventoryCommand) -> Result<(), Error> {
+    match command {
+        InventoryCommand::Count { rx, units, date } => {
+            let rx = require_rx(db, RxId::from(rx)).await?;
+            let count = record_pill_count(db, rx.id, date.unwrap_or_else(today), units).await?;
+            println!("Counted: {}", count.id);
+            println!(
+                "{}: {} on hand, {}",
+                rx.name,
+                count.counted,
+                describe_discrepancy(&count)
+            );
+        }
+        InventoryCommand::Show { rx, date } => {
+            let rx = require_rx(db, RxId::from(rx)).await?;
+            match on_hand(db, rx.id, date.unwrap_or_else(today)).await? {
+                Some(supply) => println!("On hand: {}", describe_on_hand(&supply)),
+                None => println!("On hand: unknown"),
+            }
+            if let Some(days) = rx.critical_days {
+                println!("Critical below: {} days", days);
+            }
+            let counts = list_pill_counts(db, rx.id).await?;
+            if !counts.is_empty() {
+                println!("Count\tDate\tCounted\tDiscrepancy");
+            }
+            for count in counts {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    i32::from(count.id),
+                    count.date,
+                    count.counted,
+                    describe_discrepancy(&count)
+                );
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,45 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+use clap::Subcommand;
+use rxtrack_model::{
+    person::{add_person, get_person, list_people, KnownPerson},
+    Error, PersonId,
+};
+use sea_orm::ConnectionTrait;
+
+#[derive(Debug, Subcommand)]
+pub enum PersonCommand {
+    /// Add a person whose prescriptions are tracked, e.g. for `rx add --person`
+    Add {
+        /// Name of the person
+        name: String,
+    },
+    /// List people
+    List,
+}
+
+/// Look up a person, treating a missing one as an error.
+pub async fn require_person(
+    db: &impl ConnectionTrait,
+    person: PersonId,
+) -> Result<KnownPerson, Error> {
+    get_person(db, person)
+        .await?
+        .ok_or(Error::NoSuchPerson(person))
+}
+
+pub async fn run(db: &impl ConnectionTrait, command: PersonCommand) -> Result<(), Error> {
+    match command {
+        PersonCommand::Add { name } => {
+            let id = add_person(db, &name).await?;
+            println!("Added {}", id);
+        }
+        PersonCommand::List => {
+            for person in list_people(db).await? {
+                println!("{}\t{}", i32::from(person.id), person.name);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,138 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+use clap::Subcommand;
+use rxtrack_model::{
+    pharmacy::{
+        add_pharmacy, get_pharmacy, list_pharmacies, update_pharmacy, KnownPharmacy, Pharmacy,
+    },
+    Error, PharmacyId,
+};
+use sea_orm::ConnectionTrait;
+
+#[derive(Debug, Subcommand)]
+pub enum PharmacyCommand {
+    /// Add a new pharmacy
+    Add {
+        /// Name of the pharmacy
+        name: String,
+        /// Phone number
+        #[arg(long)]
+        phone: Option<String>,
+        /// Street address
+        #[arg(long)]
+        address: Option<String>,
+        /// Opening hours, e.g. "Mon-Fri 9-6, Sat 9-1"
+        #[arg(long)]
+        hours: Option<String>,
+    },
+    /// List pharmacies
+    List,
+    /// Show details of a pharmacy
+    Show {
+        /// Pharmacy ID
+        pharmacy: i32,
+    },
+    /// Change the details of a pharmacy, leaving out ones not given
+    Update {
+        /// Pharmacy ID
+        pharmacy: i32,
+        /// New name of the pharmacy
+        #[arg(long)]
+        name: Option<String>,
+        /// New phone number, or an empty string to clear it
+        #[arg(long)]
+        phone: Option<String>,
+        /// New street address, or an empty string to clear it
+        #[arg(long)]
+        address: Option<String>,
+        /// New opening hours, or an empty string to clear them
+        #[arg(long)]
+        hours: Option<String>,
+    },
+}
+
+/// Look up a pharmacy, treating a missing one as an error.
+pub async fn require_pharmacy(
+    db: &impl ConnectionTrait,
+    pharmacy: PharmacyId,
+) -> Result<KnownPharmacy, Error> {
+    get_pharmacy(db, pharmacy)
+        .await?
+        .ok_or(Error::NoSuchPharmacy(pharmacy))
+}
+
+/// The name of a pharmacy with its phone number and hours, if known.
+pub fn describe_pharmacy(pharmacy: &Pharmacy) -> String {
+    let details: Vec<&str> = [&pharmacy.phone, &pharmacy.hours]
+        .into_iter()
+        .filter_map(|detail| detail.as_deref())
+        .collect();
+    if details.is_empty() {
+        pharmacy.name.clone()
+    } else {
+        format!("{} ({})", pharmacy.name, details.join(", "))
+    }
+}
+
+pub async fn run(db: &impl ConnectionTrait, command: PharmacyCommand) -> Result<(), Error> {
+    match command {
+        PharmacyCommand::Add {
+            name,
+            phone,
+            address,
+            hours,
+        } => {
+            let pharmacy = Pharmacy {
+                name,
+                phone,
+                address,
+                hours,
+            };
+            println!("Added {}", add_pharmacy(db, &pharmacy).await?);
+        }
+        PharmacyCommand::List => {
+            for known in list_pharmacies(db).await? {
+                println!(
+                    "{}\t{}",
+                    i32::from(known.id),
+                    describe_pharmacy(&known.pharmacy)
+                );
+            }
+        }
+        PharmacyCommand::Show { pharmacy } => {
+            let known = require_pharmacy(db, PharmacyId::from(pharmacy)).await?;
+            let pharmacy = &known.pharmacy;
+            println!("ID:      {}", i32::from(known.id));
+            println!("Name:    {}", pharmacy.name);
+            for (label, value) in [
+                ("Phone:  ", &pharmacy.phone),
+                ("Address:", &pharmacy.address),
+                ("Hours:  ", &pharmacy.hours),
+            ] {
+                if let Some(value) = value {
+                    println!("{} {}", label, value);
+                }
+            }
+        }
+        PharmacyCommand::Update {
+            pharmacy,
+            name,
+            phone,
+            address,
+            hours,
+        } => {
+            let id = PharmacyId::from(pharmacy);
+            let existing = require_pharmacy(db, id).await?.pharmacy;
+            let updated = Pharmacy {
+                name: name.unwrap_or(existing.name),
+                phone: phone.or(existing.phone),
+                address: address.or(existing.address),
+                hours: hours.or(existing.hours),
+            };
+            update_pharmacy(db, id, &updated).await?;
+            println!("Updated {}", id);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,139 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+use clap::Subcommand;
+use rxtrack_model::{
+    prescriber::{
+        add_prescriber, get_prescriber, list_prescribers, update_prescriber, KnownPrescriber,
+        Prescriber,
+    },
+    Error, PrescriberId,
+};
+use sea_orm::ConnectionTrait;
+
+#[derive(Debug, Subcommand)]
+pub enum PrescriberCommand {
+    /// Add a new prescriber
+    Add {
+        /// Name of the prescriber
+        name: String,
+        /// Name of the practice or clinic
+        #[arg(long)]
+        practice: Option<String>,
+        /// Phone number
+        #[arg(long)]
+        phone: Option<String>,
+        /// Fax number
+        #[arg(long)]
+        fax: Option<String>,
+    },
+    /// List prescribers
+    List,
+    /// Show details of a prescriber
+    Show {
+        /// Prescriber ID
+        prescriber: i32,
+    },
+    /// Change the details of a prescriber, leaving out ones not given
+    Update {
+        /// Prescriber ID
+        prescriber: i32,
+        /// New name of the prescriber
+        #[arg(long)]
+        name: Option<String>,
+        /// New practice or clinic, or an empty string to clear it
+        #[arg(long)]
+        practice: Option<String>,
+        /// New phone number, or an empty string to clear it
+        #[arg(long)]
+        phone: Option<String>,
+        /// New fax number, or an empty string to clear it
+        #[arg(long)]
+        fax: Option<String>,
+    },
+}
+
+/// Look up a prescriber, treating a missing one as an error.
+pub async fn require_prescriber(
+    db: &impl ConnectionTrait,
+    prescriber: PrescriberId,
+) -> Result<KnownPrescriber, Error> {
+    get_prescriber(db, prescriber)
+        .await?
+        .ok_or(Error::NoSuchPrescriber(prescriber))
+}
+
+/// The name of a prescriber with their practice and phone number, if known.
+pub fn describe_prescriber(prescriber: &Prescriber) -> String {
+    let details: Vec<&str> = [&prescriber.practice, &prescriber.phone]
+        .into_iter()
+        .filter_map(|detail| detail.as_deref())
+        .collect();
+    if details.is_empty() {
+        prescriber.name.clone()
+    } else {
+        format!("{} ({})", prescriber.name, details.join(", "))
+    }
+}
+
+pub async fn run(db: &impl ConnectionTrait, command: PrescriberCommand) -> Result<(), Error> {
+    match command {
+        PrescriberCommand::Add {
+            name,
+            practice,
+            phone,
+            fax,
+        } => {
+            let prescriber = Prescriber {
+                name,
+                practice,
+                phone,
+                fax,
+            };
+            println!("Added {}", add_prescriber(db, &prescriber).await?);
+        }
+        PrescriberCommand::List => {
+            for known in list_prescribers(db).await? {
+                println!(
+                    "{}\t{}",
+                    i32::from(known.id),
+                    describe_prescriber(&known.prescriber)
+                );
+            }
+        }
+        PrescriberCommand::Show { prescriber } => {
+            let known = require_prescriber(db, PrescriberId::from(prescriber)).await?;
+            let prescriber = &known.prescriber;
+            println!("ID:       {}", i32::from(known.id));
+            println!("Name:     {}", prescriber.name);
+            for (label, value) in [
+                ("Practice:", &prescriber.practice),
+                ("Phone:   ", &prescriber.phone),
+                ("Fax:     ", &prescriber.fax),
+            ] {
+                if let Some(value) = value {
+                    println!("{} {}", label, value);
+                }
+            }
+        }
+        PrescriberCommand::Update {
+            prescriber,
+            name,
+            practice,
+            phone,
+            fax,
+        } => {
+            let id = PrescriberId::from(prescriber);
+            let existing = require_prescriber(db, id).await?.prescriber;
+            let updated = Prescriber {
+                name: name.unwrap_or(existing.name),
+                practice: practice.or(existing.practice),
+                phone: phone.or(existing.phone),
+                fax: fax.or(existing.fax),
+            };
+            update_prescriber(db, id, &updated).await?;
+            println!("Updated {}", id);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,141 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+use clap::{Subcommand, ValueEnum};
+use rxtrack_model::{
+    reminder_policy::{
+        add_reminder_policy, list_reminder_policies, remove_reminder_policy, KnownReminderPolicy,
+        ReminderPolicy,
+    },
+    reminders::list_reminders,
+    Error, EventType, ReminderPolicyId, RxId,
+};
+use sea_orm::ConnectionTrait;
+
+use crate::rx::require_rx;
+
+/// The event a reminder is computed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Start {
+    Requested,
+    Filled,
+    PickedUp,
+    Cancelled,
+}
+
+impl From<Start> for EventType {
+    fn from(value: Start) -> Self {
+        match value {
+            Start::Requested => EventType::RequestFill,
+            Start::Filled => EventType::Fill,
+            Start::PickedUp => EventType::PickUp,
+            Start::Cancelled => EventType::RefillCancel,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ReminderCommand {
+    /// Add a reminder to a prescription
+    Add {
+        /// Prescription ID
+        rx: i32,
+        /// What to be reminded of, e.g. "Request refill"
+        description: String,
+        /// The latest event of this kind starts the count
+        #[arg(long, value_enum, default_value_t = Start::PickedUp)]
+        from: Start,
+        /// Days to add to the start, or subtract if negative
+        #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+        offset: i32,
+        /// Do not add the days supply of the prescription to the start
+        #[arg(long)]
+        no_days_supply: bool,
+        /// Allow the reminder to fall on a Saturday, rather than an earlier day
+        #[arg(long)]
+        allow_saturday: bool,
+        /// Allow the reminder to fall on a Sunday, rather than an earlier day
+        #[arg(long)]
+        allow_sunday: bool,
+    },
+    /// List the reminders of a prescription, with their next dates
+    List {
+        /// Prescription ID
+        rx: i32,
+    },
+    /// Remove a reminder
+    Remove {
+        /// Reminder ID, as shown by `reminder list`
+        reminder: i32,
+    },
+}
+
+fn describe_policy(policy: &KnownReminderPolicy) -> String {
+    let start = match policy.policy.starting_event {
+        Some(EventType::RequestFill) => "request",
+        Some(EventType::Fill) => "fill",
+        Some(EventType::PickUp) => "pick-up",
+        Some(EventType::RefillCancel) => "cancel",
+        None => "never",
+    };
+    let supply = if policy.policy.include_rx_duration {
+        " + days supply"
+    } else {
+        ""
+    };
+    format!(
+        "{}\t{} (from {}{} {:+} days)",
+        i32::from(policy.id),
+        policy.policy.description,
+        start,
+        supply,
+        policy.policy.offset_days
+    )
+}
+
+pub async fn run(db: &impl ConnectionTrait, command: ReminderCommand) -> Result<(), Error> {
+    match command {
+        ReminderCommand::Add {
+            rx,
+            description,
+            from,
+            offset,
+            no_days_supply,
+            allow_saturday,
+            allow_sunday,
+        } => {
+            let rx = require_rx(db, RxId::from(rx)).await?;
+            let policy = ReminderPolicy {
+                starting_event: Some(from.into()),
+                include_rx_duration: !no_days_supply,
+                offset_days: offset,
+                allow_saturday,
+                allow_sunday,
+                description,
+            };
+            let id = add_reminder_policy(db, rx.id, &policy).await?;
+            println!("Added {}", id);
+        }
+        ReminderCommand::List { rx } => {
+            let rx = require_rx(db, RxId::from(rx)).await?;
+            let reminders = list_reminders(db, rx.id).await?;
+            for policy in list_reminder_policies(db, rx.id).await? {
+                let next = reminders
+                    .iter()
+                    .find(|reminder| reminder.policy_id == policy.id);
+                match next {
+                    Some(reminder) => {
+                        println!("{}\tnext {}", describe_policy(&policy), reminder.date)
+                    }
+                    None => println!("{}", describe_policy(&policy)),
+                }
+            }
+        }
+        ReminderCommand::Remove { reminder } => {
+            let id = ReminderPolicyId::from(reminder);
+            remove_reminder_policy(db, id).await?;
+            println!("Removed {}", id);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,182 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+use clap::Subcommand;
+use rxtrack_model::{
+    authorization::Authorization,
+    renewal::{
+        approve_renewal, deny_renewal, get_renewal, list_renewals, record_renewal_received,
+        request_renewal, set_renewal_follow_up, Renewal,
+    },
+    Error, RenewalId, RenewalStatus, RxId,
+};
+use sea_orm::ConnectionTrait;
+use time::Date;
+
+use crate::{
+    dates::{parse_date, today},
+    rx::require_rx,
+};
+
+#[derive(Debug, Subcommand)]
+pub enum RenewalCommand {
+    /// Record asking the prescriber of a prescription to renew its script
+    Request {
+        /// Prescription ID
+        rx: i32,
+        /// Date of the request, defaults to today
+        #[arg(long, value_parser = parse_date)]
+        date: Option<Date>,
+        /// Date to follow up if nothing has happened, defaults to a few days after the request
+        #[arg(long, value_parser = parse_date)]
+        follow_up: Option<Date>,
+    },
+    /// Record that the prescriber approved a renewal, making the new script current
+    Approve {
+        /// Renewal ID
+        renewal: i32,
+        /// Number of refills on the new script, if known
+        #[arg(long)]
+        refills: Option<u32>,
+        /// Last date the new script can be filled, if known
+        #[arg(long, value_parser = parse_date)]
+        expires: Option<Date>,
+        /// Date of the approval, defaults to today
+        #[arg(long, value_parser = parse_date)]
+        date: Option<Date>,
+    },
+    /// Record that the prescriber denied a renewal
+    Deny {
+        /// Renewal ID
+        renewal: i32,
+        /// Why the renewal was denied
+        #[arg(long, default_value = "")]
+        reason: String,
+        /// Date of the denial, defaults to today
+        #[arg(long, value_parser = parse_date)]
+        date: Option<Date>,
+    },
+    /// Record that the new script of an approved renewal reached the pharmacy
+    Received {
+        /// Renewal ID
+        renewal: i32,
+        /// Date it was received, defaults to today
+        #[arg(long, value_parser = parse_date)]
+        date: Option<Date>,
+    },
+    /// Move the date to follow up on an open renewal
+    FollowUp {
+        /// Renewal ID
+        renewal: i32,
+        /// Date to follow up
+        #[arg(value_parser = parse_date)]
+        date: Date,
+    },
+    /// List the renewals of a prescription
+    List {
+        /// Prescription ID
+        rx: i32,
+    },
+}
+
+/// Where a renewal stands, e.g. "requested 2023-04-29, waiting on the prescriber".
+pub fn describe_renewal(renewal: &Renewal) -> String {
+    let follow_up = renewal
+        .follow_up
+        .map(|date| format!(" (follow up {})", date))
+        .unwrap_or_default();
+    match renewal.status {
+        RenewalStatus::Requested => format!(
+            "requested {}, waiting on the prescriber{}",
+            renewal.date_requested, follow_up
+        ),
+        RenewalStatus::Approved => format!(
+            "approved {}, waiting on the pharmacy{}",
+            renewal.date_approved.unwrap_or(renewal.date_requested),
+            follow_up
+        ),
+        RenewalStatus::Denied => {
+            let date = renewal.date_denied.unwrap_or(renewal.date_requested);
+            match renewal
+                .deny_reason
+                .as_deref()
+                .filter(|reason| !reason.is_empty())
+            {
+                Some(reason) => format!("denied {}: {}", date, reason),
+                None => format!("denied {}", date),
+            }
+        }
+        RenewalStatus::Received => format!(
+            "received at the pharmacy {}",
+            renewal.date_received.unwrap_or(renewal.date_requested)
+        ),
+    }
+}
+
+async fn print_renewal(
+    db: &impl ConnectionTrait,
+    action: &str,
+    id: RenewalId,
+) -> Result<(), Error> {
+    let renewal = get_renewal(db, id).await?.ok_or(Error::NoSuchRenewal(id))?;
+    let known = require_rx(db, renewal.rx_id).await?;
+    println!("{}: {}", action, id);
+    println!("{}: {}", known.name, describe_renewal(&renewal));
+    Ok(())
+}
+
+pub async fn run(db: &impl ConnectionTrait, command: RenewalCommand) -> Result<(), Error> {
+    match command {
+        RenewalCommand::Request {
+            rx,
+            date,
+            follow_up,
+        } => {
+            let id =
+                request_renewal(db, RxId::from(rx), date.unwrap_or_else(today), follow_up).await?;
+            print_renewal(db, "Requested", id).await?;
+        }
+        RenewalCommand::Approve {
+            renewal,
+            refills,
+            expires,
+            date,
+        } => {
+            let id = RenewalId::from(renewal);
+            let authorization = Authorization {
+                refills_remaining: refills,
+                expires,
+                original_fill_pending: true,
+            };
+            approve_renewal(db, id, date.unwrap_or_else(today), &authorization).await?;
+            print_renewal(db, "Approved", id).await?;
+        }
+        RenewalCommand::Deny {
+            renewal,
+            reason,
+            date,
+        } => {
+            let id = RenewalId::from(renewal);
+            deny_renewal(db, id, date.unwrap_or_else(today), &reason).await?;
+            print_renewal(db, "Denied", id).await?;
+        }
+        RenewalCommand::Received { renewal, date } => {
+            let id = RenewalId::from(renewal);
+            record_renewal_received(db, id, date.unwrap_or_else(today)).await?;
+            print_renewal(db, "Received", id).await?;
+        }
+        RenewalCommand::FollowUp { renewal, date } => {
+            let id = RenewalId::from(renewal);
+            set_renewal_follow_up(db, id, date).await?;
+            print_renewal(db, "Updated", id).await?;
+        }
+        RenewalCommand::List { rx } => {
+            let rx = RxId::from(rx);
+            require_rx(db, rx).await?;
+            for renewal in list_renewals(db, rx).await? {
+                println!("{}\t{}", i32::from(renewal.id), describe_renewal(&renewal));
+            }
+        }
+    }
+    Ok(())
+}
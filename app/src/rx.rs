@@ -0,0 +1,339 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+use clap::Subcommand;
+use rxtrack_model::{
+    authorization::{set_authorization, Authorization},
+    days_supply::{get_days_supply, set_days_supply},
+    dosing::{set_dose_rate, DoseRate},
+    inventory::{on_hand, set_critical_days},
+    pharmacy::set_default_pharmacy,
+    prescriber::{list_rx_by_prescriber, set_rx_prescriber},
+    reminders::run_out_date,
+    rx::{
+        add_person_rx, add_rx, get_rx, list_all_rx, list_rx, rename_rx, retire_rx, set_rx_hidden,
+        KnownRx, RxAddOutcome,
+    },
+    Error, PersonId, PharmacyId, PrescriberId, RxId,
+};
+use sea_orm::ConnectionTrait;
+use time::Date;
+
+use crate::{
+    dates::{parse_date, today},
+    inventory::describe_on_hand,
+    person::require_person,
+    pharmacy::{describe_pharmacy, require_pharmacy},
+    prescriber::{describe_prescriber, require_prescriber},
+};
+
+#[derive(Debug, Subcommand)]
+pub enum RxCommand {
+    /// Add a new prescription
+    Add {
+        /// Name of the prescription
+        name: String,
+        /// Add the prescription for this person
+        #[arg(long)]
+        person: Option<i32>,
+    },
+    /// List prescriptions
+    List {
+        /// Include hidden prescriptions
+        #[arg(long)]
+        all: bool,
+        /// Only list the prescriptions of this person
+        #[arg(long)]
+        person: Option<i32>,
+        /// Group by current prescriber, e.g. to see what to renew at an appointment
+        #[arg(long, conflicts_with = "all")]
+        by_prescriber: bool,
+    },
+    /// Show details of a prescription
+    Show {
+        /// Prescription ID
+        rx: i32,
+    },
+    /// Hide a prescription from the default list
+    Hide {
+        /// Prescription ID
+        rx: i32,
+    },
+    /// Show a previously-hidden prescription in the default list again
+    Unhide {
+        /// Prescription ID
+        rx: i32,
+    },
+    /// Set how many days a fill of a prescription lasts
+    SetDaysSupply {
+        /// Prescription ID
+        rx: i32,
+        /// Number of days a fill lasts
+        days: u32,
+        /// Apply to fills from this date on, defaults to today
+        #[arg(long, value_parser = parse_date)]
+        from: Option<Date>,
+    },
+    /// Change the name of a prescription
+    Rename {
+        /// Prescription ID
+        rx: i32,
+        /// New name of the prescription
+        name: String,
+    },
+    /// Permanently retire a prescription that is no longer taken
+    Retire {
+        /// Prescription ID
+        rx: i32,
+        /// Why the prescription was retired
+        #[arg(long, default_value = "")]
+        reason: String,
+        /// Date of retirement, defaults to today
+        #[arg(long, value_parser = parse_date)]
+        date: Option<Date>,
+    },
+    /// Set the pharmacy a prescription is usually filled at
+    SetPharmacy {
+        /// Prescription ID
+        rx: i32,
+        /// Pharmacy ID, leave out to clear the default pharmacy
+        pharmacy: Option<i32>,
+    },
+    /// Record the refills and expiry of the current script, e.g. after a renewal
+    Authorize {
+        /// Prescription ID
+        rx: i32,
+        /// Number of refills remaining, keeps the current number if left out
+        #[arg(long)]
+        refills: Option<u32>,
+        /// Last date the script can be filled, keeps the current date if left out
+        #[arg(long, value_parser = parse_date)]
+        expires: Option<Date>,
+        /// Date the refills remaining are as of, defaults to today.
+        /// Only pick-ups after it use up a refill
+        #[arg(long, value_parser = parse_date)]
+        date: Option<Date>,
+    },
+    /// Set the current prescriber of a prescription
+    SetPrescriber {
+        /// Prescription ID
+        rx: i32,
+        /// Prescriber ID, leave out to clear the prescriber
+        prescriber: Option<i32>,
+    },
+    /// Set how fast a prescription is used, to project when it runs out
+    SetDose {
+        /// Prescription ID
+        rx: i32,
+        /// Units used at a time, e.g. tablets, leave out to clear the dose
+        units: Option<u32>,
+        /// Number of days each dose lasts
+        #[arg(long, default_value_t = 1)]
+        every: u32,
+    },
+    /// Mark a prescription as critical, to alert when it runs low on hand
+    SetCritical {
+        /// Prescription ID
+        rx: i32,
+        /// Alert when fewer than this many days are on hand, leave out to unmark it
+        days: Option<u32>,
+    },
+}
+
+fn print_rx_line(rx: &KnownRx) {
+    let hidden = if rx.retired.is_some() {
+        " (retired)"
+    } else if rx.hidden {
+        " (hidden)"
+    } else {
+        ""
+    };
+    println!("{}\t{}{}", i32::from(rx.id), rx.name, hidden);
+}
+
+/// Look up a prescription, treating a missing one as an error.
+pub async fn require_rx(db: &impl ConnectionTrait, rx: RxId) -> Result<KnownRx, Error> {
+    get_rx(db, rx).await?.ok_or(Error::NoSuchRx(rx))
+}
+
+pub async fn run(db: &impl ConnectionTrait, command: RxCommand) -> Result<(), Error> {
+    match command {
+        RxCommand::Add { name, person } => {
+            let outcome = match person {
+                Some(person) => {
+                    let person = require_person(db, PersonId::from(person)).await?;
+                    add_person_rx(db, person.id, &name).await?
+                }
+                None => add_rx(db, &name).await?,
+            };
+            match outcome {
+                RxAddOutcome::Created(id) => println!("Added {}", id),
+                RxAddOutcome::AlreadyExists(id) => println!("Already exists: {}", id),
+            }
+        }
+        RxCommand::List {
+            person,
+            by_prescriber: true,
+            ..
+        } => {
+            let person = person.map(PersonId::from);
+            for (i, (prescriber, rxs)) in
+                list_rx_by_prescriber(db, person).await?.iter().enumerate()
+            {
+                if i > 0 {
+                    println!();
+                }
+                match prescriber {
+                    Some(prescriber) => {
+                        println!("{}:", describe_prescriber(&prescriber.prescriber))
+                    }
+                    None => println!("No prescriber:"),
+                }
+                for rx in rxs {
+                    print!("  ");
+                    print_rx_line(rx);
+                }
+            }
+        }
+        RxCommand::List { all, person, .. } => {
+            let person = person.map(PersonId::from);
+            let rxs = if all {
+                list_all_rx(db, person).await?
+            } else {
+                list_rx(db, person).await?
+            };
+            for rx in &rxs {
+                print_rx_line(rx);
+            }
+        }
+        RxCommand::Show { rx } => {
+            let rx = require_rx(db, RxId::from(rx)).await?;
+            println!("ID:     {}", i32::from(rx.id));
+            println!("Name:   {}", rx.name);
+            println!("Hidden: {}", if rx.hidden { "yes" } else { "no" });
+            if let Some(retired) = &rx.retired {
+                println!("Retired: {}", retired.date);
+                if !retired.reason.is_empty() {
+                    println!("Reason: {}", retired.reason);
+                }
+            }
+            if let Some(supply) = get_days_supply(db, rx.id, today()).await? {
+                println!("Days supply: {}", supply.days);
+            }
+            if let Some(pharmacy) = rx.pharmacy {
+                let pharmacy = require_pharmacy(db, pharmacy).await?;
+                println!("Pharmacy: {}", describe_pharmacy(&pharmacy.pharmacy));
+            }
+            if let Some(refills) = rx.authorization.refills_remaining {
+                println!("Refills: {}", refills);
+            }
+            if rx.authorization.original_fill_pending {
+                println!("Original fill: not picked up yet");
+            }
+            if let Some(expires) = rx.authorization.expires {
+                println!("Expires: {}", expires);
+            }
+            if let Some(prescriber) = rx.prescriber {
+                let prescriber = require_prescriber(db, prescriber).await?;
+                println!(
+                    "Prescriber: {}",
+                    describe_prescriber(&prescriber.prescriber)
+                );
+            }
+            if let Some(dose) = rx.dose {
+                println!("Dose: {}", dose);
+            }
+            if let Some(run_out) = run_out_date(db, rx.id).await? {
+                println!("Runs out: {}", run_out);
+            }
+            if let Some(supply) = on_hand(db, rx.id, today()).await? {
+                println!("On hand: {}", describe_on_hand(&supply));
+            }
+            if let Some(days) = rx.critical_days {
+                println!("Critical below: {} days", days);
+            }
+        }
+        RxCommand::Hide { rx } => {
+            let rx = RxId::from(rx);
+            set_rx_hidden(db, rx, true).await?;
+            println!("Hid {}", rx);
+        }
+        RxCommand::Unhide { rx } => {
+            let rx = RxId::from(rx);
+            set_rx_hidden(db, rx, false).await?;
+            println!("Unhid {}", rx);
+        }
+        RxCommand::SetDaysSupply { rx, days, from } => {
+            let rx = require_rx(db, RxId::from(rx)).await?;
+            let from = from.unwrap_or_else(today);
+            set_days_supply(db, rx.id, days, from).await?;
+            println!("Set the days supply of {} to {} from {}", rx.id, days, from);
+        }
+        RxCommand::Rename { rx, name } => {
+            let rx = RxId::from(rx);
+            rename_rx(db, rx, &name).await?;
+            println!("Renamed {}", rx);
+        }
+        RxCommand::Retire { rx, reason, date } => {
+            let rx = RxId::from(rx);
+            retire_rx(db, rx, date.unwrap_or_else(today), &reason).await?;
+            println!("Retired {}", rx);
+        }
+        RxCommand::SetPharmacy { rx, pharmacy } => {
+            let rx = RxId::from(rx);
+            let pharmacy = pharmacy.map(PharmacyId::from);
+            set_default_pharmacy(db, rx, pharmacy).await?;
+            match pharmacy {
+                Some(pharmacy) => println!("Set the pharmacy of {} to {}", rx, pharmacy),
+                None => println!("Cleared the pharmacy of {}", rx),
+            }
+        }
+        RxCommand::Authorize {
+            rx,
+            refills,
+            expires,
+            date,
+        } => {
+            let existing = require_rx(db, RxId::from(rx)).await?.authorization;
+            let authorization = Authorization {
+                refills_remaining: refills.or(existing.refills_remaining),
+                expires: expires.or(existing.expires),
+                // Refills read off a label are what remains after the original fill
+                original_fill_pending: existing.original_fill_pending && refills.is_none(),
+            };
+            let date = date.unwrap_or_else(today);
+            set_authorization(db, RxId::from(rx), &authorization, date).await?;
+            println!("Updated the authorization of {}", RxId::from(rx));
+        }
+        RxCommand::SetPrescriber { rx, prescriber } => {
+            let rx = RxId::from(rx);
+            let prescriber = prescriber.map(PrescriberId::from);
+            set_rx_prescriber(db, rx, prescriber).await?;
+            match prescriber {
+                Some(prescriber) => println!("Set the prescriber of {} to {}", rx, prescriber),
+                None => println!("Cleared the prescriber of {}", rx),
+            }
+        }
+        RxCommand::SetDose { rx, units, every } => {
+            let rx = RxId::from(rx);
+            let dose = units.map(|units| DoseRate {
+                units,
+                every_days: every,
+            });
+            set_dose_rate(db, rx, dose).await?;
+            match dose {
+                Some(dose) => println!("Set the dose of {} to {}", rx, dose),
+                None => println!("Cleared the dose of {}", rx),
+            }
+        }
+        RxCommand::SetCritical { rx, days } => {
+            let rx = RxId::from(rx);
+            set_critical_days(db, rx, days).await?;
+            match days {
+                Some(days) => println!("Marked {} critical below {} days on hand", rx, days),
+                None => println!("Unmarked {} as critical", rx),
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,114 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! A minimal local HTTP server for subscribing to the iCalendar feed from calendar apps.
+
+use std::{
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+use clap::Args;
+use rxtrack_model::ics::IcsComponent;
+use sea_orm::ConnectionTrait;
+
+use crate::{error::AppError, export::render_feed};
+
+/// How long to wait on a client before giving up on it, so one client cannot stall the feed.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The most to read of the request line and headers.
+const MAX_REQUEST_SIZE: u64 = 8192;
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8017")]
+    bind: String,
+    /// Only include prescriptions of this person
+    #[arg(long)]
+    person: Option<i32>,
+    /// Serve to-do items instead of all-day events
+    #[arg(long)]
+    todo: bool,
+}
+
+/// Read the request line, and skip the headers.
+fn read_request_line(stream: &TcpStream) -> std::io::Result<String> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST_SIZE));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header != "\r\n" && header != "\n" {
+        header.clear();
+    }
+    if reader.get_ref().limit() == 0 {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("request headers longer than {} bytes", MAX_REQUEST_SIZE),
+        ));
+    }
+    Ok(request_line)
+}
+
+fn respond(
+    mut stream: &TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+async fn handle(
+    db: &impl ConnectionTrait,
+    args: &ServeArgs,
+    stream: &TcpStream,
+) -> Result<(), AppError> {
+    let io_error = |e| AppError::io(&args.bind, e);
+    let request_line = read_request_line(stream).map_err(io_error)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let path = path.split('?').next().unwrap_or_default();
+    if method != "GET" {
+        return respond(stream, "405 Method Not Allowed", "text/plain", "").map_err(io_error);
+    }
+    if path != "/" && path != "/rxtrack.ics" {
+        return respond(stream, "404 Not Found", "text/plain", "").map_err(io_error);
+    }
+    let component = if args.todo {
+        IcsComponent::Todo
+    } else {
+        IcsComponent::Event
+    };
+    let ics = render_feed(db, args.person, component).await?;
+    respond(stream, "200 OK", "text/calendar; charset=utf-8", &ics).map_err(io_error)
+}
+
+/// Serve the feed until interrupted, generating it fresh for each request.
+pub async fn run(db: &impl ConnectionTrait, args: ServeArgs) -> Result<(), AppError> {
+    let listener = TcpListener::bind(&args.bind).map_err(|e| AppError::io(&args.bind, e))?;
+    println!("Serving http://{}/rxtrack.ics", args.bind);
+    for stream in listener.incoming() {
+        let result = match stream {
+            Ok(stream) => handle(db, &args, &stream).await,
+            Err(e) => Err(AppError::io(&args.bind, e)),
+        };
+        if let Err(err) = result {
+            eprintln!("error: {}", err);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,79 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+use clap::Args;
+use rxtrack_model::{
+    rx::list_rx,
+    stats::{request_outcomes, rx_stats, RequestOutcomes, RxStats},
+    Error, PersonId, RxId,
+};
+use sea_orm::ConnectionTrait;
+use time::{Date, Duration};
+
+use crate::{
+    dates::{parse_date, today},
+    rx::require_rx,
+};
+
+#[derive(Debug, Args)]
+pub struct StatsArgs {
+    /// Prescription ID, defaults to all prescriptions that are not hidden
+    rx: Option<i32>,
+    /// Only include prescriptions of this person
+    #[arg(long, conflicts_with = "rx")]
+    person: Option<i32>,
+    /// First day of the window, defaults to a year before the last day
+    #[arg(long, value_parser = parse_date)]
+    from: Option<Date>,
+    /// Last day of the window, defaults to today
+    #[arg(long, value_parser = parse_date)]
+    to: Option<Date>,
+}
+
+fn percent(value: Option<f64>) -> String {
+    value.map_or_else(|| "unknown".to_owned(), |v| format!("{:.1}%", v * 100.0))
+}
+
+fn print_outcomes(outcomes: &RequestOutcomes) {
+    println!(
+        "  Requests: {} completed, {} superseded, {} cancelled, {} open",
+        outcomes.completed, outcomes.superseded, outcomes.cancelled, outcomes.open
+    );
+}
+
+fn print_stats(stats: &RxStats) {
+    println!("  Pick-ups: {}", stats.pickups);
+    println!(
+        "  Proportion of days covered: {}",
+        percent(stats.proportion_of_days_covered)
+    );
+    println!(
+        "  Medication possession ratio: {}",
+        percent(stats.medication_possession_ratio)
+    );
+    match stats.longest_gap_days {
+        Some(days) => println!("  Longest gap: {} days", days),
+        None => println!("  Longest gap: unknown"),
+    }
+    match stats.average_days_between_pickups {
+        Some(days) => println!("  Average between pick-ups: {:.1} days", days),
+        None => println!("  Average between pick-ups: unknown"),
+    }
+}
+
+pub async fn run(db: &impl ConnectionTrait, args: StatsArgs) -> Result<(), Error> {
+    let end = args.to.unwrap_or_else(today);
+    let start = args.from.unwrap_or(end - Duration::days(364));
+    let rxs = match args.rx {
+        Some(rx) => vec![require_rx(db, RxId::from(rx)).await?],
+        None => list_rx(db, args.person.map(PersonId::from)).await?,
+    };
+    println!("From {} to {}", start, end);
+    for rx in &rxs {
+        let stats = rx_stats(db, rx.id, start, end).await?;
+        println!("{}\t{}", i32::from(rx.id), rx.name);
+        print_stats(&stats);
+        print_outcomes(&request_outcomes(db, rx.id, start, end).await?);
+    }
+    Ok(())
+}
@@ -6,8 +6,28 @@ use sea_orm_migration::sea_orm::{DeriveActiveEnum, EnumIter};
 
 mod m20220101_000001_create_tables;
 mod m20230122_000001_generic_event;
+mod m20230128_000001_integer_event_type;
+mod m20230204_000001_backfill_events;
+mod m20230211_000001_days_supply;
+mod m20230218_000001_person;
+mod m20230225_000001_integer_reminder_start;
+mod m20230304_000001_retire_rx;
+mod m20230311_000001_normalized_rx_name;
+mod m20230318_000001_imported_dispense;
+mod m20230325_000001_close_reason;
+mod m20230401_000001_event_correction;
+mod m20230408_000001_pharmacy;
+mod m20230415_000001_prescriber;
+mod m20230422_000001_rx_authorization;
+mod m20230429_000001_renewal_request;
+mod m20230506_000001_dose_quantity;
+mod m20230513_000001_inventory;
 
-#[derive(Debug, PartialEq, EnumIter, DeriveActiveEnum, Iden)]
+pub use m20230204_000001_backfill_events::{backfill_events, LegacyFillRequest};
+pub use m20230325_000001_close_reason::is_imported_fill;
+pub use m20230401_000001_event_correction::{link_steps, StepKey};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter, DeriveActiveEnum, Iden)]
 #[sea_orm(rs_type = "i32", db_type = "Integer")]
 pub enum EventType {
     #[sea_orm(num_value = 0)]
@@ -20,6 +40,58 @@ pub enum EventType {
     RefillCancel,
 }
 
+/// Why a fill request was closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum, Iden)]
+#[sea_orm(rs_type = "i32", db_type = "Integer")]
+pub enum CloseReason {
+    /// Picked up, or imported as dispensed
+    #[sea_orm(num_value = 0)]
+    Completed,
+    /// Replaced by a newer request
+    #[sea_orm(num_value = 1)]
+    Superseded,
+    /// Cancelled without a newer request
+    #[sea_orm(num_value = 2)]
+    Cancelled,
+}
+
+/// What the pick-up of a fill request used up of the script of its rx.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum, Iden)]
+#[sea_orm(rs_type = "i32", db_type = "Integer")]
+pub enum RefillUse {
+    /// Nothing, e.g. not picked up, or picked up before the script was authorized
+    #[sea_orm(num_value = 0)]
+    Nothing,
+    /// One of the refills of the script
+    #[sea_orm(num_value = 1)]
+    Refill,
+    /// Nothing, because it was imported,
+    /// and the refills recorded for the script may already reflect it
+    #[sea_orm(num_value = 2)]
+    Imported,
+    /// The original fill of a newly approved script, which is not one of its refills
+    #[sea_orm(num_value = 3)]
+    OriginalFill,
+}
+
+/// Where a request to renew a script is in its lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum, Iden)]
+#[sea_orm(rs_type = "i32", db_type = "Integer")]
+pub enum RenewalStatus {
+    /// Asked the prescriber, waiting on an answer
+    #[sea_orm(num_value = 0)]
+    Requested,
+    /// The prescriber approved, waiting on the script to reach the pharmacy
+    #[sea_orm(num_value = 1)]
+    Approved,
+    /// The prescriber denied the renewal
+    #[sea_orm(num_value = 2)]
+    Denied,
+    /// The new script reached the pharmacy
+    #[sea_orm(num_value = 3)]
+    Received,
+}
+
 pub struct Migrator;
 
 #[async_trait::async_trait]
@@ -28,6 +100,33 @@ impl MigratorTrait for Migrator {
         vec![
             Box::new(m20220101_000001_create_tables::Migration),
             Box::new(m20230122_000001_generic_event::Migration),
+            Box::new(m20230128_000001_integer_event_type::Migration),
+            Box::new(m20230204_000001_backfill_events::Migration),
+            Box::new(m20230211_000001_days_supply::Migration),
+            Box::new(m20230218_000001_person::Migration),
+            Box::new(m20230225_000001_integer_reminder_start::Migration),
+            Box::new(m20230304_000001_retire_rx::Migration),
+            Box::new(m20230311_000001_normalized_rx_name::Migration),
+            Box::new(m20230318_000001_imported_dispense::Migration),
+            Box::new(m20230325_000001_close_reason::Migration),
+            Box::new(m20230401_000001_event_correction::Migration),
+            Box::new(m20230408_000001_pharmacy::Migration),
+            Box::new(m20230415_000001_prescriber::Migration),
+            Box::new(m20230422_000001_rx_authorization::Migration),
+            Box::new(m20230429_000001_renewal_request::Migration),
+            Box::new(m20230506_000001_dose_quantity::Migration),
+            Box::new(m20230513_000001_inventory::Migration),
         ]
     }
 }
+
+impl Migrator {
+    /// The number of migrations that come before the one with the given name,
+    /// for migrating a database up to just before it.
+    pub fn steps_before(name: &str) -> Option<u32> {
+        Self::migrations()
+            .iter()
+            .position(|migration| migration.name() == name)
+            .map(|index| index as u32)
+    }
+}
@@ -1,7 +1,6 @@
 // Copyright 2022-2023, Ryan Pavlik <ryan@ryanpavlik.com>
 // SPDX-License-Identifier: GPL3+
 
-use sea_orm_migration::prelude::*;
 use sea_orm_migration::{
     prelude::*,
     sea_orm::{ActiveEnum, Iterable},
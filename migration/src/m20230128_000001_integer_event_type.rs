@@ -0,0 +1,64 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! The generic event migration declared the event column as an enumeration,
+//! which ends up as a text column on sqlite, while `EventType` is stored as an integer.
+//! Nothing has written to the events table yet, so just re-create it with an integer column.
+
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{ActiveEnum, Iterable},
+};
+
+use crate::m20220101_000001_create_tables::RxInfo;
+use crate::m20230122_000001_generic_event::Events;
+use crate::EventType;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+fn create_events_table(event_column: &mut ColumnDef) -> TableCreateStatement {
+    Table::create()
+        .table(Events::Table)
+        .if_not_exists()
+        .col(
+            ColumnDef::new(Events::Id)
+                .integer()
+                .not_null()
+                .auto_increment()
+                .primary_key(),
+        )
+        .col(ColumnDef::new(Events::RxId).integer().not_null())
+        .foreign_key(
+            ForeignKey::create()
+                .name("fk-events-rx_id")
+                .from(Events::Table, Events::RxId)
+                .to(RxInfo::Table, RxInfo::RxId),
+        )
+        .col(event_column.not_null())
+        .col(ColumnDef::new(Events::Date).date().not_null())
+        .to_owned()
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Events::Table).to_owned())
+            .await?;
+        manager
+            .create_table(create_events_table(ColumnDef::new(Events::Event).integer()))
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Events::Table).to_owned())
+            .await?;
+        manager
+            .create_table(create_events_table(
+                ColumnDef::new(Events::Event).enumeration(EventType::name(), EventType::iter()),
+            ))
+            .await
+    }
+}
@@ -0,0 +1,364 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Turn every existing `fill_request` row into the equivalent events,
+//! so that history is preserved when switching to event-based fill requests.
+//!
+//! Each row becomes a `RequestFill`, `Fill`, and `PickUp` event for each date it has.
+//! A row that was closed without being picked up was superseded by the following request,
+//! so it gets a `RefillCancel` event dated when that next request was made.
+//!
+//! The events are inserted row by row, in row order.
+//! Rows that do not fold back into themselves, such as one filled before it was requested,
+//! are still backfilled: the migration warns about them rather than failing,
+//! and `find_legacy_mismatches` in the model lists them later.
+//! An rx that already has events was recorded through both tables, so it is skipped.
+
+use std::collections::HashSet;
+
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{prelude::TimeDate, ConnectionTrait, QueryResult},
+};
+
+use crate::m20220101_000001_create_tables::FillRequest;
+use crate::m20230122_000001_generic_event::Events;
+use crate::EventType;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// The dates requested, filled, and picked up, and whether the request is closed.
+type FillRequestDates = (Option<TimeDate>, Option<TimeDate>, Option<TimeDate>, bool);
+
+/// A `fill_request` row as it was before events, with only the columns the events come from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LegacyFillRequest {
+    pub rx_id: i32,
+    pub date_requested: Option<TimeDate>,
+    pub date_filled: Option<TimeDate>,
+    pub date_picked_up: Option<TimeDate>,
+    pub closed: bool,
+}
+
+impl LegacyFillRequest {
+    fn try_from_row(row: &QueryResult) -> Result<Self, DbErr> {
+        Ok(LegacyFillRequest {
+            rx_id: row.try_get("", &FillRequest::RxId.to_string())?,
+            date_requested: row.try_get("", &FillRequest::DateRequested.to_string())?,
+            date_filled: row.try_get("", &FillRequest::DateFilled.to_string())?,
+            date_picked_up: row.try_get("", &FillRequest::DatePickedUp.to_string())?,
+            closed: row.try_get("", &FillRequest::Closed.to_string())?,
+        })
+    }
+
+    /// The events equivalent to this row, given the next row for the same rx (if any).
+    fn events(&self, next: Option<&LegacyFillRequest>) -> Vec<(EventType, TimeDate)> {
+        let mut events = Vec::new();
+        if let Some(date) = self.date_requested {
+            events.push((EventType::RequestFill, date));
+        }
+        if let Some(date) = self.date_filled {
+            events.push((EventType::Fill, date));
+        }
+        if let Some(date) = self.date_picked_up {
+            events.push((EventType::PickUp, date));
+        } else if self.closed {
+            let cancel_date = next
+                .and_then(|next| next.date_requested)
+                .or(self.date_filled)
+                .or(self.date_requested);
+            if let Some(date) = cancel_date {
+                events.push((EventType::RefillCancel, date));
+            }
+        }
+        events
+    }
+
+    fn dates(&self) -> FillRequestDates {
+        (
+            self.date_requested,
+            self.date_filled,
+            self.date_picked_up,
+            self.closed,
+        )
+    }
+}
+
+/// Fold the events of a single rx, sorted by date, into the dates of its fill requests,
+/// following the same rules as `ImplicitFillRequest`.
+fn fold_events(events: &[(EventType, TimeDate)]) -> Vec<FillRequestDates> {
+    let mut requests = Vec::new();
+    let mut open: Option<FillRequestDates> = None;
+    for &(event, date) in events {
+        match event {
+            EventType::RequestFill => {
+                if let Some(mut superseded) = open.take() {
+                    superseded.3 = true;
+                    requests.push(superseded);
+                }
+                open = Some((Some(date), None, None, false));
+            }
+            EventType::Fill => {
+                open.get_or_insert((None, None, None, false)).1 = Some(date);
+            }
+            EventType::PickUp => {
+                let mut request = open.take().unwrap_or((None, None, None, false));
+                request.2 = Some(date);
+                request.3 = true;
+                requests.push(request);
+            }
+            EventType::RefillCancel => {
+                if let Some(mut cancelled) = open.take() {
+                    cancelled.3 = true;
+                    requests.push(cancelled);
+                }
+            }
+        }
+    }
+    requests.extend(open);
+    requests
+}
+
+/// Turn legacy rows, sorted by rx and then ID, into events, in the order to insert them.
+///
+/// Returns each event with the index of the row it came from.
+/// Also used to restore backups made before this migration.
+pub fn backfill_events(rows: &[LegacyFillRequest]) -> Vec<(usize, EventType, TimeDate)> {
+    rows.iter()
+        .enumerate()
+        .flat_map(|(i, row)| {
+            let next = rows.get(i + 1).filter(|next| next.rx_id == row.rx_id);
+            row.events(next)
+                .into_iter()
+                .map(move |(event, date)| (i, event, date))
+        })
+        .collect()
+}
+
+/// Whether the events backfilled from the rows of a single rx fold back into those rows.
+fn folds_back(rows: &[&LegacyFillRequest], events: &[(EventType, TimeDate)]) -> bool {
+    let mut events = events.to_vec();
+    // Stable, so events on the same date stay in the order they were inserted
+    events.sort_by_key(|&(_, date)| date);
+    let legacy: Vec<_> = rows.iter().map(|row| row.dates()).collect();
+    fold_events(&events) == legacy
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        let backend = manager.get_database_backend();
+
+        let select = Query::select()
+            .distinct()
+            .column(Events::RxId)
+            .from(Events::Table)
+            .to_owned();
+        let recorded: HashSet<i32> = db
+            .query_all(backend.build(&select))
+            .await?
+            .iter()
+            .map(|row| row.try_get("", &Events::RxId.to_string()))
+            .collect::<Result<_, _>>()?;
+
+        let select = Query::select()
+            .columns([
+                FillRequest::RxId,
+                FillRequest::DateRequested,
+                FillRequest::DateFilled,
+                FillRequest::DatePickedUp,
+                FillRequest::Closed,
+            ])
+            .from(FillRequest::Table)
+            .order_by(FillRequest::RxId, Order::Asc)
+            .order_by(FillRequest::Id, Order::Asc)
+            .to_owned();
+        let rows = db
+            .query_all(backend.build(&select))
+            .await?
+            .iter()
+            .map(LegacyFillRequest::try_from_row)
+            .filter(|row| {
+                row.as_ref()
+                    .map_or(true, |row| !recorded.contains(&row.rx_id))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let events = backfill_events(&rows);
+        for &(i, event, date) in &events {
+            let insert = Query::insert()
+                .into_table(Events::Table)
+                .columns([Events::RxId, Events::Event, Events::Date])
+                .values_panic([rows[i].rx_id.into(), event.into(), date.into()])
+                .to_owned();
+            db.execute(backend.build(&insert)).await?;
+        }
+
+        let mut mismatches = Vec::new();
+        let mut start = 0;
+        while start < rows.len() {
+            let rx_id = rows[start].rx_id;
+            let end = start
+                + rows[start..]
+                    .iter()
+                    .take_while(|row| row.rx_id == rx_id)
+                    .count();
+            let rx_rows: Vec<_> = rows[start..end].iter().collect();
+            let rx_events: Vec<_> = events
+                .iter()
+                .filter(|&&(i, _, _)| (start..end).contains(&i))
+                .map(|&(_, event, date)| (event, date))
+                .collect();
+            if !folds_back(&rx_rows, &rx_events) {
+                mismatches.push(rx_id);
+            }
+            start = end;
+        }
+        if !mismatches.is_empty() {
+            eprintln!(
+                "Warning: events backfilled from fill_request do not match the rows of rx {:?}",
+                mismatches
+            );
+        }
+        Ok(())
+    }
+
+    /// Every event so far can be rebuilt from the `fill_request` table, so empty it.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let delete = Query::delete().from_table(Events::Table).to_owned();
+        manager
+            .get_connection()
+            .execute(manager.get_database_backend().build(&delete))
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use sea_orm_migration::sea_orm::{Database, DatabaseConnection, Statement};
+
+    use super::*;
+    use crate::{m20220101_000001_create_tables::RxInfo, Migrator};
+
+    /// Migrate a fresh database up to just before the backfill, with two prescriptions.
+    async fn legacy_db() -> Result<DatabaseConnection, DbErr> {
+        let db = Database::connect("sqlite::memory:").await?;
+        let steps = Migrator::steps_before(Migration.name()).unwrap();
+        Migrator::up(&db, Some(steps)).await?;
+        let backend = db.get_database_backend();
+        for name in ["amoxicillin", "prednisone"] {
+            let insert = Query::insert()
+                .into_table(RxInfo::Table)
+                .columns([RxInfo::RxName])
+                .values_panic([name.into()])
+                .to_owned();
+            db.execute(backend.build(&insert)).await?;
+        }
+        Ok(db)
+    }
+
+    async fn insert_row(
+        db: &impl ConnectionTrait,
+        rx_id: i32,
+        dates: [Option<&str>; 3],
+        closed: bool,
+    ) -> Result<(), DbErr> {
+        let backend = db.get_database_backend();
+        let insert = Query::insert()
+            .into_table(FillRequest::Table)
+            .columns([
+                FillRequest::RxId,
+                FillRequest::DateRequested,
+                FillRequest::DateFilled,
+                FillRequest::DatePickedUp,
+                FillRequest::Closed,
+            ])
+            .values_panic([
+                rx_id.into(),
+                dates[0].into(),
+                dates[1].into(),
+                dates[2].into(),
+                closed.into(),
+            ])
+            .to_owned();
+        db.execute(backend.build(&insert)).await?;
+        Ok(())
+    }
+
+    async fn count_events(db: &impl ConnectionTrait) -> Result<i32, DbErr> {
+        let row = db
+            .query_one(Statement::from_string(
+                db.get_database_backend(),
+                "SELECT COUNT(*) AS count FROM events".to_owned(),
+            ))
+            .await?
+            .unwrap();
+        row.try_get("", "count")
+    }
+
+    #[async_std::test]
+    async fn test_backfill() -> Result<(), DbErr> {
+        let db = legacy_db().await?;
+        // Superseded, then picked up, then open
+        insert_row(&db, 1, [Some("2023-01-01"), None, None], true).await?;
+        insert_row(
+            &db,
+            1,
+            [Some("2023-01-05"), Some("2023-01-06"), Some("2023-01-07")],
+            true,
+        )
+        .await?;
+        insert_row(&db, 1, [Some("2023-02-01"), None, None], false).await?;
+        // Cancelled after being filled
+        insert_row(&db, 2, [Some("2023-01-01"), Some("2023-01-02"), None], true).await?;
+
+        Migrator::up(&db, None).await?;
+        // 2 + 3 + 1 for the first rx and 3 for the second, each closed request with a cancel
+        assert_eq!(count_events(&db).await?, 9);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_backfill_odd_rows() -> Result<(), DbErr> {
+        let db = legacy_db().await?;
+        // Filled and picked up before it was requested
+        insert_row(
+            &db,
+            1,
+            [Some("2023-01-10"), Some("2023-01-05"), Some("2023-01-06")],
+            true,
+        )
+        .await?;
+        // Left open, but followed by a newer request that supersedes it
+        insert_row(&db, 2, [Some("2023-01-01"), None, None], false).await?;
+        insert_row(&db, 2, [Some("2023-01-05"), None, None], false).await?;
+
+        // Still backfilled, rather than failing the migration
+        Migrator::up(&db, None).await?;
+        assert_eq!(count_events(&db).await?, 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_folds_back() {
+        let date = |day| TimeDate::from_ordinal_date(2023, day).unwrap();
+        let row = |dates: [Option<u16>; 3], closed| LegacyFillRequest {
+            rx_id: 1,
+            date_requested: dates[0].map(date),
+            date_filled: dates[1].map(date),
+            date_picked_up: dates[2].map(date),
+            closed,
+        };
+        let superseded = row([Some(1), None, None], true);
+        let picked_up = row([Some(5), Some(6), Some(7)], true);
+        let events: Vec<_> = [superseded.events(Some(&picked_up)), picked_up.events(None)].concat();
+        assert!(folds_back(&[&superseded, &picked_up], &events));
+
+        let early_fill = row([Some(10), Some(5), Some(6)], true);
+        assert!(!folds_back(&[&early_fill], &early_fill.events(None)));
+    }
+}
@@ -0,0 +1,70 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+use sea_orm_migration::prelude::*;
+
+use crate::m20220101_000001_create_tables::RxInfo;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(Iden)]
+pub enum RxDaysSupply {
+    Table,
+    Id,
+    RxId,
+    /// The days supply applies to fills on or after this date
+    EffectiveDate,
+    DaysSupply,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(RxDaysSupply::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RxDaysSupply::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(RxDaysSupply::RxId).integer().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-days_supply-rx_id")
+                            .from(RxDaysSupply::Table, RxDaysSupply::RxId)
+                            .to(RxInfo::Table, RxInfo::RxId),
+                    )
+                    .col(
+                        ColumnDef::new(RxDaysSupply::EffectiveDate)
+                            .date()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(RxDaysSupply::DaysSupply)
+                            .integer()
+                            .not_null(),
+                    )
+                    .index(
+                        Index::create()
+                            .name("idx-days_supply-rx_id-effective_date")
+                            .col(RxDaysSupply::RxId)
+                            .col(RxDaysSupply::EffectiveDate)
+                            .unique(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(RxDaysSupply::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Restore the person table, and optionally associate each rx with a person.
+//! Existing prescriptions are left without a person.
+
+use sea_orm_migration::{prelude::*, sea_orm::DbBackend};
+
+use crate::m20220101_000001_create_tables::RxInfo;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(Iden)]
+#[allow(clippy::enum_variant_names)]
+pub enum Person {
+    Table,
+    PersonId,
+    Name,
+}
+
+#[derive(Iden)]
+enum RxInfoPerson {
+    PersonId,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Person::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Person::PersonId)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Person::Name).string().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        let mut person_id = ColumnDef::new(RxInfoPerson::PersonId);
+        person_id.integer();
+        if manager.get_database_backend() == DbBackend::Sqlite {
+            // Sqlite cannot add a foreign key to an existing table, but can add a column with a reference.
+            person_id.extra(format!(
+                "REFERENCES \"{}\" (\"{}\")",
+                Person::Table.to_string(),
+                Person::PersonId.to_string()
+            ));
+        }
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RxInfo::Table)
+                    .add_column(&mut person_id)
+                    .to_owned(),
+            )
+            .await?;
+        if manager.get_database_backend() != DbBackend::Sqlite {
+            manager
+                .create_foreign_key(
+                    ForeignKey::create()
+                        .name("fk-rx-person_id")
+                        .from(RxInfo::Table, RxInfoPerson::PersonId)
+                        .to(Person::Table, Person::PersonId)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.get_database_backend() != DbBackend::Sqlite {
+            manager
+                .drop_foreign_key(
+                    ForeignKey::drop()
+                        .name("fk-rx-person_id")
+                        .table(RxInfo::Table)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RxInfo::Table)
+                    .drop_column(RxInfoPerson::PersonId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Person::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,196 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Like the events table, the reminder policy starting date was declared as an enumeration,
+//! which ends up as a text column on sqlite, while `EventType` is stored as an integer.
+//! Re-create the table with an integer column, converting any existing rows.
+//! Starting dates that cannot be understood are cleared.
+
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{ActiveEnum, ConnectionTrait, Iterable, QueryResult},
+};
+
+use crate::m20220101_000001_create_tables::{ReminderPolicy, RxInfo};
+use crate::EventType;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+struct PolicyRow {
+    reminder_id: i32,
+    rx_id: i32,
+    starting_date: Option<String>,
+    include_rx_duration: bool,
+    offset: i32,
+    allow_saturday: bool,
+    allow_sunday: bool,
+    description: String,
+}
+
+impl PolicyRow {
+    fn try_from_row(row: &QueryResult) -> Result<Self, DbErr> {
+        Ok(PolicyRow {
+            reminder_id: row.try_get("", &ReminderPolicy::ReminderId.to_string())?,
+            rx_id: row.try_get("", &ReminderPolicy::RxId.to_string())?,
+            starting_date: row.try_get("", &ReminderPolicy::StartingDate.to_string())?,
+            include_rx_duration: row.try_get("", &ReminderPolicy::IncludeRxDuration.to_string())?,
+            offset: row.try_get("", &ReminderPolicy::Offset.to_string())?,
+            allow_saturday: row.try_get("", &ReminderPolicy::AllowSaturday.to_string())?,
+            allow_sunday: row.try_get("", &ReminderPolicy::AllowSunday.to_string())?,
+            description: row.try_get("", &ReminderPolicy::Description.to_string())?,
+        })
+    }
+}
+
+/// Parse a starting date stored either as the name used in the enumeration (e.g. `pick_up`)
+/// or as its integer value.
+fn parse_starting_event(starting_date: &str) -> Option<EventType> {
+    let starting_date = starting_date.trim();
+    if let Ok(value) = starting_date.parse::<i32>() {
+        return EventType::try_from_value(&value).ok();
+    }
+    EventType::iter().find(|event_type| Iden::to_string(event_type) == starting_date)
+}
+
+fn create_reminder_policy_table(starting_date_column: &mut ColumnDef) -> TableCreateStatement {
+    Table::create()
+        .table(ReminderPolicy::Table)
+        .if_not_exists()
+        .col(
+            ColumnDef::new(ReminderPolicy::ReminderId)
+                .integer()
+                .not_null()
+                .auto_increment()
+                .primary_key(),
+        )
+        .col(ColumnDef::new(ReminderPolicy::RxId).integer().not_null())
+        .foreign_key(
+            ForeignKey::create()
+                .name("fk-reminder-rx_id")
+                .from(ReminderPolicy::Table, ReminderPolicy::RxId)
+                .to(RxInfo::Table, RxInfo::RxId),
+        )
+        .col(starting_date_column)
+        .col(
+            ColumnDef::new(ReminderPolicy::IncludeRxDuration)
+                .boolean()
+                .not_null(),
+        )
+        .col(ColumnDef::new(ReminderPolicy::Offset).integer().not_null())
+        .col(
+            ColumnDef::new(ReminderPolicy::AllowSaturday)
+                .boolean()
+                .not_null(),
+        )
+        .col(
+            ColumnDef::new(ReminderPolicy::AllowSunday)
+                .boolean()
+                .not_null(),
+        )
+        .col(
+            ColumnDef::new(ReminderPolicy::Description)
+                .string()
+                .not_null(),
+        )
+        .to_owned()
+}
+
+/// Read every policy, then drop and re-create the table with the given starting date column,
+/// and put the policies back with their starting date converted.
+async fn recreate_reminder_policy_table(
+    manager: &SchemaManager<'_>,
+    starting_date_column: &mut ColumnDef,
+    convert_starting_date: impl Fn(&str) -> Option<Value>,
+) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+    let backend = manager.get_database_backend();
+
+    let select = Query::select()
+        .columns([
+            ReminderPolicy::ReminderId,
+            ReminderPolicy::RxId,
+            ReminderPolicy::StartingDate,
+            ReminderPolicy::IncludeRxDuration,
+            ReminderPolicy::Offset,
+            ReminderPolicy::AllowSaturday,
+            ReminderPolicy::AllowSunday,
+            ReminderPolicy::Description,
+        ])
+        .from(ReminderPolicy::Table)
+        .order_by(ReminderPolicy::ReminderId, Order::Asc)
+        .to_owned();
+    let rows = db
+        .query_all(backend.build(&select))
+        .await?
+        .iter()
+        .map(PolicyRow::try_from_row)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    manager
+        .drop_table(Table::drop().table(ReminderPolicy::Table).to_owned())
+        .await?;
+    manager
+        .create_table(create_reminder_policy_table(starting_date_column))
+        .await?;
+
+    for row in rows {
+        let starting_date = row
+            .starting_date
+            .as_deref()
+            .and_then(&convert_starting_date);
+        let insert = Query::insert()
+            .into_table(ReminderPolicy::Table)
+            .columns([
+                ReminderPolicy::ReminderId,
+                ReminderPolicy::RxId,
+                ReminderPolicy::StartingDate,
+                ReminderPolicy::IncludeRxDuration,
+                ReminderPolicy::Offset,
+                ReminderPolicy::AllowSaturday,
+                ReminderPolicy::AllowSunday,
+                ReminderPolicy::Description,
+            ])
+            .values_panic([
+                row.reminder_id.into(),
+                row.rx_id.into(),
+                starting_date.unwrap_or(Value::Int(None)).into(),
+                row.include_rx_duration.into(),
+                row.offset.into(),
+                row.allow_saturday.into(),
+                row.allow_sunday.into(),
+                row.description.into(),
+            ])
+            .to_owned();
+        db.execute(backend.build(&insert)).await?;
+    }
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        recreate_reminder_policy_table(
+            manager,
+            ColumnDef::new(ReminderPolicy::StartingDate).integer(),
+            |starting_date| {
+                parse_starting_event(starting_date)
+                    .map(|event_type| Value::from(event_type.to_value()))
+            },
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        recreate_reminder_policy_table(
+            manager,
+            ColumnDef::new(ReminderPolicy::StartingDate)
+                .enumeration(EventType::name(), EventType::iter()),
+            |starting_date| {
+                parse_starting_event(starting_date)
+                    .map(|event_type| Value::from(Iden::to_string(&event_type)))
+            },
+        )
+        .await
+    }
+}
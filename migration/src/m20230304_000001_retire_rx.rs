@@ -0,0 +1,55 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Record when and why an rx was permanently retired.
+//! Existing prescriptions are left active.
+
+use sea_orm_migration::prelude::*;
+
+use crate::m20220101_000001_create_tables::RxInfo;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(Iden)]
+enum RxInfoRetired {
+    RetiredDate,
+    RetiredReason,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Sqlite can only add one column per statement.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RxInfo::Table)
+                    .add_column(ColumnDef::new(RxInfoRetired::RetiredDate).date())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RxInfo::Table)
+                    .add_column(ColumnDef::new(RxInfoRetired::RetiredReason).string())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [RxInfoRetired::RetiredReason, RxInfoRetired::RetiredDate] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(RxInfo::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Add a normalized rx name, unique per person, to detect duplicate prescriptions.
+//!
+//! Existing prescriptions get their normalized name, except for retired ones,
+//! and any that duplicate an earlier prescription of the same person: those are left `NULL`,
+//! which does not conflict in a unique index.
+
+use std::collections::HashSet;
+
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{prelude::TimeDate, ConnectionTrait, Statement},
+};
+
+use crate::m20220101_000001_create_tables::RxInfo;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(Iden)]
+enum RxInfoNormalized {
+    NormalizedName,
+    PersonId,
+    RetiredDate,
+}
+
+const INDEX_NAME: &str = "idx-rx-person_id-normalized_name";
+
+/// Normalize an rx name as of this migration: trimmed, with whitespace collapsed, in lowercase.
+fn normalize_rx_name(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        let backend = manager.get_database_backend();
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RxInfo::Table)
+                    .add_column(ColumnDef::new(RxInfoNormalized::NormalizedName).string())
+                    .to_owned(),
+            )
+            .await?;
+
+        let select = Query::select()
+            .columns([RxInfo::RxId, RxInfo::RxName])
+            .columns([RxInfoNormalized::PersonId, RxInfoNormalized::RetiredDate])
+            .from(RxInfo::Table)
+            .order_by(RxInfo::RxId, Order::Asc)
+            .to_owned();
+        let mut seen = HashSet::new();
+        for row in db.query_all(backend.build(&select)).await? {
+            let rx_id: i32 = row.try_get("", &RxInfo::RxId.to_string())?;
+            let rx_name: String = row.try_get("", &RxInfo::RxName.to_string())?;
+            let person_id: Option<i32> =
+                row.try_get("", &RxInfoNormalized::PersonId.to_string())?;
+            let retired_date: Option<TimeDate> =
+                row.try_get("", &RxInfoNormalized::RetiredDate.to_string())?;
+            if retired_date.is_some() {
+                continue;
+            }
+            let normalized_name = normalize_rx_name(&rx_name);
+            if !seen.insert((person_id, normalized_name.clone())) {
+                continue;
+            }
+            let update = Query::update()
+                .table(RxInfo::Table)
+                .value(RxInfoNormalized::NormalizedName, normalized_name)
+                .and_where(Expr::col(RxInfo::RxId).eq(rx_id))
+                .to_owned();
+            db.execute(backend.build(&update)).await?;
+        }
+
+        // A prescription without a person must also be unique, but NULL never conflicts,
+        // so index on an expression. The parentheses make this valid for every backend.
+        db.execute(Statement::from_string(
+            backend,
+            format!(
+                r#"CREATE UNIQUE INDEX "{}" ON "{}" ((COALESCE("{}", 0)), "{}")"#,
+                INDEX_NAME,
+                RxInfo::Table.to_string(),
+                RxInfoNormalized::PersonId.to_string(),
+                RxInfoNormalized::NormalizedName.to_string(),
+            ),
+        ))
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name(INDEX_NAME)
+                    .table(RxInfo::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RxInfo::Table)
+                    .drop_column(RxInfoNormalized::NormalizedName)
+                    .to_owned(),
+            )
+            .await
+    }
+}
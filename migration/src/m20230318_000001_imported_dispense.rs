@@ -0,0 +1,67 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Remember which dispenses have been imported, so importing the same history again skips them.
+
+use sea_orm_migration::prelude::*;
+
+use crate::m20220101_000001_create_tables::RxInfo;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(Iden)]
+pub enum ImportedDispense {
+    Table,
+    Id,
+    RxId,
+    /// Identifies the imported row within its rx, from its rx name, rx number, and dates
+    ImportKey,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImportedDispense::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImportedDispense::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ImportedDispense::RxId).integer().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-imported_dispense-rx_id")
+                            .from(ImportedDispense::Table, ImportedDispense::RxId)
+                            .to(RxInfo::Table, RxInfo::RxId),
+                    )
+                    .col(
+                        ColumnDef::new(ImportedDispense::ImportKey)
+                            .string()
+                            .not_null(),
+                    )
+                    // The same history can be imported for more than one person
+                    .index(
+                        Index::create()
+                            .name("idx-imported_dispense-rx_id-import_key")
+                            .col(ImportedDispense::RxId)
+                            .col(ImportedDispense::ImportKey)
+                            .unique(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ImportedDispense::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,218 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Record why a fill request was closed, and when and why it was cancelled.
+//!
+//! Existing closed requests are classified from their own dates and the other requests of the rx:
+//! - Picked up: completed.
+//! - Imported as a dispense without a pick-up date: completed, since it was dispensed.
+//! - Followed by a `RefillCancel` event before the next request was made: cancelled.
+//!   (The event backfill dates the `RefillCancel` of a superseded request on the day of the next one.)
+//! - Followed by another request: superseded.
+//! - Otherwise, the last request of the rx: cancelled.
+
+use std::collections::HashMap;
+
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{prelude::TimeDate, ConnectionTrait, QueryResult},
+};
+
+use crate::m20220101_000001_create_tables::FillRequest;
+use crate::m20230122_000001_generic_event::Events;
+use crate::m20230318_000001_imported_dispense::ImportedDispense;
+use crate::{CloseReason, EventType};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(Iden)]
+enum FillRequestClose {
+    CloseReason,
+    DateCancelled,
+    CancelReason,
+}
+
+/// Whether a request filled on `date_filled`, but not picked up, was imported
+/// as a dispense without a pick-up date, given the import keys of its rx.
+///
+/// Import keys end with the fill date and then the (empty) pick-up date.
+pub fn is_imported_fill(date_filled: TimeDate, import_keys: &[String]) -> bool {
+    let suffix = format!("|{}|", date_filled);
+    import_keys.iter().any(|key| key.ends_with(&suffix))
+}
+
+struct ExistingRow {
+    id: i32,
+    rx_id: i32,
+    date_requested: Option<TimeDate>,
+    date_filled: Option<TimeDate>,
+    date_picked_up: Option<TimeDate>,
+    closed: bool,
+}
+
+impl ExistingRow {
+    fn try_from_row(row: &QueryResult) -> Result<Self, DbErr> {
+        Ok(ExistingRow {
+            id: row.try_get("", &FillRequest::Id.to_string())?,
+            rx_id: row.try_get("", &FillRequest::RxId.to_string())?,
+            date_requested: row.try_get("", &FillRequest::DateRequested.to_string())?,
+            date_filled: row.try_get("", &FillRequest::DateFilled.to_string())?,
+            date_picked_up: row.try_get("", &FillRequest::DatePickedUp.to_string())?,
+            closed: row.try_get("", &FillRequest::Closed.to_string())?,
+        })
+    }
+
+    /// Why this row was closed, and when it was cancelled (if known),
+    /// given the next row for the same rx, the dates of the cancel events of the rx in order,
+    /// and the import keys of the rx.
+    fn close_reason(
+        &self,
+        next: Option<&ExistingRow>,
+        cancel_dates: &[TimeDate],
+        import_keys: &[String],
+    ) -> Option<(CloseReason, Option<TimeDate>)> {
+        if !self.closed {
+            return None;
+        }
+        if self.date_picked_up.is_some() {
+            return Some((CloseReason::Completed, None));
+        }
+        let imported = self.date_requested.is_none()
+            && self
+                .date_filled
+                .is_some_and(|date_filled| is_imported_fill(date_filled, import_keys));
+        if imported {
+            return Some((CloseReason::Completed, None));
+        }
+        let start = self.date_filled.or(self.date_requested);
+        let next_requested = next.and_then(|next| next.date_requested);
+        let cancel_date = cancel_dates.iter().copied().find(|&date| {
+            start.is_none_or(|start| date >= start)
+                && next_requested.is_none_or(|next_requested| date < next_requested)
+        });
+        match (cancel_date, next) {
+            (Some(date), _) => Some((CloseReason::Cancelled, Some(date))),
+            (None, Some(_)) => Some((CloseReason::Superseded, None)),
+            (None, None) => Some((CloseReason::Cancelled, None)),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        let backend = manager.get_database_backend();
+
+        // Sqlite can only add one column per statement.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(FillRequest::Table)
+                    .add_column(ColumnDef::new(FillRequestClose::CloseReason).integer())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(FillRequest::Table)
+                    .add_column(ColumnDef::new(FillRequestClose::DateCancelled).date())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(FillRequest::Table)
+                    .add_column(ColumnDef::new(FillRequestClose::CancelReason).string())
+                    .to_owned(),
+            )
+            .await?;
+
+        let select = Query::select()
+            .columns([Events::RxId, Events::Date])
+            .from(Events::Table)
+            .and_where(Expr::col(Events::Event).eq(EventType::RefillCancel))
+            .order_by(Events::Date, Order::Asc)
+            .to_owned();
+        let mut cancel_dates: HashMap<i32, Vec<TimeDate>> = HashMap::new();
+        for row in db.query_all(backend.build(&select)).await? {
+            let rx_id: i32 = row.try_get("", &Events::RxId.to_string())?;
+            let date: TimeDate = row.try_get("", &Events::Date.to_string())?;
+            cancel_dates.entry(rx_id).or_default().push(date);
+        }
+
+        let select = Query::select()
+            .columns([ImportedDispense::RxId, ImportedDispense::ImportKey])
+            .from(ImportedDispense::Table)
+            .to_owned();
+        let mut import_keys: HashMap<i32, Vec<String>> = HashMap::new();
+        for row in db.query_all(backend.build(&select)).await? {
+            let rx_id: i32 = row.try_get("", &ImportedDispense::RxId.to_string())?;
+            let key: String = row.try_get("", &ImportedDispense::ImportKey.to_string())?;
+            import_keys.entry(rx_id).or_default().push(key);
+        }
+
+        let select = Query::select()
+            .columns([
+                FillRequest::Id,
+                FillRequest::RxId,
+                FillRequest::DateRequested,
+                FillRequest::DateFilled,
+                FillRequest::DatePickedUp,
+                FillRequest::Closed,
+            ])
+            .from(FillRequest::Table)
+            .order_by(FillRequest::RxId, Order::Asc)
+            .order_by(FillRequest::Id, Order::Asc)
+            .to_owned();
+        let rows = db
+            .query_all(backend.build(&select))
+            .await?
+            .iter()
+            .map(ExistingRow::try_from_row)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (i, row) in rows.iter().enumerate() {
+            let next = rows.get(i + 1).filter(|next| next.rx_id == row.rx_id);
+            let dates = cancel_dates
+                .get(&row.rx_id)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let keys = import_keys
+                .get(&row.rx_id)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            if let Some((reason, date_cancelled)) = row.close_reason(next, dates, keys) {
+                let update = Query::update()
+                    .table(FillRequest::Table)
+                    .value(FillRequestClose::CloseReason, reason)
+                    .value(FillRequestClose::DateCancelled, date_cancelled)
+                    .and_where(Expr::col(FillRequest::Id).eq(row.id))
+                    .to_owned();
+                db.execute(backend.build(&update)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            FillRequestClose::CancelReason,
+            FillRequestClose::DateCancelled,
+            FillRequestClose::CloseReason,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(FillRequest::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,303 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Allow voiding events, and log every correction to an event or fill request step
+//! along with the original date.
+//! Existing events are not voided.
+//!
+//! Each event that records a fill request step is also linked to its fill request,
+//! so a correction finds the other half by ID rather than by its rx, type, and date.
+//! Existing events are linked by matching each step of each fill request, oldest first,
+//! to the oldest event of the same rx, type, and date that is not linked yet.
+//! Events that match no step, such as the cancel of a superseded request, stay unlinked.
+
+use std::collections::{HashMap, VecDeque};
+
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{prelude::TimeDate, ActiveEnum, ConnectionTrait, DbBackend},
+};
+
+use crate::m20220101_000001_create_tables::{FillRequest, RxInfo};
+use crate::m20230122_000001_generic_event::Events;
+use crate::EventType;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(Iden)]
+enum EventsCorrection {
+    Voided,
+    FillRequestId,
+}
+
+#[derive(Iden)]
+enum FillRequestCancelled {
+    DateCancelled,
+}
+
+#[derive(Iden)]
+pub enum EventCorrection {
+    Table,
+    Id,
+    RxId,
+    /// The corrected event, if any
+    EventId,
+    /// The corrected fill request, if any
+    FillRequestId,
+    /// The type of the event, or the fill request step
+    EventType,
+    OriginalDate,
+    /// The amended date, or `NULL` if voided
+    NewDate,
+    Reason,
+}
+
+/// What an event and a fill request step are matched on: the rx, the type of event, and the date.
+pub type StepKey = (i32, EventType, TimeDate);
+
+/// Link each fill request step to the oldest event with the same key that is not linked yet.
+///
+/// Takes the steps as (fill request ID, key) and the unvoided events as (event ID, key),
+/// each sorted by ID, and returns (event ID, fill request ID) for each link.
+/// Also used to restore backups made before this migration.
+pub fn link_steps(steps: &[(i32, StepKey)], events: &[(i32, StepKey)]) -> Vec<(i32, i32)> {
+    let mut unlinked: HashMap<StepKey, VecDeque<i32>> = HashMap::new();
+    for &(event_id, key) in events {
+        unlinked.entry(key).or_default().push_back(event_id);
+    }
+    steps
+        .iter()
+        .filter_map(|&(fill_request_id, key)| {
+            let event_id = unlinked.get_mut(&key)?.pop_front()?;
+            Some((event_id, fill_request_id))
+        })
+        .collect()
+}
+
+/// Link the existing events to the fill request steps they record.
+async fn link_existing_events(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+    let backend = manager.get_database_backend();
+    let select = Query::select()
+        .columns([
+            FillRequest::Id,
+            FillRequest::RxId,
+            FillRequest::DateRequested,
+            FillRequest::DateFilled,
+            FillRequest::DatePickedUp,
+        ])
+        .column(FillRequestCancelled::DateCancelled)
+        .from(FillRequest::Table)
+        .order_by(FillRequest::Id, Order::Asc)
+        .to_owned();
+    let mut steps = Vec::new();
+    for row in db.query_all(backend.build(&select)).await? {
+        let id: i32 = row.try_get("", &FillRequest::Id.to_string())?;
+        let rx_id: i32 = row.try_get("", &FillRequest::RxId.to_string())?;
+        for (event_type, column) in [
+            (
+                EventType::RequestFill,
+                FillRequest::DateRequested.to_string(),
+            ),
+            (EventType::Fill, FillRequest::DateFilled.to_string()),
+            (EventType::PickUp, FillRequest::DatePickedUp.to_string()),
+            (
+                EventType::RefillCancel,
+                FillRequestCancelled::DateCancelled.to_string(),
+            ),
+        ] {
+            let date: Option<TimeDate> = row.try_get("", &column)?;
+            if let Some(date) = date {
+                steps.push((id, (rx_id, event_type, date)));
+            }
+        }
+    }
+
+    let select = Query::select()
+        .columns([Events::Id, Events::RxId, Events::Event, Events::Date])
+        .from(Events::Table)
+        .order_by(Events::Id, Order::Asc)
+        .to_owned();
+    let mut events = Vec::new();
+    for row in db.query_all(backend.build(&select)).await? {
+        let event_type: i32 = row.try_get("", &Events::Event.to_string())?;
+        let event_type = EventType::try_from_value(&event_type)?;
+        events.push((
+            row.try_get("", &Events::Id.to_string())?,
+            (
+                row.try_get("", &Events::RxId.to_string())?,
+                event_type,
+                row.try_get("", &Events::Date.to_string())?,
+            ),
+        ));
+    }
+
+    for (event_id, fill_request_id) in link_steps(&steps, &events) {
+        let update = Query::update()
+            .table(Events::Table)
+            .value(EventsCorrection::FillRequestId, fill_request_id)
+            .and_where(Expr::col(Events::Id).eq(event_id))
+            .to_owned();
+        db.execute(backend.build(&update)).await?;
+    }
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Events::Table)
+                    .add_column(
+                        ColumnDef::new(EventsCorrection::Voided)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        let mut fill_request_id = ColumnDef::new(EventsCorrection::FillRequestId);
+        fill_request_id.integer();
+        if manager.get_database_backend() == DbBackend::Sqlite {
+            // Sqlite cannot add a foreign key to an existing table, but can add a column with a reference.
+            fill_request_id.extra(format!(
+                "REFERENCES \"{}\" (\"{}\")",
+                FillRequest::Table.to_string(),
+                FillRequest::Id.to_string()
+            ));
+        }
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Events::Table)
+                    .add_column(&mut fill_request_id)
+                    .to_owned(),
+            )
+            .await?;
+        if manager.get_database_backend() != DbBackend::Sqlite {
+            manager
+                .create_foreign_key(
+                    ForeignKey::create()
+                        .name("fk-events-fill_request_id")
+                        .from(Events::Table, EventsCorrection::FillRequestId)
+                        .to(FillRequest::Table, FillRequest::Id)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        link_existing_events(manager).await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(EventCorrection::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(EventCorrection::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(EventCorrection::RxId).integer().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-event_correction-rx_id")
+                            .from(EventCorrection::Table, EventCorrection::RxId)
+                            .to(RxInfo::Table, RxInfo::RxId),
+                    )
+                    .col(ColumnDef::new(EventCorrection::EventId).integer())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-event_correction-event_id")
+                            .from(EventCorrection::Table, EventCorrection::EventId)
+                            .to(Events::Table, Events::Id),
+                    )
+                    .col(ColumnDef::new(EventCorrection::FillRequestId).integer())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-event_correction-fill_request_id")
+                            .from(EventCorrection::Table, EventCorrection::FillRequestId)
+                            .to(FillRequest::Table, FillRequest::Id),
+                    )
+                    // Stored as an integer, like the events table
+                    .col(
+                        ColumnDef::new(EventCorrection::EventType)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(EventCorrection::OriginalDate)
+                            .date()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(EventCorrection::NewDate).date())
+                    .col(ColumnDef::new(EventCorrection::Reason).string().not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(EventCorrection::Table).to_owned())
+            .await?;
+        if manager.get_database_backend() != DbBackend::Sqlite {
+            manager
+                .drop_foreign_key(
+                    ForeignKey::drop()
+                        .name("fk-events-fill_request_id")
+                        .table(Events::Table)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        for column in [EventsCorrection::FillRequestId, EventsCorrection::Voided] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Events::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_link_steps() {
+        let day = |n: u16| TimeDate::from_ordinal_date(2023, n + 1).unwrap();
+        // Two requests on the same day, the first cancelled that day, the second picked up
+        let steps = [
+            (1, (1, EventType::RequestFill, day(0))),
+            (1, (1, EventType::RefillCancel, day(0))),
+            (2, (1, EventType::RequestFill, day(0))),
+            (2, (1, EventType::PickUp, day(3))),
+        ];
+        let events = [
+            (10, (1, EventType::RequestFill, day(0))),
+            (11, (1, EventType::RefillCancel, day(0))),
+            (12, (1, EventType::RequestFill, day(0))),
+            // Recorded directly, matching no request
+            (13, (2, EventType::PickUp, day(3))),
+            (14, (1, EventType::PickUp, day(3))),
+        ];
+        assert_eq!(
+            link_steps(&steps, &events),
+            vec![(10, 1), (11, 1), (12, 2), (14, 2)]
+        );
+        // A step without an event is left out
+        assert_eq!(link_steps(&steps, &events[..2]), vec![(10, 1), (11, 1)]);
+    }
+}
@@ -0,0 +1,127 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Add pharmacies, a default pharmacy for each rx,
+//! and the pharmacy of each fill request and event.
+//! Existing prescriptions, fill requests, and events are left without a pharmacy.
+
+use sea_orm_migration::{prelude::*, sea_orm::DbBackend};
+
+use crate::m20220101_000001_create_tables::{FillRequest, RxInfo};
+use crate::m20230122_000001_generic_event::Events;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(Iden)]
+#[allow(clippy::enum_variant_names)]
+pub enum Pharmacy {
+    Table,
+    PharmacyId,
+    Name,
+    Phone,
+    Address,
+    /// Opening hours, as free text
+    Hours,
+}
+
+#[derive(Iden)]
+enum WithPharmacy {
+    PharmacyId,
+}
+
+/// The tables that get a pharmacy, with the name of the foreign key for other backends.
+fn referencing_tables() -> [(DynIden, &'static str); 3] {
+    [
+        (RxInfo::Table.into_iden(), "fk-rx-pharmacy_id"),
+        (
+            FillRequest::Table.into_iden(),
+            "fk-fill_request-pharmacy_id",
+        ),
+        (Events::Table.into_iden(), "fk-events-pharmacy_id"),
+    ]
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Pharmacy::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Pharmacy::PharmacyId)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Pharmacy::Name).string().not_null())
+                    .col(ColumnDef::new(Pharmacy::Phone).string())
+                    .col(ColumnDef::new(Pharmacy::Address).string())
+                    .col(ColumnDef::new(Pharmacy::Hours).string())
+                    .to_owned(),
+            )
+            .await?;
+
+        for (table, fk_name) in referencing_tables() {
+            let mut pharmacy_id = ColumnDef::new(WithPharmacy::PharmacyId);
+            pharmacy_id.integer();
+            if manager.get_database_backend() == DbBackend::Sqlite {
+                // Sqlite cannot add a foreign key to an existing table, but can add a column with a reference.
+                pharmacy_id.extra(format!(
+                    "REFERENCES \"{}\" (\"{}\")",
+                    Pharmacy::Table.to_string(),
+                    Pharmacy::PharmacyId.to_string()
+                ));
+            }
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(table.clone())
+                        .add_column(&mut pharmacy_id)
+                        .to_owned(),
+                )
+                .await?;
+            if manager.get_database_backend() != DbBackend::Sqlite {
+                manager
+                    .create_foreign_key(
+                        ForeignKey::create()
+                            .name(fk_name)
+                            .from(table, WithPharmacy::PharmacyId)
+                            .to(Pharmacy::Table, Pharmacy::PharmacyId)
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for (table, fk_name) in referencing_tables() {
+            if manager.get_database_backend() != DbBackend::Sqlite {
+                manager
+                    .drop_foreign_key(
+                        ForeignKey::drop()
+                            .name(fk_name)
+                            .table(table.clone())
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(table)
+                        .drop_column(WithPharmacy::PharmacyId)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .drop_table(Table::drop().table(Pharmacy::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Add prescribers, and the current prescriber of each rx.
+//! Existing prescriptions are left without a prescriber.
+
+use sea_orm_migration::{prelude::*, sea_orm::DbBackend};
+
+use crate::m20220101_000001_create_tables::RxInfo;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(Iden)]
+#[allow(clippy::enum_variant_names)]
+pub enum Prescriber {
+    Table,
+    PrescriberId,
+    Name,
+    /// Name of the practice or clinic
+    Practice,
+    Phone,
+    Fax,
+}
+
+#[derive(Iden)]
+enum RxPrescriber {
+    PrescriberId,
+}
+
+const FOREIGN_KEY_NAME: &str = "fk-rx-prescriber_id";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Prescriber::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Prescriber::PrescriberId)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Prescriber::Name).string().not_null())
+                    .col(ColumnDef::new(Prescriber::Practice).string())
+                    .col(ColumnDef::new(Prescriber::Phone).string())
+                    .col(ColumnDef::new(Prescriber::Fax).string())
+                    .to_owned(),
+            )
+            .await?;
+
+        let mut prescriber_id = ColumnDef::new(RxPrescriber::PrescriberId);
+        prescriber_id.integer();
+        if manager.get_database_backend() == DbBackend::Sqlite {
+            // Sqlite cannot add a foreign key to an existing table, but can add a column with a reference.
+            prescriber_id.extra(format!(
+                "REFERENCES \"{}\" (\"{}\")",
+                Prescriber::Table.to_string(),
+                Prescriber::PrescriberId.to_string()
+            ));
+        }
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RxInfo::Table)
+                    .add_column(&mut prescriber_id)
+                    .to_owned(),
+            )
+            .await?;
+        if manager.get_database_backend() != DbBackend::Sqlite {
+            manager
+                .create_foreign_key(
+                    ForeignKey::create()
+                        .name(FOREIGN_KEY_NAME)
+                        .from(RxInfo::Table, RxPrescriber::PrescriberId)
+                        .to(Prescriber::Table, Prescriber::PrescriberId)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.get_database_backend() != DbBackend::Sqlite {
+            manager
+                .drop_foreign_key(
+                    ForeignKey::drop()
+                        .name(FOREIGN_KEY_NAME)
+                        .table(RxInfo::Table)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RxInfo::Table)
+                    .drop_column(RxPrescriber::PrescriberId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Prescriber::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,89 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Record how many refills of an rx remain authorized, when its script expires,
+//! and the first date whose pick-ups count against the script.
+//! All are left unknown for existing prescriptions.
+//!
+//! Also record what the pick-up of each fill request used up of the script,
+//! so correcting the pick-up can give it back.
+//! Existing fill requests used nothing, since no script was recorded before.
+
+use sea_orm_migration::prelude::*;
+
+use crate::m20220101_000001_create_tables::{FillRequest, RxInfo};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(Iden)]
+enum RxInfoAuthorization {
+    RefillsRemaining,
+    ScriptExpires,
+    RefillsCountedFrom,
+}
+
+#[derive(Iden)]
+enum FillRequestRefill {
+    RefillUse,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Sqlite can only add one column per statement.
+        for column in [
+            ColumnDef::new(RxInfoAuthorization::RefillsRemaining).integer(),
+            ColumnDef::new(RxInfoAuthorization::ScriptExpires).date(),
+            ColumnDef::new(RxInfoAuthorization::RefillsCountedFrom).date(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(RxInfo::Table)
+                        .add_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(FillRequest::Table)
+                    .add_column(
+                        ColumnDef::new(FillRequestRefill::RefillUse)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(FillRequest::Table)
+                    .drop_column(FillRequestRefill::RefillUse)
+                    .to_owned(),
+            )
+            .await?;
+        for column in [
+            RxInfoAuthorization::RefillsCountedFrom,
+            RxInfoAuthorization::ScriptExpires,
+            RxInfoAuthorization::RefillsRemaining,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(RxInfo::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Track requests to a prescriber to renew the script of an rx.
+//!
+//! Also record whether the original fill of a newly approved script is still to be picked up,
+//! since it does not use one of the refills of the script.
+//! Existing prescriptions have no original fill pending.
+
+use sea_orm_migration::prelude::*;
+
+use crate::m20220101_000001_create_tables::RxInfo;
+use crate::m20230415_000001_prescriber::Prescriber;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(Iden)]
+pub enum RenewalRequest {
+    Table,
+    Id,
+    RxId,
+    /// The prescriber asked, if known
+    PrescriberId,
+    /// Where the request is in its lifecycle
+    Status,
+    DateRequested,
+    DateApproved,
+    DateDenied,
+    DenyReason,
+    /// The date the new script arrived at the pharmacy
+    DateReceived,
+    /// When to follow up if nothing has happened, or `NULL` once the request is closed
+    FollowUpDate,
+    /// Refills authorized by the new script
+    Refills,
+    /// Expiry of the new script
+    Expires,
+}
+
+#[derive(Iden)]
+enum RxInfoOriginalFill {
+    OriginalFillPending,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(RenewalRequest::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RenewalRequest::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(RenewalRequest::RxId).integer().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-renewal_request-rx_id")
+                            .from(RenewalRequest::Table, RenewalRequest::RxId)
+                            .to(RxInfo::Table, RxInfo::RxId),
+                    )
+                    .col(ColumnDef::new(RenewalRequest::PrescriberId).integer())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-renewal_request-prescriber_id")
+                            .from(RenewalRequest::Table, RenewalRequest::PrescriberId)
+                            .to(Prescriber::Table, Prescriber::PrescriberId),
+                    )
+                    // Stored as an integer, like the events table
+                    .col(ColumnDef::new(RenewalRequest::Status).integer().not_null())
+                    .col(
+                        ColumnDef::new(RenewalRequest::DateRequested)
+                            .date()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(RenewalRequest::DateApproved).date())
+                    .col(ColumnDef::new(RenewalRequest::DateDenied).date())
+                    .col(ColumnDef::new(RenewalRequest::DenyReason).string())
+                    .col(ColumnDef::new(RenewalRequest::DateReceived).date())
+                    .col(ColumnDef::new(RenewalRequest::FollowUpDate).date())
+                    .col(ColumnDef::new(RenewalRequest::Refills).integer())
+                    .col(ColumnDef::new(RenewalRequest::Expires).date())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RxInfo::Table)
+                    .add_column(
+                        ColumnDef::new(RxInfoOriginalFill::OriginalFillPending)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RxInfo::Table)
+                    .drop_column(RxInfoOriginalFill::OriginalFillPending)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(RenewalRequest::Table).to_owned())
+            .await
+    }
+}
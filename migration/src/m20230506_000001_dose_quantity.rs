@@ -0,0 +1,72 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Record the dose rate of an rx, as units used every so many days,
+//! and the quantity dispensed at each pick-up.
+//! Both are left unknown for existing data.
+
+use sea_orm_migration::prelude::*;
+
+use crate::m20220101_000001_create_tables::RxInfo;
+use crate::m20230122_000001_generic_event::Events;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(Iden)]
+enum RxInfoDose {
+    DoseUnits,
+    DoseEveryDays,
+}
+
+#[derive(Iden)]
+enum EventsQuantity {
+    Quantity,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Sqlite can only add one column per statement.
+        for column in [RxInfoDose::DoseUnits, RxInfoDose::DoseEveryDays] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(RxInfo::Table)
+                        .add_column(ColumnDef::new(column).integer())
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Events::Table)
+                    .add_column(ColumnDef::new(EventsQuantity::Quantity).integer())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Events::Table)
+                    .drop_column(EventsQuantity::Quantity)
+                    .to_owned(),
+            )
+            .await?;
+        for column in [RxInfoDose::DoseEveryDays, RxInfoDose::DoseUnits] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(RxInfo::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
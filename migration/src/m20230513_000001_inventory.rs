@@ -0,0 +1,83 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Record manual counts of the units of an rx on hand,
+//! and mark prescriptions as critical with a minimum number of days on hand.
+
+use sea_orm_migration::prelude::*;
+
+use crate::m20220101_000001_create_tables::RxInfo;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(Iden)]
+enum RxInfoCritical {
+    /// Alert when fewer days than this are on hand, or `NULL` if not critical
+    CriticalDays,
+}
+
+#[derive(Iden)]
+pub enum PillCount {
+    Table,
+    Id,
+    RxId,
+    Date,
+    /// Units counted on hand
+    Counted,
+    /// Units expected on hand at the time, if known
+    Expected,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RxInfo::Table)
+                    .add_column(ColumnDef::new(RxInfoCritical::CriticalDays).integer())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(PillCount::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PillCount::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(PillCount::RxId).integer().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-pill_count-rx_id")
+                            .from(PillCount::Table, PillCount::RxId)
+                            .to(RxInfo::Table, RxInfo::RxId),
+                    )
+                    .col(ColumnDef::new(PillCount::Date).date().not_null())
+                    .col(ColumnDef::new(PillCount::Counted).integer().not_null())
+                    .col(ColumnDef::new(PillCount::Expected).integer())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PillCount::Table).to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RxInfo::Table)
+                    .drop_column(RxInfoCritical::CriticalDays)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,378 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! How many refills of a prescription remain authorized, and when its script expires.
+
+use std::fmt::Display;
+
+use sea_orm::{
+    prelude::TimeDate, ActiveModelTrait, ActiveValue::Set, ConnectionTrait, EntityTrait,
+};
+
+use crate::{entities::rx_info, Error, RefillUse, RxId};
+
+/// What the current script of an rx still allows. The refills and expiry may be unknown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Authorization {
+    /// Refills left before the script has to be renewed
+    pub refills_remaining: Option<u32>,
+    /// The last date the script can be filled on
+    pub expires: Option<TimeDate>,
+    /// Whether the original fill of a new script is still to be picked up,
+    /// which does not use up one of its refills
+    pub original_fill_pending: bool,
+}
+
+/// Why the next refill of an rx cannot happen without renewing its script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthorizationProblem {
+    /// No refills remain
+    NoRefillsLeft,
+    /// The script expires (or expired) on this date, before the refill is due
+    Expired(TimeDate),
+}
+
+impl Display for AuthorizationProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthorizationProblem::NoRefillsLeft => write!(f, "no refills left"),
+            AuthorizationProblem::Expired(date) => write!(f, "script expires {}", date),
+        }
+    }
+}
+
+impl Authorization {
+    /// Check whether a fill on the given date is still authorized.
+    ///
+    /// Unknown refills or expiry are assumed to be fine,
+    /// and no refills are needed while the original fill is pending.
+    pub fn problem_on(&self, date: TimeDate) -> Option<AuthorizationProblem> {
+        match self.expires {
+            Some(expires) if expires < date => Some(AuthorizationProblem::Expired(expires)),
+            _ if !self.original_fill_pending && self.refills_remaining == Some(0) => {
+                Some(AuthorizationProblem::NoRefillsLeft)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<&rx_info::Model> for Authorization {
+    fn from(value: &rx_info::Model) -> Self {
+        Authorization {
+            refills_remaining: value.refills_remaining.map(|refills| refills.max(0) as u32),
+            expires: value.script_expires,
+            original_fill_pending: value.original_fill_pending,
+        }
+    }
+}
+
+/// Record the refills and expiry of the current script of an rx, as of the given date,
+/// e.g. from the label of the latest pick-up or when the script is renewed.
+///
+/// The refills are taken to be what remains after any pick-up on that date,
+/// so only pick-ups after it count against them.
+/// If the original fill is pending, though, a pick-up on that date can be the original fill,
+/// so pick-ups from that date on count.
+pub async fn set_authorization(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    authorization: &Authorization,
+    date: TimeDate,
+) -> Result<(), Error> {
+    let existing = rx_info::Entity::find_by_id(i32::from(rx))
+        .one(db)
+        .await?
+        .ok_or(Error::NoSuchRx(rx))?;
+    let counted_from = if authorization.original_fill_pending {
+        date
+    } else {
+        date.next_day().unwrap_or(date)
+    };
+    let mut model: rx_info::ActiveModel = existing.into();
+    model.refills_remaining = Set(authorization
+        .refills_remaining
+        .map(|refills| refills.try_into().unwrap_or(i32::MAX)));
+    model.script_expires = Set(authorization.expires);
+    model.refills_counted_from = Set(Some(counted_from));
+    model.original_fill_pending = Set(authorization.original_fill_pending);
+    model.update(db).await?;
+    Ok(())
+}
+
+/// Whether a pick-up on this date counts against the current script of an rx,
+/// rather than an earlier one: it is on or after the first date the script counts from.
+fn counts_against_script(rx: &rx_info::Model, pickup_date: TimeDate) -> bool {
+    rx.refills_counted_from
+        .is_none_or(|counted_from| counted_from <= pickup_date)
+}
+
+/// Count a pick-up on this date against the script of an rx, if it counts against the script:
+/// as the original fill if that is pending, otherwise as a refill if refills are known to remain.
+pub(crate) async fn use_refill(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    pickup_date: TimeDate,
+) -> Result<RefillUse, Error> {
+    let existing = rx_info::Entity::find_by_id(i32::from(rx))
+        .one(db)
+        .await?
+        .ok_or(Error::NoSuchRx(rx))?;
+    if !counts_against_script(&existing, pickup_date) {
+        return Ok(RefillUse::Nothing);
+    }
+    let original_fill_pending = existing.original_fill_pending;
+    let refills = existing.refills_remaining.filter(|&refills| refills > 0);
+    let mut model: rx_info::ActiveModel = existing.into();
+    let used = if original_fill_pending {
+        model.original_fill_pending = Set(false);
+        RefillUse::OriginalFill
+    } else if let Some(refills) = refills {
+        model.refills_remaining = Set(Some(refills - 1));
+        RefillUse::Refill
+    } else {
+        return Ok(RefillUse::Nothing);
+    };
+    model.update(db).await?;
+    Ok(used)
+}
+
+/// Give back what a pick-up on this date used up,
+/// unless the script of the rx was authorized again since.
+async fn return_refill(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    pickup_date: TimeDate,
+    used: RefillUse,
+) -> Result<(), Error> {
+    let existing = rx_info::Entity::find_by_id(i32::from(rx))
+        .one(db)
+        .await?
+        .ok_or(Error::NoSuchRx(rx))?;
+    if !counts_against_script(&existing, pickup_date) {
+        return Ok(());
+    }
+    let refills = existing.refills_remaining;
+    let mut model: rx_info::ActiveModel = existing.into();
+    match (used, refills) {
+        (RefillUse::OriginalFill, _) => model.original_fill_pending = Set(true),
+        (RefillUse::Refill, Some(refills)) => {
+            model.refills_remaining = Set(Some(refills.saturating_add(1)))
+        }
+        _ => return Ok(()),
+    }
+    model.update(db).await?;
+    Ok(())
+}
+
+/// Check again what a pick-up uses up of the script of an rx, when its date is corrected
+/// from `old_date` to `new_date`, or it is voided if `None`.
+///
+/// What it used on the old date is given back, then it is counted again on the new date,
+/// so moving it across the date the script counts from uses up a refill or gives one back.
+/// Imported pick-ups never use anything.
+pub(crate) async fn recheck_refill(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    used: RefillUse,
+    old_date: TimeDate,
+    new_date: Option<TimeDate>,
+) -> Result<RefillUse, Error> {
+    if used == RefillUse::Imported {
+        return Ok(used);
+    }
+    return_refill(db, rx, old_date, used).await?;
+    match new_date {
+        Some(date) => use_refill(db, rx, date).await,
+        None => Ok(RefillUse::Nothing),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::Database;
+    use time::{Date, Duration, Month};
+
+    use super::*;
+    use crate::{
+        corrections::{amend_fill_request_step, void_event, void_fill_request_step},
+        events::list_events,
+        fill_request::{list_fill_requests, record_fill_request, record_pickup},
+        import::{import_dispenses, Dispense},
+        rx::{add_rx, get_rx},
+        EventType, FillRequestId,
+    };
+
+    #[test]
+    fn test_problem_on() {
+        let date = Date::from_calendar_date(2023, Month::April, 22).unwrap();
+        assert_eq!(Authorization::default().problem_on(date), None);
+        let authorization = Authorization {
+            refills_remaining: Some(1),
+            expires: Some(date),
+            original_fill_pending: false,
+        };
+        // Still valid on the day it expires
+        assert_eq!(authorization.problem_on(date), None);
+        assert_eq!(
+            authorization.problem_on(date.next_day().unwrap()),
+            Some(AuthorizationProblem::Expired(date))
+        );
+        let authorization = Authorization {
+            refills_remaining: Some(0),
+            ..authorization
+        };
+        assert_eq!(
+            authorization.problem_on(date),
+            Some(AuthorizationProblem::NoRefillsLeft)
+        );
+        // The original fill of a new script does not need a refill
+        let authorization = Authorization {
+            original_fill_pending: true,
+            ..authorization
+        };
+        assert_eq!(authorization.problem_on(date), None);
+    }
+
+    async fn refills(db: &impl ConnectionTrait, rx: RxId) -> Result<Option<u32>, Error> {
+        Ok(get_rx(db, rx)
+            .await?
+            .ok_or(Error::NoSuchRx(rx))?
+            .authorization
+            .refills_remaining)
+    }
+
+    #[async_std::test]
+    async fn test_use_refill() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::April, 22).unwrap();
+        let day = |n| date + Duration::days(n);
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+
+        // Unknown refills stay unknown
+        record_pickup(&db, amox_id, Some(date), date).await?;
+        assert_eq!(
+            get_rx(&db, amox_id).await?.unwrap().authorization,
+            Authorization::default()
+        );
+
+        let expires = date + Duration::days(365);
+        set_authorization(
+            &db,
+            amox_id,
+            &Authorization {
+                refills_remaining: Some(1),
+                expires: Some(expires),
+                original_fill_pending: false,
+            },
+            date,
+        )
+        .await?;
+        // Only pick-ups count
+        record_fill_request(&db, amox_id, day(1)).await?;
+        record_pickup(&db, amox_id, Some(day(1)), day(1)).await?;
+        let authorization = get_rx(&db, amox_id).await?.unwrap().authorization;
+        assert_eq!(authorization.refills_remaining, Some(0));
+        assert_eq!(authorization.expires, Some(expires));
+        // Never goes negative
+        record_pickup(&db, amox_id, Some(day(2)), day(2)).await?;
+        assert_eq!(refills(&db, amox_id).await?, Some(0));
+        assert_eq!(
+            set_authorization(&db, RxId::from(42), &authorization, date).await,
+            Err(Error::NoSuchRx(RxId::from(42)))
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_refills_after_authorized() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::April, 22).unwrap();
+        let day = |n| date + Duration::days(n);
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        let authorization = Authorization {
+            refills_remaining: Some(3),
+            expires: None,
+            original_fill_pending: false,
+        };
+
+        // The refills are as of the end of the day they were recorded,
+        // so a pick-up that day does not count, whether recorded before or after.
+        record_pickup(&db, amox_id, Some(date), date).await?;
+        set_authorization(&db, amox_id, &authorization, date).await?;
+        record_pickup(&db, amox_id, Some(date), date).await?;
+        // Nor one logged late, from before
+        record_pickup(&db, amox_id, Some(day(-5)), day(-5)).await?;
+        assert_eq!(refills(&db, amox_id).await?, Some(3));
+        // Nor imported history
+        let dispense = Dispense {
+            rx_name: "amoxicillin".to_owned(),
+            rx_number: None,
+            date_filled: Some(day(20)),
+            date_picked_up: Some(day(20)),
+        };
+        import_dispenses(&db, None, vec![dispense]).await?;
+        assert_eq!(refills(&db, amox_id).await?, Some(3));
+        // But the next day's does
+        record_pickup(&db, amox_id, Some(day(1)), day(1)).await?;
+        assert_eq!(refills(&db, amox_id).await?, Some(2));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_corrected_pickup() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::April, 22).unwrap();
+        let day = |n| date + Duration::days(n);
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        let authorization = Authorization {
+            refills_remaining: Some(3),
+            expires: None,
+            original_fill_pending: false,
+        };
+        set_authorization(&db, amox_id, &authorization, day(10)).await?;
+        let dispense = Dispense {
+            rx_name: "amoxicillin".to_owned(),
+            rx_number: None,
+            date_filled: Some(day(2)),
+            date_picked_up: Some(day(2)),
+        };
+        import_dispenses(&db, None, vec![dispense]).await?;
+        let imported = FillRequestId::from(list_fill_requests(&db, amox_id).await?[0].id);
+
+        // Logged before the script was authorized by mistake, then moved after it
+        let pickup = record_pickup(&db, amox_id, Some(day(5)), day(5)).await?;
+        assert_eq!(refills(&db, amox_id).await?, Some(3));
+        amend_fill_request_step(&db, pickup, EventType::PickUp, day(12), "").await?;
+        assert_eq!(refills(&db, amox_id).await?, Some(2));
+        // Moving it within the script keeps the one refill
+        amend_fill_request_step(&db, pickup, EventType::PickUp, day(14), "").await?;
+        assert_eq!(refills(&db, amox_id).await?, Some(2));
+        // Moving it back before, or onto the day the script was authorized, gives it back
+        amend_fill_request_step(&db, pickup, EventType::PickUp, day(10), "").await?;
+        assert_eq!(refills(&db, amox_id).await?, Some(3));
+        // Voiding it after moving it across uses nothing
+        amend_fill_request_step(&db, pickup, EventType::PickUp, day(11), "").await?;
+        assert_eq!(refills(&db, amox_id).await?, Some(2));
+        let event = list_events(&db, amox_id, day(11)..=day(11)).await?[0].id;
+        void_event(&db, event, "wrong prescription").await?;
+        assert_eq!(refills(&db, amox_id).await?, Some(3));
+
+        // Imported history never counts, wherever it is moved
+        amend_fill_request_step(&db, imported, EventType::PickUp, day(12), "").await?;
+        assert_eq!(refills(&db, amox_id).await?, Some(3));
+
+        // A refill of a script since replaced is not given back to the new one
+        let replaced = record_pickup(&db, amox_id, Some(day(15)), day(15)).await?;
+        assert_eq!(refills(&db, amox_id).await?, Some(2));
+        set_authorization(&db, amox_id, &authorization, day(20)).await?;
+        void_fill_request_step(&db, replaced, EventType::PickUp, "").await?;
+        assert_eq!(refills(&db, amox_id).await?, Some(3));
+        Ok(())
+    }
+}
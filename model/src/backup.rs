@@ -0,0 +1,1064 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Export the whole database as a versioned JSON document, and restore it into an empty database.
+
+use std::collections::HashMap;
+
+use migration::{
+    backfill_events, is_imported_fill, link_steps, Iden, LegacyFillRequest, Migrator, MigratorTrait,
+};
+use sea_orm::{
+    prelude::TimeDate, ActiveValue::Set, ConnectionTrait, EntityTrait, Iterable, QueryOrder,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entities::{
+        event_correction, events, fill_request, imported_dispense, person, pharmacy, pill_count,
+        prescriber, reminder_policy, renewal_request, rx_days_supply, rx_info,
+    },
+    CloseReason, Error, EventType, RefillUse, RenewalStatus,
+};
+
+/// The schema version of this build: the number of migrations it knows about.
+///
+/// Backups record the schema version they were made with,
+/// and only backups from the same or an older version can be restored.
+pub fn schema_version() -> u32 {
+    Migrator::migrations().len() as u32
+}
+
+/// The schema version that first included the named migration.
+/// Backups with an older version were made before it ran.
+fn migration_version(name: &str) -> u32 {
+    Migrator::steps_before(name).expect("migration is known") + 1
+}
+
+/// The events for the fill requests of a backup from before events were recorded,
+/// converted and linked to their fill requests the same way as when migrating.
+fn backfill_backup_events(rows: &[FillRequestRow]) -> Vec<events::ActiveModel> {
+    let mut rows: Vec<&FillRequestRow> = rows.iter().collect();
+    rows.sort_by_key(|row| (row.rx_id, row.id));
+    let legacy: Vec<LegacyFillRequest> = rows
+        .iter()
+        .map(|row| LegacyFillRequest {
+            rx_id: row.rx_id,
+            date_requested: row.date_requested,
+            date_filled: row.date_filled,
+            date_picked_up: row.date_picked_up,
+            closed: row.closed,
+        })
+        .collect();
+    backfill_events(&legacy)
+        .into_iter()
+        .map(|(i, event, date)| events::ActiveModel {
+            rx_id: Set(rows[i].rx_id),
+            event: Set(event),
+            date: Set(date),
+            // Such backups have no cancel dates, so a cancel matches no step of the request
+            fill_request_id: Set((event != EventType::RefillCancel).then_some(rows[i].id)),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// The fill request of each event in a backup from before events were linked to them,
+/// by event ID, linked the same way as when migrating.
+fn link_backup_events(
+    fill_requests: &[FillRequestRow],
+    events: &[EventRow],
+) -> Result<HashMap<i32, i32>, Error> {
+    let mut fill_requests: Vec<&FillRequestRow> = fill_requests.iter().collect();
+    fill_requests.sort_by_key(|row| row.id);
+    let steps: Vec<_> = fill_requests
+        .iter()
+        .flat_map(|row| {
+            [
+                (EventType::RequestFill, row.date_requested),
+                (EventType::Fill, row.date_filled),
+                (EventType::PickUp, row.date_picked_up),
+                (EventType::RefillCancel, row.date_cancelled),
+            ]
+            .into_iter()
+            .filter_map(|(event, date)| Some((row.id, (row.rx_id, event, date?))))
+        })
+        .collect();
+    let mut events: Vec<&EventRow> = events.iter().filter(|row| !row.voided).collect();
+    events.sort_by_key(|row| row.id);
+    let events = events
+        .into_iter()
+        .map(|row| Ok((row.id, (row.rx_id, parse_event_type(&row.event)?, row.date))))
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(link_steps(&steps, &events).into_iter().collect())
+}
+
+/// The name of an enum value, e.g. `pick_up`.
+fn enum_name(value: impl Iden) -> String {
+    Iden::to_string(&value)
+}
+
+fn parse_enum<T: Iden + Iterable>(kind: &str, name: &str) -> Result<T, Error> {
+    T::iter()
+        .find(|value| Iden::to_string(value) == name)
+        .ok_or_else(|| Error::InvalidBackup(format!("unknown {} \"{}\"", kind, name)))
+}
+
+fn parse_event_type(name: &str) -> Result<EventType, Error> {
+    parse_enum("event type", name)
+}
+
+/// Why a closed request in a backup from before close reasons were recorded was closed,
+/// guessed the same way as when migrating: picked up or imported as dispensed,
+/// followed by another request, or cancelled.
+fn infer_close_reason(
+    row: &FillRequestRow,
+    rows: &[FillRequestRow],
+    imported: &[ImportedDispenseRow],
+) -> CloseReason {
+    let import_keys: Vec<String> = imported
+        .iter()
+        .filter(|imported| imported.rx_id == row.rx_id)
+        .map(|imported| imported.import_key.clone())
+        .collect();
+    let imported_fill = row.date_requested.is_none()
+        && row
+            .date_filled
+            .is_some_and(|date_filled| is_imported_fill(date_filled, &import_keys));
+    if row.date_picked_up.is_some() || imported_fill {
+        CloseReason::Completed
+    } else if rows
+        .iter()
+        .any(|other| other.rx_id == row.rx_id && other.id > row.id)
+    {
+        CloseReason::Superseded
+    } else {
+        CloseReason::Cancelled
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersonRow {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PharmacyRow {
+    pub id: i32,
+    pub name: String,
+    #[serde(default)]
+    pub phone: Option<String>,
+    #[serde(default)]
+    pub address: Option<String>,
+    #[serde(default)]
+    pub hours: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrescriberRow {
+    pub id: i32,
+    pub name: String,
+    #[serde(default)]
+    pub practice: Option<String>,
+    #[serde(default)]
+    pub phone: Option<String>,
+    #[serde(default)]
+    pub fax: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RxRow {
+    pub id: i32,
+    pub name: String,
+    pub hidden: bool,
+    #[serde(default)]
+    pub person_id: Option<i32>,
+    #[serde(default)]
+    pub retired_date: Option<TimeDate>,
+    #[serde(default)]
+    pub retired_reason: Option<String>,
+    #[serde(default)]
+    pub normalized_name: Option<String>,
+    #[serde(default)]
+    pub pharmacy_id: Option<i32>,
+    #[serde(default)]
+    pub prescriber_id: Option<i32>,
+    #[serde(default)]
+    pub refills_remaining: Option<i32>,
+    #[serde(default)]
+    pub script_expires: Option<TimeDate>,
+    #[serde(default)]
+    pub refills_counted_from: Option<TimeDate>,
+    #[serde(default)]
+    pub original_fill_pending: bool,
+    pub dose_units: Option<i32>,
+    #[serde(default)]
+    pub dose_every_days: Option<i32>,
+    #[serde(default)]
+    pub critical_days: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FillRequestRow {
+    pub id: i32,
+    pub rx_id: i32,
+    pub date_requested: Option<TimeDate>,
+    pub date_filled: Option<TimeDate>,
+    pub date_picked_up: Option<TimeDate>,
+    pub closed: bool,
+    /// Why the request was closed, e.g. `superseded`
+    #[serde(default)]
+    pub close_reason: Option<String>,
+    #[serde(default)]
+    pub date_cancelled: Option<TimeDate>,
+    #[serde(default)]
+    pub cancel_reason: Option<String>,
+    #[serde(default)]
+    pub pharmacy_id: Option<i32>,
+    /// What its pick-up used up of the script, e.g. `refill`
+    #[serde(default)]
+    pub refill_use: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventRow {
+    pub id: i32,
+    pub rx_id: i32,
+    /// The event type, e.g. `pick_up`
+    pub event: String,
+    pub date: TimeDate,
+    #[serde(default)]
+    pub voided: bool,
+    #[serde(default)]
+    pub fill_request_id: Option<i32>,
+    pub pharmacy_id: Option<i32>,
+    #[serde(default)]
+    pub quantity: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReminderPolicyRow {
+    pub id: i32,
+    pub rx_id: i32,
+    /// The event type, e.g. `pick_up`
+    pub starting_event: Option<String>,
+    pub include_rx_duration: bool,
+    pub offset_days: i32,
+    pub allow_saturday: bool,
+    pub allow_sunday: bool,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaysSupplyRow {
+    pub id: i32,
+    pub rx_id: i32,
+    pub effective_date: TimeDate,
+    pub days_supply: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportedDispenseRow {
+    pub id: i32,
+    pub rx_id: i32,
+    pub import_key: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CorrectionRow {
+    pub id: i32,
+    pub rx_id: i32,
+    pub event_id: Option<i32>,
+    pub fill_request_id: Option<i32>,
+    /// The event type, e.g. `pick_up`
+    pub event_type: String,
+    pub original_date: TimeDate,
+    /// The amended date, or `None` if voided
+    pub new_date: Option<TimeDate>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenewalRow {
+    pub id: i32,
+    pub rx_id: i32,
+    #[serde(default)]
+    pub prescriber_id: Option<i32>,
+    /// The renewal status, e.g. `approved`
+    pub status: String,
+    pub date_requested: TimeDate,
+    #[serde(default)]
+    pub date_approved: Option<TimeDate>,
+    #[serde(default)]
+    pub date_denied: Option<TimeDate>,
+    #[serde(default)]
+    pub deny_reason: Option<String>,
+    #[serde(default)]
+    pub date_received: Option<TimeDate>,
+    #[serde(default)]
+    pub follow_up_date: Option<TimeDate>,
+    #[serde(default)]
+    pub refills: Option<i32>,
+    #[serde(default)]
+    pub expires: Option<TimeDate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PillCountRow {
+    pub id: i32,
+    pub rx_id: i32,
+    pub date: TimeDate,
+    pub counted: i32,
+    #[serde(default)]
+    pub expected: Option<i32>,
+}
+
+/// Every row of every table, keeping their IDs.
+///
+/// Tables added after a backup was made are empty when it is read.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Backup {
+    pub schema_version: u32,
+    #[serde(default)]
+    pub people: Vec<PersonRow>,
+    #[serde(default)]
+    pub pharmacies: Vec<PharmacyRow>,
+    #[serde(default)]
+    pub prescribers: Vec<PrescriberRow>,
+    pub rx: Vec<RxRow>,
+    pub fill_requests: Vec<FillRequestRow>,
+    #[serde(default)]
+    pub events: Vec<EventRow>,
+    pub reminder_policies: Vec<ReminderPolicyRow>,
+    #[serde(default)]
+    pub days_supply: Vec<DaysSupplyRow>,
+    #[serde(default)]
+    pub imported_dispenses: Vec<ImportedDispenseRow>,
+    #[serde(default)]
+    pub corrections: Vec<CorrectionRow>,
+    #[serde(default)]
+    pub renewals: Vec<RenewalRow>,
+    #[serde(default)]
+    pub pill_counts: Vec<PillCountRow>,
+}
+
+/// Just enough of a backup to check its version before reading the rest.
+#[derive(Deserialize)]
+struct BackupVersion {
+    schema_version: u32,
+}
+
+impl Backup {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("backups can always be serialized")
+    }
+
+    /// Parse a backup, rejecting those from a newer schema version.
+    pub fn from_json(json: &str) -> Result<Backup, Error> {
+        let version: BackupVersion =
+            serde_json::from_str(json).map_err(|e| Error::InvalidBackup(e.to_string()))?;
+        if version.schema_version > schema_version() {
+            return Err(Error::NewerBackupVersion(version.schema_version));
+        }
+        serde_json::from_str(json).map_err(|e| Error::InvalidBackup(e.to_string()))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.people.is_empty()
+            && self.pharmacies.is_empty()
+            && self.prescribers.is_empty()
+            && self.rx.is_empty()
+            && self.fill_requests.is_empty()
+            && self.events.is_empty()
+            && self.reminder_policies.is_empty()
+            && self.days_supply.is_empty()
+            && self.imported_dispenses.is_empty()
+            && self.corrections.is_empty()
+            && self.renewals.is_empty()
+            && self.pill_counts.is_empty()
+    }
+}
+
+/// Read every row of the database into a backup.
+pub async fn export_backup(db: &impl ConnectionTrait) -> Result<Backup, Error> {
+    let people = person::Entity::find()
+        .order_by_asc(person::Column::PersonId)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|row| PersonRow {
+            id: row.person_id,
+            name: row.name,
+        })
+        .collect();
+    let pharmacies = pharmacy::Entity::find()
+        .order_by_asc(pharmacy::Column::PharmacyId)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|row| PharmacyRow {
+            id: row.pharmacy_id,
+            name: row.name,
+            phone: row.phone,
+            address: row.address,
+            hours: row.hours,
+        })
+        .collect();
+    let prescribers = prescriber::Entity::find()
+        .order_by_asc(prescriber::Column::PrescriberId)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|row| PrescriberRow {
+            id: row.prescriber_id,
+            name: row.name,
+            practice: row.practice,
+            phone: row.phone,
+            fax: row.fax,
+        })
+        .collect();
+    let rx = rx_info::Entity::find()
+        .order_by_asc(rx_info::Column::RxId)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|row| RxRow {
+            id: row.rx_id,
+            name: row.rx_name,
+            hidden: row.hidden,
+            person_id: row.person_id,
+            retired_date: row.retired_date,
+            retired_reason: row.retired_reason,
+            normalized_name: row.normalized_name,
+            pharmacy_id: row.pharmacy_id,
+            prescriber_id: row.prescriber_id,
+            refills_remaining: row.refills_remaining,
+            script_expires: row.script_expires,
+            refills_counted_from: row.refills_counted_from,
+            original_fill_pending: row.original_fill_pending,
+            dose_units: row.dose_units,
+            dose_every_days: row.dose_every_days,
+            critical_days: row.critical_days,
+        })
+        .collect();
+    let fill_requests = fill_request::Entity::find()
+        .order_by_asc(fill_request::Column::Id)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|row| FillRequestRow {
+            id: row.id,
+            rx_id: row.rx_id,
+            date_requested: row.date_requested,
+            date_filled: row.date_filled,
+            date_picked_up: row.date_picked_up,
+            closed: row.closed,
+            close_reason: row.close_reason.map(enum_name),
+            date_cancelled: row.date_cancelled,
+            cancel_reason: row.cancel_reason,
+            pharmacy_id: row.pharmacy_id,
+            refill_use: Some(enum_name(row.refill_use)),
+        })
+        .collect();
+    let events = events::Entity::find()
+        .order_by_asc(events::Column::Id)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|row| EventRow {
+            id: row.id,
+            rx_id: row.rx_id,
+            event: enum_name(row.event),
+            date: row.date,
+            voided: row.voided,
+            fill_request_id: row.fill_request_id,
+            pharmacy_id: row.pharmacy_id,
+            quantity: row.quantity,
+        })
+        .collect();
+    let reminder_policies = reminder_policy::Entity::find()
+        .order_by_asc(reminder_policy::Column::ReminderId)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|row| ReminderPolicyRow {
+            id: row.reminder_id,
+            rx_id: row.rx_id,
+            starting_event: row.starting_date.map(enum_name),
+            include_rx_duration: row.include_rx_duration,
+            offset_days: row.offset,
+            allow_saturday: row.allow_saturday,
+            allow_sunday: row.allow_sunday,
+            description: row.description,
+        })
+        .collect();
+    let days_supply = rx_days_supply::Entity::find()
+        .order_by_asc(rx_days_supply::Column::Id)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|row| DaysSupplyRow {
+            id: row.id,
+            rx_id: row.rx_id,
+            effective_date: row.effective_date,
+            days_supply: row.days_supply,
+        })
+        .collect();
+    let imported_dispenses = imported_dispense::Entity::find()
+        .order_by_asc(imported_dispense::Column::Id)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|row| ImportedDispenseRow {
+            id: row.id,
+            rx_id: row.rx_id,
+            import_key: row.import_key,
+        })
+        .collect();
+    let corrections = event_correction::Entity::find()
+        .order_by_asc(event_correction::Column::Id)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|row| CorrectionRow {
+            id: row.id,
+            rx_id: row.rx_id,
+            event_id: row.event_id,
+            fill_request_id: row.fill_request_id,
+            event_type: enum_name(row.event_type),
+            original_date: row.original_date,
+            new_date: row.new_date,
+            reason: row.reason,
+        })
+        .collect();
+    let renewals = renewal_request::Entity::find()
+        .order_by_asc(renewal_request::Column::Id)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|row| RenewalRow {
+            id: row.id,
+            rx_id: row.rx_id,
+            prescriber_id: row.prescriber_id,
+            status: enum_name(row.status),
+            date_requested: row.date_requested,
+            date_approved: row.date_approved,
+            date_denied: row.date_denied,
+            deny_reason: row.deny_reason,
+            date_received: row.date_received,
+            follow_up_date: row.follow_up_date,
+            refills: row.refills,
+            expires: row.expires,
+        })
+        .collect();
+    let pill_counts = pill_count::Entity::find()
+        .order_by_asc(pill_count::Column::Id)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|row| PillCountRow {
+            id: row.id,
+            rx_id: row.rx_id,
+            date: row.date,
+            counted: row.counted,
+            expected: row.expected,
+        })
+        .collect();
+    Ok(Backup {
+        schema_version: schema_version(),
+        people,
+        pharmacies,
+        prescribers,
+        rx,
+        fill_requests,
+        events,
+        reminder_policies,
+        days_supply,
+        imported_dispenses,
+        corrections,
+        renewals,
+        pill_counts,
+    })
+}
+
+/// Insert all rows of a table, if there are any.
+async fn insert_rows<A>(
+    db: &impl ConnectionTrait,
+    rows: impl IntoIterator<Item = A>,
+) -> Result<(), Error>
+where
+    A: sea_orm::ActiveModelTrait,
+{
+    let rows: Vec<A> = rows.into_iter().collect();
+    if !rows.is_empty() {
+        A::Entity::insert_many(rows).exec(db).await?;
+    }
+    Ok(())
+}
+
+/// Restore a backup into an empty database, keeping all IDs, in a single transaction.
+///
+/// Fails without changing anything if the database already has any data.
+pub async fn restore_backup<C>(db: &C, backup: &Backup) -> Result<(), Error>
+where
+    C: ConnectionTrait + TransactionTrait,
+{
+    if backup.schema_version > schema_version() {
+        return Err(Error::NewerBackupVersion(backup.schema_version));
+    }
+
+    let txn = db.begin().await?;
+    if !export_backup(&txn).await?.is_empty() {
+        return Err(Error::RestoreTargetNotEmpty);
+    }
+    insert_rows(
+        &txn,
+        backup.people.iter().map(|row| person::ActiveModel {
+            person_id: Set(row.id),
+            name: Set(row.name.clone()),
+        }),
+    )
+    .await?;
+    insert_rows(
+        &txn,
+        backup.pharmacies.iter().map(|row| pharmacy::ActiveModel {
+            pharmacy_id: Set(row.id),
+            name: Set(row.name.clone()),
+            phone: Set(row.phone.clone()),
+            address: Set(row.address.clone()),
+            hours: Set(row.hours.clone()),
+        }),
+    )
+    .await?;
+    insert_rows(
+        &txn,
+        backup
+            .prescribers
+            .iter()
+            .map(|row| prescriber::ActiveModel {
+                prescriber_id: Set(row.id),
+                name: Set(row.name.clone()),
+                practice: Set(row.practice.clone()),
+                phone: Set(row.phone.clone()),
+                fax: Set(row.fax.clone()),
+            }),
+    )
+    .await?;
+    insert_rows(
+        &txn,
+        backup.rx.iter().map(|row| rx_info::ActiveModel {
+            rx_id: Set(row.id),
+            rx_name: Set(row.name.clone()),
+            hidden: Set(row.hidden),
+            person_id: Set(row.person_id),
+            retired_date: Set(row.retired_date),
+            retired_reason: Set(row.retired_reason.clone()),
+            normalized_name: Set(row.normalized_name.clone()),
+            pharmacy_id: Set(row.pharmacy_id),
+            prescriber_id: Set(row.prescriber_id),
+            refills_remaining: Set(row.refills_remaining),
+            script_expires: Set(row.script_expires),
+            refills_counted_from: Set(row.refills_counted_from),
+            original_fill_pending: Set(row.original_fill_pending),
+            dose_units: Set(row.dose_units),
+            dose_every_days: Set(row.dose_every_days),
+            critical_days: Set(row.critical_days),
+        }),
+    )
+    .await?;
+    let fill_requests = backup
+        .fill_requests
+        .iter()
+        .map(|row| {
+            let close_reason = match (&row.close_reason, row.closed) {
+                (Some(name), _) => Some(parse_enum("close reason", name)?),
+                (None, true) => Some(infer_close_reason(
+                    row,
+                    &backup.fill_requests,
+                    &backup.imported_dispenses,
+                )),
+                (None, false) => None,
+            };
+            let refill_use = match &row.refill_use {
+                Some(name) => parse_enum("refill use", name)?,
+                None => RefillUse::Nothing,
+            };
+            Ok(fill_request::ActiveModel {
+                id: Set(row.id),
+                rx_id: Set(row.rx_id),
+                date_requested: Set(row.date_requested),
+                date_filled: Set(row.date_filled),
+                date_picked_up: Set(row.date_picked_up),
+                closed: Set(row.closed),
+                close_reason: Set(close_reason),
+                date_cancelled: Set(row.date_cancelled),
+                cancel_reason: Set(row.cancel_reason.clone()),
+                pharmacy_id: Set(row.pharmacy_id),
+                refill_use: Set(refill_use),
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    insert_rows(&txn, fill_requests).await?;
+    let links = if backup.schema_version < migration_version("m20230401_000001_event_correction") {
+        link_backup_events(&backup.fill_requests, &backup.events)?
+    } else {
+        HashMap::new()
+    };
+    let events = backup
+        .events
+        .iter()
+        .map(|row| {
+            Ok(events::ActiveModel {
+                id: Set(row.id),
+                rx_id: Set(row.rx_id),
+                event: Set(parse_event_type(&row.event)?),
+                date: Set(row.date),
+                voided: Set(row.voided),
+                fill_request_id: Set(row.fill_request_id.or_else(|| links.get(&row.id).copied())),
+                pharmacy_id: Set(row.pharmacy_id),
+                quantity: Set(row.quantity),
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    insert_rows(&txn, events).await?;
+    if backup.schema_version < migration_version("m20230204_000001_backfill_events") {
+        // Migrations already ran on the empty database, so nothing else will backfill them
+        insert_rows(&txn, backfill_backup_events(&backup.fill_requests)).await?;
+    }
+    let reminder_policies = backup
+        .reminder_policies
+        .iter()
+        .map(|row| {
+            Ok(reminder_policy::ActiveModel {
+                reminder_id: Set(row.id),
+                rx_id: Set(row.rx_id),
+                starting_date: Set(row
+                    .starting_event
+                    .as_deref()
+                    .map(parse_event_type)
+                    .transpose()?),
+                include_rx_duration: Set(row.include_rx_duration),
+                offset: Set(row.offset_days),
+                allow_saturday: Set(row.allow_saturday),
+                allow_sunday: Set(row.allow_sunday),
+                description: Set(row.description.clone()),
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    insert_rows(&txn, reminder_policies).await?;
+    insert_rows(
+        &txn,
+        backup
+            .days_supply
+            .iter()
+            .map(|row| rx_days_supply::ActiveModel {
+                id: Set(row.id),
+                rx_id: Set(row.rx_id),
+                effective_date: Set(row.effective_date),
+                days_supply: Set(row.days_supply),
+            }),
+    )
+    .await?;
+    insert_rows(
+        &txn,
+        backup
+            .imported_dispenses
+            .iter()
+            .map(|row| imported_dispense::ActiveModel {
+                id: Set(row.id),
+                rx_id: Set(row.rx_id),
+                import_key: Set(row.import_key.clone()),
+            }),
+    )
+    .await?;
+    let corrections = backup
+        .corrections
+        .iter()
+        .map(|row| {
+            Ok(event_correction::ActiveModel {
+                id: Set(row.id),
+                rx_id: Set(row.rx_id),
+                event_id: Set(row.event_id),
+                fill_request_id: Set(row.fill_request_id),
+                event_type: Set(parse_event_type(&row.event_type)?),
+                original_date: Set(row.original_date),
+                new_date: Set(row.new_date),
+                reason: Set(row.reason.clone()),
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    insert_rows(&txn, corrections).await?;
+    let renewals = backup
+        .renewals
+        .iter()
+        .map(|row| {
+            Ok(renewal_request::ActiveModel {
+                id: Set(row.id),
+                rx_id: Set(row.rx_id),
+                prescriber_id: Set(row.prescriber_id),
+                status: Set(parse_enum::<RenewalStatus>("renewal status", &row.status)?),
+                date_requested: Set(row.date_requested),
+                date_approved: Set(row.date_approved),
+                date_denied: Set(row.date_denied),
+                deny_reason: Set(row.deny_reason.clone()),
+                date_received: Set(row.date_received),
+                follow_up_date: Set(row.follow_up_date),
+                refills: Set(row.refills),
+                expires: Set(row.expires),
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    insert_rows(&txn, renewals).await?;
+    insert_rows(
+        &txn,
+        backup
+            .pill_counts
+            .iter()
+            .map(|row| pill_count::ActiveModel {
+                id: Set(row.id),
+                rx_id: Set(row.rx_id),
+                date: Set(row.date),
+                counted: Set(row.counted),
+                expected: Set(row.expected),
+            }),
+    )
+    .await?;
+    txn.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::Database;
+    use time::{Date, Duration, Month};
+
+    use super::*;
+    use crate::{
+        authorization::{set_authorization, Authorization},
+        days_supply::set_days_supply,
+        dosing::{set_dose_rate, set_pickup_quantity, DoseRate},
+        events::list_events,
+        fill_request::{list_fill_requests, record_fill_request, record_pickup, FillRequest},
+        implicit_fill_request::find_legacy_mismatches,
+        inventory::{record_pill_count, set_critical_days},
+        person::add_person,
+        pharmacy::{add_pharmacy, set_default_pharmacy, Pharmacy},
+        prescriber::{add_prescriber, set_rx_prescriber, Prescriber},
+        reminder_policy::{add_reminder_policy, ReminderPolicy},
+        renewal::{approve_renewal, request_renewal},
+        rx::{add_person_rx, add_rx, list_all_rx, retire_rx},
+        FillRequestId,
+    };
+
+    #[async_std::test]
+    async fn test_backup_round_trip() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let alice = add_person(&db, "Alice").await?;
+        let amox_id = add_person_rx(&db, alice, "amoxicillin").await?.id();
+        let pred_id = add_rx(&db, "prednisone").await?.id();
+        retire_rx(&db, pred_id, date, "done").await?;
+        set_days_supply(&db, amox_id, 30, date).await?;
+        let picked_up = record_pickup(&db, amox_id, Some(date), date).await?;
+        let corner = add_pharmacy(
+            &db,
+            &Pharmacy {
+                name: "Corner Drug".to_owned(),
+                phone: Some("555-0100".to_owned()),
+                ..Default::default()
+            },
+        )
+        .await?;
+        set_default_pharmacy(&db, amox_id, Some(corner)).await?;
+        let smith = add_prescriber(
+            &db,
+            &Prescriber {
+                name: "Dr. Smith".to_owned(),
+                fax: Some("555-0151".to_owned()),
+                ..Default::default()
+            },
+        )
+        .await?;
+        set_rx_prescriber(&db, amox_id, Some(smith)).await?;
+        set_authorization(
+            &db,
+            amox_id,
+            &Authorization {
+                refills_remaining: Some(5),
+                expires: Some(date + Duration::days(365)),
+                original_fill_pending: false,
+            },
+            date,
+        )
+        .await?;
+        set_dose_rate(&db, amox_id, Some(DoseRate::daily(2))).await?;
+        set_pickup_quantity(&db, picked_up, Some(60)).await?;
+        set_critical_days(&db, amox_id, Some(7)).await?;
+        record_pill_count(&db, amox_id, date + Duration::days(10), 38).await?;
+        let renewal = request_renewal(&db, amox_id, date, None).await?;
+        approve_renewal(
+            &db,
+            renewal,
+            date,
+            &Authorization {
+                refills_remaining: Some(5),
+                expires: Some(date + Duration::days(365)),
+                original_fill_pending: false,
+            },
+        )
+        .await?;
+        record_fill_request(&db, amox_id, date + Duration::days(25)).await?;
+        add_reminder_policy(
+            &db,
+            amox_id,
+            &ReminderPolicy {
+                starting_event: Some(EventType::PickUp),
+                include_rx_duration: true,
+                offset_days: -7,
+                allow_saturday: false,
+                allow_sunday: true,
+                description: "Request refill".to_owned(),
+            },
+        )
+        .await?;
+
+        let backup = export_backup(&db).await?;
+        assert_eq!(backup.schema_version, schema_version());
+        assert_eq!(backup.events.len(), 3);
+        assert_eq!(backup.events[1].event, "pick_up");
+        assert_eq!(backup.pharmacies.len(), 1);
+        assert_eq!(backup.rx[0].prescriber_id, Some(i32::from(smith)));
+        assert_eq!(backup.rx[0].refills_remaining, Some(5));
+        assert_eq!(backup.events[2].pharmacy_id, Some(i32::from(corner)));
+        assert_eq!(backup.rx[0].dose_units, Some(2));
+        assert_eq!(backup.events[1].quantity, Some(60));
+        assert_eq!(backup.rx[0].critical_days, Some(7));
+        assert_eq!(backup.pill_counts[0].expected, Some(40));
+        assert_eq!(backup.renewals.len(), 1);
+        assert_eq!(backup.renewals[0].status, "approved");
+        let json = backup.to_json();
+        let parsed = Backup::from_json(&json)?;
+        assert_eq!(parsed, backup);
+
+        // Restoring into a database with data fails
+        assert_eq!(
+            restore_backup(&db, &parsed).await,
+            Err(Error::RestoreTargetNotEmpty)
+        );
+
+        let restored = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&restored, None).await?;
+        restore_backup(&restored, &parsed).await?;
+        assert_eq!(export_backup(&restored).await?, backup);
+        assert_eq!(
+            list_all_rx(&restored, None).await?,
+            list_all_rx(&db, None).await?
+        );
+        // New rows continue after the restored IDs
+        assert_ne!(add_rx(&restored, "ibuprofen").await?.id(), amox_id);
+
+        // From before events were linked to their fill requests, they are linked on restore
+        let mut unlinked = backup.clone();
+        unlinked.schema_version = migration_version("m20230401_000001_event_correction") - 1;
+        for event in &mut unlinked.events {
+            event.fill_request_id = None;
+        }
+        let restored = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&restored, None).await?;
+        restore_backup(&restored, &unlinked).await?;
+        assert_eq!(export_backup(&restored).await?.events, backup.events);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_backup_versions() -> Result<(), Error> {
+        let newer = format!(
+            r#"{{"schema_version": {}, "future_table": []}}"#,
+            schema_version() + 1
+        );
+        assert_eq!(
+            Backup::from_json(&newer),
+            Err(Error::NewerBackupVersion(schema_version() + 1))
+        );
+        assert!(matches!(
+            Backup::from_json("not json"),
+            Err(Error::InvalidBackup(_))
+        ));
+
+        // An older backup, from before people and events existed
+        let older = r#"{
+            "schema_version": 1,
+            "rx": [{"id": 4, "name": "amoxicillin", "hidden": false}],
+            "fill_requests": [
+                {"id": 2, "rx_id": 4, "date_requested": "2023-01-01",
+                    "date_filled": null, "date_picked_up": null, "closed": false},
+                {"id": 1, "rx_id": 4, "date_requested": "2022-12-01",
+                    "date_filled": null, "date_picked_up": null, "closed": true}
+            ],
+            "reminder_policies": []
+        }"#;
+        let backup = Backup::from_json(older)?;
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        restore_backup(&db, &backup).await?;
+        let rx = list_all_rx(&db, None).await?;
+        assert_eq!(rx.len(), 1);
+        assert_eq!(i32::from(rx[0].id), 4);
+        // Closed before close reasons were recorded, and followed by another request
+        let requests = list_fill_requests(&db, rx[0].id).await?;
+        assert_eq!(requests[0].close_reason(), Some(CloseReason::Superseded));
+        assert_eq!(requests[1].close_reason(), None);
+        // Events are generated for the fill requests, as the migration would have
+        let restored_events = list_events(&db, rx[0].id, ..).await?;
+        assert_eq!(
+            restored_events
+                .iter()
+                .map(|event| event.event_type)
+                .collect::<Vec<_>>(),
+            vec![
+                EventType::RequestFill,
+                EventType::RefillCancel,
+                EventType::RequestFill
+            ]
+        );
+        assert!(find_legacy_mismatches(&db).await?.is_empty());
+        // and linked to the requests they record
+        assert_eq!(
+            restored_events
+                .iter()
+                .map(|event| event.fill_request_id)
+                .collect::<Vec<_>>(),
+            vec![
+                Some(FillRequestId::from(1)),
+                None,
+                Some(FillRequestId::from(2))
+            ]
+        );
+
+        let mut bad = backup.clone();
+        bad.events.push(EventRow {
+            id: 1,
+            rx_id: 4,
+            event: "whenever".to_owned(),
+            date: Date::from_calendar_date(2023, Month::January, 1).unwrap(),
+            voided: false,
+            fill_request_id: None,
+            pharmacy_id: None,
+            quantity: None,
+        });
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        assert!(matches!(
+            restore_backup(&db, &bad).await,
+            Err(Error::InvalidBackup(_))
+        ));
+        // Nothing was restored
+        assert!(list_all_rx(&db, None).await?.is_empty());
+        Ok(())
+    }
+}
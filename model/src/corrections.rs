@@ -0,0 +1,471 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Amend the date of, or void, a recorded event or fill request step,
+//! keeping the original date in a correction log.
+//!
+//! Because fill requests are recorded both as events and in the `fill_request` table,
+//! a correction applies to the event and fill request step alike:
+//! the event of that type linked to the fill request.
+
+use sea_orm::{
+    prelude::TimeDate, ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait,
+    EntityTrait, QueryFilter, QueryOrder,
+};
+
+use crate::{
+    authorization::recheck_refill,
+    entities::{event_correction, events, fill_request},
+    CloseReason, CorrectionId, Error, EventId, EventType, FillRequestId, RxId,
+};
+
+/// A single amendment or voiding of an event and/or fill request step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Correction {
+    pub id: CorrectionId,
+    pub rx_id: RxId,
+    pub event_id: Option<EventId>,
+    pub fill_request_id: Option<FillRequestId>,
+    /// The type of event, which for a fill request is the step that was corrected
+    pub event_type: EventType,
+    pub original_date: TimeDate,
+    /// The amended date, or `None` if voided
+    pub new_date: Option<TimeDate>,
+    pub reason: String,
+}
+
+impl Correction {
+    pub fn voided(&self) -> bool {
+        self.new_date.is_none()
+    }
+}
+
+impl From<event_correction::Model> for Correction {
+    fn from(value: event_correction::Model) -> Self {
+        Correction {
+            id: CorrectionId::from(value.id),
+            rx_id: RxId::from(value.rx_id),
+            event_id: value.event_id.map(EventId::from),
+            fill_request_id: value.fill_request_id.map(FillRequestId::from),
+            event_type: value.event_type,
+            original_date: value.original_date,
+            new_date: value.new_date,
+            reason: value.reason,
+        }
+    }
+}
+
+fn step_date(request: &fill_request::Model, step: EventType) -> Option<TimeDate> {
+    match step {
+        EventType::RequestFill => request.date_requested,
+        EventType::Fill => request.date_filled,
+        EventType::PickUp => request.date_picked_up,
+        EventType::RefillCancel => request.date_cancelled,
+    }
+}
+
+fn set_step_date(request: &mut fill_request::ActiveModel, step: EventType, date: Option<TimeDate>) {
+    match step {
+        EventType::RequestFill => request.date_requested = Set(date),
+        EventType::Fill => request.date_filled = Set(date),
+        EventType::PickUp => request.date_picked_up = Set(date),
+        EventType::RefillCancel => request.date_cancelled = Set(date),
+    }
+}
+
+/// The latest event recording this step of a fill request that is not voided, if any.
+pub(crate) async fn find_step_event(
+    db: &impl ConnectionTrait,
+    id: FillRequestId,
+    step: EventType,
+) -> Result<Option<events::Model>, Error> {
+    let event = events::Entity::find()
+        .filter(events::Column::FillRequestId.eq(i32::from(id)))
+        .filter(events::Column::Event.eq(step))
+        .filter(events::Column::Voided.eq(false))
+        .order_by_desc(events::Column::Id)
+        .one(db)
+        .await?;
+    Ok(event)
+}
+
+/// Voiding the step that closed a request undoes the closing:
+/// the request is open again, unless a later request has since superseded it.
+async fn reopen_fill_request(
+    db: &impl ConnectionTrait,
+    request: &fill_request::Model,
+    active: &mut fill_request::ActiveModel,
+) -> Result<(), Error> {
+    let later = fill_request::Entity::find()
+        .filter(fill_request::Column::RxId.eq(request.rx_id))
+        .filter(fill_request::Column::Id.gt(request.id))
+        .one(db)
+        .await?;
+    if later.is_some() {
+        active.close_reason = Set(Some(CloseReason::Superseded));
+    } else {
+        active.closed = Set(false);
+        active.close_reason = Set(None);
+    }
+    active.cancel_reason = Set(None);
+    Ok(())
+}
+
+/// The event and/or fill request step to correct.
+struct Target {
+    rx: RxId,
+    event_type: EventType,
+    original_date: TimeDate,
+    event: Option<events::Model>,
+    request: Option<fill_request::Model>,
+}
+
+/// Apply a correction to an event and/or fill request, and log it.
+async fn apply_correction(
+    db: &impl ConnectionTrait,
+    target: Target,
+    new_date: Option<TimeDate>,
+    reason: &str,
+) -> Result<CorrectionId, Error> {
+    let Target {
+        rx,
+        event_type,
+        original_date,
+        event,
+        request,
+    } = target;
+    let event_id = event.as_ref().map(|event| event.id);
+    let fill_request_id = request.as_ref().map(|request| request.id);
+
+    if let Some(event) = event {
+        let mut event: events::ActiveModel = event.into();
+        match new_date {
+            Some(date) => event.date = Set(date),
+            None => event.voided = Set(true),
+        }
+        event.update(db).await?;
+    }
+
+    if let Some(request) = request {
+        let mut active: fill_request::ActiveModel = request.clone().into();
+        set_step_date(&mut active, event_type, new_date);
+        let closed_by_step = matches!(
+            (event_type, request.close_reason),
+            (EventType::PickUp, Some(CloseReason::Completed))
+                | (EventType::RefillCancel, Some(CloseReason::Cancelled))
+        );
+        if new_date.is_none() && closed_by_step {
+            reopen_fill_request(db, &request, &mut active).await?;
+        }
+        if event_type == EventType::PickUp {
+            let used = recheck_refill(db, rx, request.refill_use, original_date, new_date).await?;
+            active.refill_use = Set(used);
+        }
+        active.update(db).await?;
+    }
+
+    let res = event_correction::Entity::insert(event_correction::ActiveModel {
+        rx_id: Set(rx.0),
+        event_id: Set(event_id),
+        fill_request_id: Set(fill_request_id),
+        event_type: Set(event_type),
+        original_date: Set(original_date),
+        new_date: Set(new_date),
+        reason: Set(reason.trim().to_owned()),
+        ..Default::default()
+    })
+    .exec(db)
+    .await?;
+    Ok(CorrectionId::from(res.last_insert_id))
+}
+
+async fn correct_event(
+    db: &impl ConnectionTrait,
+    id: EventId,
+    new_date: Option<TimeDate>,
+    reason: &str,
+) -> Result<CorrectionId, Error> {
+    let event = events::Entity::find_by_id(i32::from(id))
+        .one(db)
+        .await?
+        .ok_or(Error::NoSuchEvent(id))?;
+    if event.voided {
+        return Err(Error::EventVoided(id));
+    }
+    let rx = RxId::from(event.rx_id);
+    let (event_type, original_date) = (event.event, event.date);
+    let request = match event.fill_request_id {
+        Some(id) => fill_request::Entity::find_by_id(id).one(db).await?,
+        None => None,
+    };
+    let target = Target {
+        rx,
+        event_type,
+        original_date,
+        event: Some(event),
+        request,
+    };
+    apply_correction(db, target, new_date, reason).await
+}
+
+async fn correct_fill_request_step(
+    db: &impl ConnectionTrait,
+    id: FillRequestId,
+    step: EventType,
+    new_date: Option<TimeDate>,
+    reason: &str,
+) -> Result<CorrectionId, Error> {
+    let request = fill_request::Entity::find_by_id(i32::from(id))
+        .one(db)
+        .await?
+        .ok_or(Error::NoSuchFillRequest(id))?;
+    let original_date = step_date(&request, step).ok_or(Error::NoSuchFillRequestStep(id, step))?;
+    let rx = RxId::from(request.rx_id);
+    let event = find_step_event(db, id, step).await?;
+    let target = Target {
+        rx,
+        event_type: step,
+        original_date,
+        event,
+        request: Some(request),
+    };
+    apply_correction(db, target, new_date, reason).await
+}
+
+/// Change the date of an event, along with the fill request step it records, if any.
+///
+/// Moving a pick-up to before or after the script of the rx was authorized
+/// gives back or uses up a refill to match.
+pub async fn amend_event(
+    db: &impl ConnectionTrait,
+    id: EventId,
+    new_date: TimeDate,
+    reason: &str,
+) -> Result<CorrectionId, Error> {
+    correct_event(db, id, Some(new_date), reason).await
+}
+
+/// Void an event, so it no longer counts anywhere, along with the fill request step it records, if any.
+///
+/// Voiding the pick-up or cancellation that closed a fill request opens it again,
+/// unless a later request superseded it.
+/// Voiding a pick-up that used a refill gives the refill back.
+pub async fn void_event(
+    db: &impl ConnectionTrait,
+    id: EventId,
+    reason: &str,
+) -> Result<CorrectionId, Error> {
+    correct_event(db, id, None, reason).await
+}
+
+/// Change the date of a step of a fill request, along with the event that records it, if any.
+///
+/// The step is given by the type of event that records it. See [`amend_event`].
+pub async fn amend_fill_request_step(
+    db: &impl ConnectionTrait,
+    id: FillRequestId,
+    step: EventType,
+    new_date: TimeDate,
+    reason: &str,
+) -> Result<CorrectionId, Error> {
+    correct_fill_request_step(db, id, step, Some(new_date), reason).await
+}
+
+/// Clear a step of a fill request, along with voiding the event that records it, if any.
+///
+/// See [`void_event`].
+pub async fn void_fill_request_step(
+    db: &impl ConnectionTrait,
+    id: FillRequestId,
+    step: EventType,
+    reason: &str,
+) -> Result<CorrectionId, Error> {
+    correct_fill_request_step(db, id, step, None, reason).await
+}
+
+/// List the corrections made to the events and fill requests of an rx, oldest first.
+pub async fn list_corrections(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+) -> Result<Vec<Correction>, Error> {
+    let corrections = event_correction::Entity::find()
+        .filter(event_correction::Column::RxId.eq(rx.0))
+        .order_by_asc(event_correction::Column::Id)
+        .all(db)
+        .await?;
+    Ok(corrections.into_iter().map(Correction::from).collect())
+}
+
+#[cfg(test)]
+mod test {
+
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::Database;
+    use time::{Date, Duration, Month};
+
+    use super::*;
+    use crate::{
+        days_supply::set_days_supply,
+        events::list_events,
+        fill_request::{
+            cancel_fill_request, get_fill_state, list_fill_requests, record_fill_request,
+            record_pickup, FillRequest, FillState,
+        },
+        implicit_fill_request::{self, find_legacy_mismatches},
+        reminders::run_out_date,
+        rx::add_rx,
+    };
+
+    #[async_std::test]
+    async fn test_amend() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let day = |n| date + Duration::days(n);
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        set_days_supply(&db, amox_id, 30, date).await?;
+
+        // Picked up on day 2, but logged on day 20
+        let request_id = record_pickup(&db, amox_id, Some(day(2)), day(20)).await?;
+        assert_eq!(run_out_date(&db, amox_id).await?, Some(day(50)));
+        let pickup = list_events(&db, amox_id, ..).await?.pop().unwrap();
+        assert_eq!(pickup.event_type, EventType::PickUp);
+
+        let correction = amend_event(&db, pickup.id, day(2), " wrong day ").await?;
+        assert_eq!(run_out_date(&db, amox_id).await?, Some(day(32)));
+        let requests = list_fill_requests(&db, amox_id).await?;
+        assert_eq!(*requests[0].date_picked_up(), Some(day(2)));
+        assert!(find_legacy_mismatches(&db).await?.is_empty());
+
+        // Or from the fill request side
+        amend_fill_request_step(&db, request_id, EventType::Fill, day(1), "").await?;
+        let events = list_events(&db, amox_id, ..).await?;
+        assert_eq!(events[0].event_type, EventType::Fill);
+        assert_eq!(events[0].date, day(1));
+        assert_eq!(
+            amend_fill_request_step(&db, request_id, EventType::RequestFill, day(0), "").await,
+            Err(Error::NoSuchFillRequestStep(
+                request_id,
+                EventType::RequestFill
+            ))
+        );
+
+        let corrections = list_corrections(&db, amox_id).await?;
+        assert_eq!(corrections.len(), 2);
+        assert_eq!(
+            corrections[0],
+            Correction {
+                id: correction,
+                rx_id: amox_id,
+                event_id: Some(pickup.id),
+                fill_request_id: Some(request_id),
+                event_type: EventType::PickUp,
+                original_date: day(20),
+                new_date: Some(day(2)),
+                reason: "wrong day".to_owned(),
+            }
+        );
+        assert_eq!(corrections[1].original_date, day(2));
+        assert_eq!(corrections[1].new_date, Some(day(1)));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_void() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let day = |n| date + Duration::days(n);
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        let pred_id = add_rx(&db, "prednisone").await?.id();
+
+        // A pick-up logged for the wrong prescription
+        let request_id = record_fill_request(&db, amox_id, day(0)).await?;
+        record_pickup(&db, amox_id, Some(day(3)), day(3)).await?;
+        let pickup = list_events(&db, amox_id, ..).await?.pop().unwrap();
+        let correction = void_event(&db, pickup.id, "wrong prescription").await?;
+        assert_eq!(
+            void_event(&db, pickup.id, "").await,
+            Err(Error::EventVoided(pickup.id))
+        );
+        assert_eq!(
+            amend_event(&db, pickup.id, day(4), "").await,
+            Err(Error::EventVoided(pickup.id))
+        );
+
+        // The request is open again, both ways of reconstructing it
+        assert_eq!(
+            get_fill_state(&db, amox_id).await?,
+            FillState::ReadyForPickup {
+                date_filled: day(3)
+            }
+        );
+        let implicit = implicit_fill_request::list_fill_requests(&db, amox_id).await?;
+        assert_eq!(implicit.len(), 1);
+        assert!(implicit[0].date_picked_up().is_none());
+        assert!(find_legacy_mismatches(&db).await?.is_empty());
+        assert_eq!(run_out_date(&db, amox_id).await?, None);
+
+        let corrections = list_corrections(&db, amox_id).await?;
+        assert_eq!(corrections[0].id, correction);
+        assert!(corrections[0].voided());
+        assert_eq!(corrections[0].original_date, day(3));
+        assert_eq!(corrections[0].fill_request_id, Some(request_id));
+
+        // Voiding a cancellation that was followed by a new request leaves it superseded
+        let pred_request = record_fill_request(&db, pred_id, day(0)).await?;
+        cancel_fill_request(&db, pred_id, day(1), "not needed").await?;
+        let pred_request_2 = record_fill_request(&db, pred_id, day(5)).await?;
+        void_fill_request_step(&db, pred_request, EventType::RefillCancel, "").await?;
+        let pred = list_fill_requests(&db, pred_id).await?;
+        assert_eq!(pred[0].close_reason(), Some(CloseReason::Superseded));
+        assert!(pred[0].date_cancelled().is_none());
+        assert!(pred[0].cancel_reason().is_none());
+        assert_eq!(pred[1].fill_request_id(), pred_request_2);
+        assert!(list_events(&db, pred_id, ..)
+            .await?
+            .iter()
+            .all(|event| event.event_type != EventType::RefillCancel));
+        assert_eq!(
+            void_fill_request_step(&db, FillRequestId::from(42), EventType::Fill, "").await,
+            Err(Error::NoSuchFillRequest(FillRequestId::from(42)))
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_same_day_requests() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let day = |n| date + Duration::days(n);
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+
+        // Requested, cancelled, and requested again, all on the same day
+        let first = record_fill_request(&db, amox_id, day(0)).await?;
+        cancel_fill_request(&db, amox_id, day(0), "wrong strength").await?;
+        let second = record_fill_request(&db, amox_id, day(0)).await?;
+        let events = list_events(&db, amox_id, ..).await?;
+        let first_event = events[0].id;
+        let second_event = events[2].id;
+
+        // Each correction applies to its own request and event, not the latest on that date
+        let correction =
+            amend_fill_request_step(&db, first, EventType::RequestFill, day(-1), "").await?;
+        let corrections = list_corrections(&db, amox_id).await?;
+        assert_eq!(corrections[0].id, correction);
+        assert_eq!(corrections[0].event_id, Some(first_event));
+        let requests = list_fill_requests(&db, amox_id).await?;
+        assert_eq!(*requests[0].date_requested(), Some(day(-1)));
+        assert_eq!(*requests[1].date_requested(), Some(day(0)));
+
+        amend_event(&db, second_event, day(1), "").await?;
+        let corrections = list_corrections(&db, amox_id).await?;
+        assert_eq!(corrections[1].fill_request_id, Some(second));
+        let requests = list_fill_requests(&db, amox_id).await?;
+        assert_eq!(*requests[0].date_requested(), Some(day(-1)));
+        assert_eq!(*requests[1].date_requested(), Some(day(1)));
+        assert!(find_legacy_mismatches(&db).await?.is_empty());
+        Ok(())
+    }
+}
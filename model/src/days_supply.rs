@@ -0,0 +1,162 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Days supply (duration) of each rx, with history so that changes only apply going forward.
+
+use sea_orm::{
+    prelude::TimeDate, ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait,
+    EntityTrait, QueryFilter, QueryOrder,
+};
+use time::Duration;
+
+use crate::{entities::rx_days_supply, Error, RxId};
+
+/// The number of days a single fill of an rx lasts, starting from some date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DaysSupply {
+    pub effective_date: TimeDate,
+    pub days: u32,
+}
+
+impl DaysSupply {
+    pub fn duration(&self) -> Duration {
+        Duration::days(self.days.into())
+    }
+}
+
+impl From<rx_days_supply::Model> for DaysSupply {
+    fn from(value: rx_days_supply::Model) -> Self {
+        DaysSupply {
+            effective_date: value.effective_date,
+            days: u32::try_from(value.days_supply).unwrap_or_default(),
+        }
+    }
+}
+
+/// Set the days supply of an rx for fills on or after the effective date.
+///
+/// Replaces any days supply previously set with the same effective date.
+pub async fn set_days_supply(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    days: u32,
+    effective_date: TimeDate,
+) -> Result<(), Error> {
+    let days_supply = match i32::try_from(days) {
+        Ok(d) if d > 0 => d,
+        _ => return Err(Error::InvalidDaysSupply(days)),
+    };
+    let existing = rx_days_supply::Entity::find()
+        .filter(rx_days_supply::Column::RxId.eq(rx.0))
+        .filter(rx_days_supply::Column::EffectiveDate.eq(effective_date))
+        .one(db)
+        .await?;
+    let mut model: rx_days_supply::ActiveModel = match existing {
+        Some(existing) => existing.into(),
+        None => rx_days_supply::ActiveModel {
+            rx_id: Set(rx.0),
+            effective_date: Set(effective_date),
+            ..Default::default()
+        },
+    };
+    model.days_supply = Set(days_supply);
+    model.save(db).await?;
+    Ok(())
+}
+
+/// List the days supply history of an rx, oldest first.
+pub async fn list_days_supply(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+) -> Result<Vec<DaysSupply>, Error> {
+    let result = rx_days_supply::Entity::find()
+        .filter(rx_days_supply::Column::RxId.eq(rx.0))
+        .order_by_asc(rx_days_supply::Column::EffectiveDate)
+        .all(db)
+        .await?;
+    Ok(result.into_iter().map(DaysSupply::from).collect())
+}
+
+/// Find the days supply that applies to a fill on the given date, from a history sorted oldest first.
+///
+/// The oldest entry also applies to any fills before it, since nothing else is known about them.
+pub fn days_supply_on(history: &[DaysSupply], date: TimeDate) -> Option<DaysSupply> {
+    history
+        .iter()
+        .rev()
+        .find(|supply| supply.effective_date <= date)
+        .or_else(|| history.first())
+        .copied()
+}
+
+/// Get the days supply that applies to a fill of an rx on the given date, if known.
+pub async fn get_days_supply(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    date: TimeDate,
+) -> Result<Option<DaysSupply>, Error> {
+    let history = list_days_supply(db, rx).await?;
+    Ok(days_supply_on(&history, date))
+}
+
+#[cfg(test)]
+mod test {
+
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::Database;
+    use time::{Date, Month};
+
+    use super::*;
+    use crate::rx::add_rx;
+
+    #[async_std::test]
+    async fn test_days_supply() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let later = date + Duration::days(60);
+
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        let pred_id = add_rx(&db, "prednisone").await?.id();
+
+        assert_eq!(get_days_supply(&db, amox_id, date).await?, None);
+        assert_eq!(
+            set_days_supply(&db, amox_id, 0, date).await,
+            Err(Error::InvalidDaysSupply(0))
+        );
+
+        set_days_supply(&db, amox_id, 10, date).await?;
+        set_days_supply(&db, amox_id, 30, date).await?;
+        set_days_supply(&db, amox_id, 90, later).await?;
+        set_days_supply(&db, pred_id, 5, date).await?;
+
+        let history = list_days_supply(&db, amox_id).await?;
+        assert_eq!(
+            history,
+            vec![
+                DaysSupply {
+                    effective_date: date,
+                    days: 30
+                },
+                DaysSupply {
+                    effective_date: later,
+                    days: 90
+                }
+            ]
+        );
+        let days_on = |d| days_supply_on(&history, d).map(|supply| supply.days);
+        assert_eq!(days_on(date - Duration::days(100)), Some(30));
+        assert_eq!(days_on(date), Some(30));
+        assert_eq!(days_on(later.previous_day().unwrap()), Some(30));
+        assert_eq!(days_on(later), Some(90));
+        assert_eq!(days_on(later + Duration::days(100)), Some(90));
+
+        assert_eq!(
+            get_days_supply(&db, pred_id, later)
+                .await?
+                .map(|supply| supply.duration()),
+            Some(Duration::days(5))
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,240 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! How fast an rx is used, and how much is dispensed at each pick-up,
+//! to project when it runs out, carrying over what is left when a refill comes early.
+
+use std::fmt::Display;
+
+use sea_orm::{
+    prelude::TimeDate, sea_query::Expr, ActiveModelTrait, ActiveValue::Set, ColumnTrait,
+    ConnectionTrait, EntityTrait, QueryFilter,
+};
+use time::Duration;
+
+use crate::{
+    corrections::find_step_event,
+    entities::{events, fill_request, rx_info},
+    events::Event,
+    Error, EventType, FillRequestId, RxId,
+};
+
+/// How much of an rx is used: a number of units every so many days, e.g. 2 every day or 1 every 2 days.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DoseRate {
+    pub units: u32,
+    pub every_days: u32,
+}
+
+impl DoseRate {
+    pub fn daily(units: u32) -> Self {
+        DoseRate {
+            units,
+            every_days: 1,
+        }
+    }
+
+    /// The whole number of days a quantity lasts at this rate.
+    pub fn days_for(&self, quantity: u32) -> u32 {
+        let days = u64::from(quantity) * u64::from(self.every_days) / u64::from(self.units.max(1));
+        days.try_into().unwrap_or(u32::MAX)
+    }
+
+    /// Read the dose rate columns of an rx, if both are known and valid.
+    pub(crate) fn from_columns(units: Option<i32>, every_days: Option<i32>) -> Option<DoseRate> {
+        let units = u32::try_from(units?).ok().filter(|&units| units > 0)?;
+        let every_days = u32::try_from(every_days?).ok().filter(|&days| days > 0)?;
+        Some(DoseRate { units, every_days })
+    }
+}
+
+impl Display for DoseRate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.every_days {
+            1 => write!(f, "{} daily", self.units),
+            days => write!(f, "{} every {} days", self.units, days),
+        }
+    }
+}
+
+/// Set the dose rate of an rx, or clear it with `None`.
+pub async fn set_dose_rate(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    dose: Option<DoseRate>,
+) -> Result<(), Error> {
+    let columns = match dose {
+        Some(dose) => match (i32::try_from(dose.units), i32::try_from(dose.every_days)) {
+            (Ok(units), Ok(every_days)) if units > 0 && every_days > 0 => {
+                (Some(units), Some(every_days))
+            }
+            _ => return Err(Error::InvalidDoseRate(dose.units, dose.every_days)),
+        },
+        None => (None, None),
+    };
+    let existing = rx_info::Entity::find_by_id(i32::from(rx))
+        .one(db)
+        .await?
+        .ok_or(Error::NoSuchRx(rx))?;
+    let mut model: rx_info::ActiveModel = existing.into();
+    model.dose_units = Set(columns.0);
+    model.dose_every_days = Set(columns.1);
+    model.update(db).await?;
+    Ok(())
+}
+
+/// Record the quantity dispensed when a fill request was picked up, or clear it with `None`.
+pub async fn set_pickup_quantity(
+    db: &impl ConnectionTrait,
+    id: FillRequestId,
+    quantity: Option<u32>,
+) -> Result<(), Error> {
+    fill_request::Entity::find_by_id(i32::from(id))
+        .one(db)
+        .await?
+        .ok_or(Error::NoSuchFillRequest(id))?;
+    let event = find_step_event(db, id, EventType::PickUp)
+        .await?
+        .ok_or(Error::NoSuchFillRequestStep(id, EventType::PickUp))?;
+    let quantity = quantity.map(|quantity| i32::try_from(quantity).unwrap_or(i32::MAX));
+    events::Entity::update_many()
+        .col_expr(events::Column::Quantity, Expr::value(quantity))
+        .filter(events::Column::Id.eq(event.id))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// Project when an rx runs out from the quantities picked up and its dose rate,
+/// given its events in date order.
+///
+/// Supply left over from the previous pick-up when a refill comes early is carried over.
+/// Returns `None` if the latest pick-up has no quantity recorded.
+/// A pick-up without a quantity breaks the chain, so nothing is carried over past it.
+pub fn compute_supply_run_out(events: &[Event], dose: DoseRate) -> Option<TimeDate> {
+    let mut run_out: Option<TimeDate> = None;
+    for event in events
+        .iter()
+        .filter(|event| event.event_type == EventType::PickUp)
+    {
+        run_out = event.quantity.and_then(|quantity| {
+            let start = run_out.map_or(event.date, |previous| previous.max(event.date));
+            start.checked_add(Duration::days(dose.days_for(quantity).into()))
+        });
+    }
+    run_out
+}
+
+#[cfg(test)]
+mod test {
+
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::Database;
+    use time::{Date, Month};
+
+    use super::*;
+    use crate::{
+        events::list_events,
+        fill_request::{record_fill_request, record_pickup},
+        rx::{add_rx, get_rx},
+        EventId,
+    };
+
+    fn make_pickup(id: i32, date: Date, quantity: Option<u32>) -> Event {
+        Event {
+            id: EventId::from(id),
+            rx_id: RxId::from(1),
+            event_type: EventType::PickUp,
+            date,
+            fill_request_id: None,
+            pharmacy: None,
+            quantity,
+        }
+    }
+
+    #[test]
+    fn test_compute_supply_run_out() {
+        let date = Date::from_calendar_date(2023, Month::May, 6).unwrap();
+        let day = |n| date + Duration::days(n);
+        let twice_daily = DoseRate::daily(2);
+        assert_eq!(twice_daily.days_for(60), 30);
+        assert_eq!(
+            DoseRate {
+                units: 1,
+                every_days: 2
+            }
+            .days_for(15),
+            30
+        );
+        assert_eq!(compute_supply_run_out(&[], twice_daily), None);
+
+        let first = make_pickup(1, date, Some(60));
+        assert_eq!(
+            compute_supply_run_out(std::slice::from_ref(&first), twice_daily),
+            Some(day(30))
+        );
+        // Picked up 5 days early: the leftover 5 days carry over
+        let early = make_pickup(2, day(25), Some(60));
+        assert_eq!(
+            compute_supply_run_out(&[first.clone(), early.clone()], twice_daily),
+            Some(day(60))
+        );
+        // Picked up late: nothing carries over
+        let late = make_pickup(2, day(33), Some(60));
+        assert_eq!(
+            compute_supply_run_out(&[first.clone(), late], twice_daily),
+            Some(day(63))
+        );
+        // Unknown quantities break the chain
+        let unknown = make_pickup(2, day(25), None);
+        assert_eq!(
+            compute_supply_run_out(&[first.clone(), unknown.clone()], twice_daily),
+            None
+        );
+        assert_eq!(
+            compute_supply_run_out(&[unknown, make_pickup(3, day(28), Some(60))], twice_daily),
+            Some(day(58))
+        );
+    }
+
+    #[async_std::test]
+    async fn test_dose_and_quantity() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::May, 6).unwrap();
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        assert_eq!(get_rx(&db, amox_id).await?.unwrap().dose, None);
+
+        set_dose_rate(&db, amox_id, Some(DoseRate::daily(2))).await?;
+        assert_eq!(
+            get_rx(&db, amox_id).await?.unwrap().dose,
+            Some(DoseRate::daily(2))
+        );
+        let invalid = DoseRate {
+            units: 0,
+            every_days: 1,
+        };
+        assert_eq!(
+            set_dose_rate(&db, amox_id, Some(invalid)).await,
+            Err(Error::InvalidDoseRate(0, 1))
+        );
+
+        let requested = record_fill_request(&db, amox_id, date).await?;
+        assert_eq!(
+            set_pickup_quantity(&db, requested, Some(60)).await,
+            Err(Error::NoSuchFillRequestStep(requested, EventType::PickUp))
+        );
+        let picked_up = record_pickup(&db, amox_id, Some(date), date).await?;
+        set_pickup_quantity(&db, picked_up, Some(60)).await?;
+        let events = list_events(&db, amox_id, ..).await?;
+        assert_eq!(events.last().unwrap().quantity, Some(60));
+        assert_eq!(
+            compute_supply_run_out(&events, DoseRate::daily(2)),
+            Some(date + Duration::days(30))
+        );
+
+        set_dose_rate(&db, amox_id, None).await?;
+        assert_eq!(get_rx(&db, amox_id).await?.unwrap().dose, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,443 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Summarize which prescriptions need action.
+
+use std::collections::{BTreeMap, HashMap};
+
+use sea_orm::{prelude::TimeDate, ConnectionTrait};
+
+use crate::{
+    authorization::AuthorizationProblem,
+    fill_request::{get_fill_state, list_open_fill_requests, FillRequest, FillState},
+    inventory::{check_low_on_hand, OnHand},
+    pharmacy::{get_pharmacy, KnownPharmacy},
+    reminders::{next_reminder, Reminder},
+    renewal::{list_open_renewals, Renewal},
+    rx::{list_rx, KnownRx},
+    Error, PersonId, PharmacyId, RxId,
+};
+
+/// The visible prescriptions that need action, grouped by what to do.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DueSummary {
+    /// No open request, and a reminder has come due: request a refill now
+    pub request_now: Vec<(KnownRx, Reminder)>,
+    /// Requested (on the given date, if known), waiting on the pharmacy
+    pub waiting_on_pharmacy: Vec<(KnownRx, Option<TimeDate>)>,
+    /// Filled on the given date, ready to pick up
+    pub ready_to_pick_up: Vec<(KnownRx, TimeDate)>,
+    /// The next refill cannot happen until the script is renewed
+    pub needs_renewal: Vec<(KnownRx, AuthorizationProblem)>,
+    /// Renewal requested, waiting on the prescriber or on the new script reaching the pharmacy
+    pub renewals: Vec<(KnownRx, Renewal)>,
+    /// Critical, with fewer days on hand than it should have
+    pub low_on_hand: Vec<(KnownRx, OnHand)>,
+}
+
+impl DueSummary {
+    pub fn is_empty(&self) -> bool {
+        self.request_now.is_empty()
+            && self.waiting_on_pharmacy.is_empty()
+            && self.ready_to_pick_up.is_empty()
+            && self.needs_renewal.is_empty()
+            && self.renewals.is_empty()
+            && self.low_on_hand.is_empty()
+    }
+}
+
+/// Summarize the prescriptions that are not hidden, optionally only those of a single person,
+/// as of the given date.
+///
+/// Scripts are checked ahead of time: one that will have expired by the date of the next reminder,
+/// or has no refills left, needs renewal even if the reminder has not come due yet,
+/// unless a renewal is already open for it.
+/// Critical prescriptions are also checked for running low on hand,
+/// which catches lost or spilled units that reminders miss.
+pub async fn list_due(
+    db: &impl ConnectionTrait,
+    person: Option<PersonId>,
+    today: TimeDate,
+) -> Result<DueSummary, Error> {
+    let mut summary = DueSummary::default();
+    let mut open_renewals: HashMap<RxId, Renewal> = list_open_renewals(db, person)
+        .await?
+        .into_iter()
+        .map(|renewal| (renewal.rx_id, renewal))
+        .collect();
+    for rx in list_rx(db, person).await? {
+        if let Some(supply) = check_low_on_hand(db, &rx, today).await? {
+            summary.low_on_hand.push((rx.clone(), supply));
+        }
+        // When the next refill is expected to happen, to check the script against
+        let refill_date = match get_fill_state(db, rx.id).await? {
+            FillState::Idle => {
+                let reminder = next_reminder(db, rx.id).await?;
+                let refill_date = reminder
+                    .as_ref()
+                    .map_or(today, |reminder| reminder.date.max(today));
+                if let Some(reminder) = reminder.filter(|reminder| reminder.date <= today) {
+                    summary.request_now.push((rx.clone(), reminder));
+                }
+                Some(refill_date)
+            }
+            FillState::Requested { date_requested } => {
+                summary
+                    .waiting_on_pharmacy
+                    .push((rx.clone(), date_requested));
+                Some(today)
+            }
+            FillState::ReadyForPickup { date_filled } => {
+                // Already filled, so already authorized
+                summary.ready_to_pick_up.push((rx.clone(), date_filled));
+                None
+            }
+        };
+        if let Some(renewal) = open_renewals.remove(&rx.id) {
+            summary.renewals.push((rx, renewal));
+        } else if let Some(problem) = refill_date.and_then(|date| rx.authorization.problem_on(date))
+        {
+            summary.needs_renewal.push((rx, problem));
+        }
+    }
+    Ok(summary)
+}
+
+/// Summarize the prescriptions as in [`list_due`], grouped by the pharmacy to deal with.
+///
+/// That is the pharmacy of the open fill request of an rx, or else its default pharmacy.
+/// Groups are in pharmacy ID order, with prescriptions without a pharmacy last.
+/// Pharmacies with nothing to do are left out.
+pub async fn list_due_by_pharmacy(
+    db: &impl ConnectionTrait,
+    person: Option<PersonId>,
+    today: TimeDate,
+) -> Result<Vec<(Option<KnownPharmacy>, DueSummary)>, Error> {
+    let open_pharmacies: HashMap<RxId, Option<PharmacyId>> = list_open_fill_requests(db, person)
+        .await?
+        .iter()
+        .map(|request| (RxId::from(request.rx_id), request.pharmacy_id()))
+        .collect();
+    let pharmacy_of = |rx: &KnownRx| open_pharmacies.get(&rx.id).copied().unwrap_or(rx.pharmacy);
+
+    let summary = list_due(db, person, today).await?;
+    let mut groups: BTreeMap<Option<PharmacyId>, DueSummary> = BTreeMap::new();
+    for (rx, reminder) in summary.request_now {
+        let group = groups.entry(pharmacy_of(&rx)).or_default();
+        group.request_now.push((rx, reminder));
+    }
+    for (rx, date) in summary.waiting_on_pharmacy {
+        let group = groups.entry(pharmacy_of(&rx)).or_default();
+        group.waiting_on_pharmacy.push((rx, date));
+    }
+    for (rx, date) in summary.ready_to_pick_up {
+        let group = groups.entry(pharmacy_of(&rx)).or_default();
+        group.ready_to_pick_up.push((rx, date));
+    }
+    for (rx, problem) in summary.needs_renewal {
+        let group = groups.entry(pharmacy_of(&rx)).or_default();
+        group.needs_renewal.push((rx, problem));
+    }
+    for (rx, renewal) in summary.renewals {
+        let group = groups.entry(pharmacy_of(&rx)).or_default();
+        group.renewals.push((rx, renewal));
+    }
+    for (rx, supply) in summary.low_on_hand {
+        let group = groups.entry(pharmacy_of(&rx)).or_default();
+        group.low_on_hand.push((rx, supply));
+    }
+
+    let mut result = Vec::new();
+    let mut unassigned = None;
+    for (pharmacy, group) in groups {
+        match pharmacy {
+            Some(id) => result.push((get_pharmacy(db, id).await?, group)),
+            None => unassigned = Some(group),
+        }
+    }
+    result.extend(unassigned.map(|group| (None, group)));
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::Database;
+    use time::{Date, Duration, Month};
+
+    use super::*;
+    use crate::{
+        authorization::{set_authorization, Authorization},
+        days_supply::set_days_supply,
+        dosing::{set_dose_rate, set_pickup_quantity, DoseRate},
+        fill_request::{record_fill, record_fill_request, record_pickup},
+        inventory::{record_pill_count, set_critical_days},
+        pharmacy::{add_pharmacy, set_default_pharmacy, set_fill_request_pharmacy, Pharmacy},
+        reminder_policy::{add_reminder_policy, ReminderPolicy},
+        renewal::{approve_renewal, record_renewal_received, request_renewal},
+        rx::{add_rx, set_rx_hidden},
+        EventType, RenewalStatus, RxId,
+    };
+
+    async fn add_policy(db: &impl ConnectionTrait, rx: RxId) -> Result<(), Error> {
+        let policy = ReminderPolicy {
+            starting_event: Some(EventType::PickUp),
+            include_rx_duration: true,
+            offset_days: -7,
+            allow_saturday: true,
+            allow_sunday: true,
+            description: "Request refill".to_owned(),
+        };
+        add_reminder_policy(db, rx, &policy).await?;
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_list_due() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let today = date + Duration::days(25);
+
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        assert!(list_due(&db, None, today).await?.is_empty());
+
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        let pred_id = add_rx(&db, "prednisone").await?.id();
+        let vitd_id = add_rx(&db, "vitamin d").await?.id();
+        let ibu_id = add_rx(&db, "ibuprofen").await?.id();
+        let hidden_id = add_rx(&db, "hidden").await?.id();
+        for rx in [amox_id, ibu_id, hidden_id] {
+            add_policy(&db, rx).await?;
+            set_days_supply(&db, rx, 30, date).await?;
+        }
+
+        // Reminder 2023-01-24, due
+        record_pickup(&db, amox_id, Some(date), date).await?;
+        // Reminder 2023-01-27, not yet due
+        record_pickup(&db, ibu_id, Some(date), date + Duration::days(3)).await?;
+        record_fill_request(&db, pred_id, date).await?;
+        record_fill(&db, vitd_id, date).await?;
+        record_pickup(&db, hidden_id, Some(date), date).await?;
+        set_rx_hidden(&db, hidden_id, true).await?;
+
+        let summary = list_due(&db, None, today).await?;
+        assert_eq!(summary.request_now.len(), 1);
+        assert_eq!(summary.request_now[0].0.id, amox_id);
+        assert_eq!(summary.request_now[0].1.date, date + Duration::days(23));
+        assert_eq!(summary.waiting_on_pharmacy.len(), 1);
+        assert_eq!(summary.waiting_on_pharmacy[0].0.id, pred_id);
+        assert_eq!(summary.waiting_on_pharmacy[0].1, Some(date));
+        assert_eq!(summary.ready_to_pick_up.len(), 1);
+        assert_eq!(summary.ready_to_pick_up[0].0.id, vitd_id);
+
+        // Once requested, it is waiting rather than due
+        record_fill_request(&db, amox_id, today).await?;
+        let summary = list_due(&db, None, today).await?;
+        assert!(summary.request_now.is_empty());
+        assert_eq!(summary.waiting_on_pharmacy.len(), 2);
+        assert!(summary.needs_renewal.is_empty());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_needs_renewal() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let today = date + Duration::days(10);
+
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        let pred_id = add_rx(&db, "prednisone").await?.id();
+        let vitd_id = add_rx(&db, "vitamin d").await?.id();
+        for rx in [amox_id, pred_id, vitd_id] {
+            add_policy(&db, rx).await?;
+            set_days_supply(&db, rx, 30, date).await?;
+        }
+        // Last refill used up on pick-up the day after it was authorized
+        set_authorization(
+            &db,
+            amox_id,
+            &Authorization {
+                refills_remaining: Some(1),
+                expires: None,
+                original_fill_pending: false,
+            },
+            date - Duration::days(1),
+        )
+        .await?;
+        record_pickup(&db, amox_id, Some(date), date).await?;
+        // Expires before the next reminder on 2023-01-24, which is not due yet
+        let expires = date + Duration::days(20);
+        set_authorization(
+            &db,
+            pred_id,
+            &Authorization {
+                refills_remaining: Some(3),
+                expires: Some(expires),
+                original_fill_pending: false,
+            },
+            date,
+        )
+        .await?;
+        record_pickup(&db, pred_id, Some(date), date).await?;
+        // Fine until after the next reminder
+        set_authorization(
+            &db,
+            vitd_id,
+            &Authorization {
+                refills_remaining: Some(3),
+                expires: Some(date + Duration::days(30)),
+                original_fill_pending: false,
+            },
+            date,
+        )
+        .await?;
+        record_pickup(&db, vitd_id, Some(date), date).await?;
+
+        let summary = list_due(&db, None, today).await?;
+        assert!(summary.request_now.is_empty());
+        assert_eq!(
+            summary
+                .needs_renewal
+                .iter()
+                .map(|(rx, problem)| (rx.id, *problem))
+                .collect::<Vec<_>>(),
+            vec![
+                (amox_id, AuthorizationProblem::NoRefillsLeft),
+                (pred_id, AuthorizationProblem::Expired(expires))
+            ]
+        );
+
+        // Once a renewal is requested, it is tracked there instead
+        let renewal = request_renewal(&db, amox_id, today, None).await?;
+        let summary = list_due(&db, None, today).await?;
+        assert_eq!(summary.needs_renewal.len(), 1);
+        assert_eq!(summary.needs_renewal[0].0.id, pred_id);
+        assert_eq!(summary.renewals.len(), 1);
+        assert_eq!(summary.renewals[0].0.id, amox_id);
+        assert_eq!(summary.renewals[0].1.id, renewal);
+
+        // Approval renews the script, so nothing is needed after the new script arrives
+        let renewed = Authorization {
+            refills_remaining: Some(5),
+            expires: Some(date + Duration::days(365)),
+            original_fill_pending: true,
+        };
+        approve_renewal(&db, renewal, today, &renewed).await?;
+        assert_eq!(
+            list_due(&db, None, today).await?.renewals[0].1.status,
+            RenewalStatus::Approved
+        );
+        record_renewal_received(&db, renewal, today).await?;
+        let summary = list_due(&db, None, today).await?;
+        assert!(summary.renewals.is_empty());
+        assert_eq!(summary.needs_renewal.len(), 1);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_renewed_without_refills() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let today = date + Duration::days(10);
+
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let rx = add_rx(&db, "amoxicillin").await?.id();
+        add_policy(&db, rx).await?;
+        set_days_supply(&db, rx, 30, date).await?;
+        record_pickup(&db, rx, Some(date), date).await?;
+
+        // The original fill of a script approved without refills is still to come
+        let renewal = request_renewal(&db, rx, today, None).await?;
+        let renewed = Authorization {
+            refills_remaining: Some(0),
+            expires: None,
+            original_fill_pending: true,
+        };
+        approve_renewal(&db, renewal, today, &renewed).await?;
+        record_renewal_received(&db, renewal, today).await?;
+        assert!(list_due(&db, None, today).await?.needs_renewal.is_empty());
+
+        // Once it has been picked up, there is nothing left to refill
+        record_pickup(&db, rx, Some(today), today).await?;
+        let summary = list_due(&db, None, today).await?;
+        assert_eq!(summary.needs_renewal.len(), 1);
+        assert_eq!(
+            summary.needs_renewal[0].1,
+            AuthorizationProblem::NoRefillsLeft
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_low_on_hand() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::May, 13).unwrap();
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        add_policy(&db, amox_id).await?;
+        set_days_supply(&db, amox_id, 30, date).await?;
+        set_dose_rate(&db, amox_id, Some(DoseRate::daily(2))).await?;
+        set_critical_days(&db, amox_id, Some(7)).await?;
+        let picked_up = record_pickup(&db, amox_id, Some(date), date).await?;
+        set_pickup_quantity(&db, picked_up, Some(60)).await?;
+        assert!(list_due(&db, None, date).await?.is_empty());
+
+        // Lost some, long before the reminder is due
+        record_pill_count(&db, amox_id, date, 10).await?;
+        let summary = list_due(&db, None, date).await?;
+        assert!(summary.request_now.is_empty());
+        assert_eq!(summary.low_on_hand.len(), 1);
+        assert_eq!(summary.low_on_hand[0].0.id, amox_id);
+        assert_eq!(summary.low_on_hand[0].1, OnHand { units: 10, days: 5 });
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_list_due_by_pharmacy() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::April, 8).unwrap();
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        assert!(list_due_by_pharmacy(&db, None, date).await?.is_empty());
+
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        let pred_id = add_rx(&db, "prednisone").await?.id();
+        let vitd_id = add_rx(&db, "vitamin d").await?.id();
+        let corner = add_pharmacy(
+            &db,
+            &Pharmacy {
+                name: "Corner Drug".to_owned(),
+                ..Default::default()
+            },
+        )
+        .await?;
+        let mail = add_pharmacy(
+            &db,
+            &Pharmacy {
+                name: "Mail order".to_owned(),
+                ..Default::default()
+            },
+        )
+        .await?;
+        set_default_pharmacy(&db, amox_id, Some(corner)).await?;
+        set_default_pharmacy(&db, pred_id, Some(corner)).await?;
+
+        record_fill_request(&db, amox_id, date).await?;
+        // Sent elsewhere this time
+        let pred_request = record_fill_request(&db, pred_id, date).await?;
+        set_fill_request_pharmacy(&db, pred_request, Some(mail)).await?;
+        record_fill(&db, vitd_id, date).await?;
+
+        let groups = list_due_by_pharmacy(&db, None, date).await?;
+        let ids: Vec<_> = groups
+            .iter()
+            .map(|(pharmacy, _)| pharmacy.as_ref().map(|pharmacy| pharmacy.id))
+            .collect();
+        assert_eq!(ids, vec![Some(corner), Some(mail), None]);
+        assert_eq!(groups[0].1.waiting_on_pharmacy[0].0.id, amox_id);
+        assert_eq!(groups[1].1.waiting_on_pharmacy[0].0.id, pred_id);
+        assert_eq!(groups[2].1.ready_to_pick_up[0].0.id, vitd_id);
+        Ok(())
+    }
+}
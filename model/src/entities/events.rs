@@ -3,6 +3,7 @@
 
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.10.6
 
+use migration::EventType;
 use sea_orm::entity::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
@@ -11,12 +12,34 @@ pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
     pub rx_id: i32,
-    pub event: String,
+    pub event: EventType,
     pub date: TimeDate,
+    pub voided: bool,
+    pub fill_request_id: Option<i32>,
+    pub pharmacy_id: Option<i32>,
+    pub quantity: Option<i32>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::event_correction::Entity")]
+    EventCorrection,
+    #[sea_orm(
+        belongs_to = "super::fill_request::Entity",
+        from = "Column::FillRequestId",
+        to = "super::fill_request::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    FillRequest,
+    #[sea_orm(
+        belongs_to = "super::pharmacy::Entity",
+        from = "Column::PharmacyId",
+        to = "super::pharmacy::Column::PharmacyId",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Pharmacy,
     #[sea_orm(
         belongs_to = "super::rx_info::Entity",
         from = "Column::RxId",
@@ -27,6 +50,24 @@ pub enum Relation {
     RxInfo,
 }
 
+impl Related<super::event_correction::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::EventCorrection.def()
+    }
+}
+
+impl Related<super::fill_request::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::FillRequest.def()
+    }
+}
+
+impl Related<super::pharmacy::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Pharmacy.def()
+    }
+}
+
 impl Related<super::rx_info::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::RxInfo.def()
@@ -3,6 +3,7 @@
 
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.10.6
 
+use migration::{CloseReason, RefillUse};
 use sea_orm::entity::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
@@ -15,10 +16,27 @@ pub struct Model {
     pub date_filled: Option<TimeDate>,
     pub date_picked_up: Option<TimeDate>,
     pub closed: bool,
+    pub close_reason: Option<CloseReason>,
+    pub date_cancelled: Option<TimeDate>,
+    pub cancel_reason: Option<String>,
+    pub pharmacy_id: Option<i32>,
+    pub refill_use: RefillUse,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::event_correction::Entity")]
+    EventCorrection,
+    #[sea_orm(has_many = "super::events::Entity")]
+    Events,
+    #[sea_orm(
+        belongs_to = "super::pharmacy::Entity",
+        from = "Column::PharmacyId",
+        to = "super::pharmacy::Column::PharmacyId",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Pharmacy,
     #[sea_orm(
         belongs_to = "super::rx_info::Entity",
         from = "Column::RxId",
@@ -29,6 +47,24 @@ pub enum Relation {
     RxInfo,
 }
 
+impl Related<super::event_correction::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::EventCorrection.def()
+    }
+}
+
+impl Related<super::events::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Events.def()
+    }
+}
+
+impl Related<super::pharmacy::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Pharmacy.def()
+    }
+}
+
 impl Related<super::rx_info::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::RxInfo.def()
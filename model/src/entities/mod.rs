@@ -5,7 +5,15 @@
 
 pub mod prelude;
 
+pub mod event_correction;
 pub mod events;
 pub mod fill_request;
+pub mod imported_dispense;
+pub mod person;
+pub mod pharmacy;
+pub mod pill_count;
+pub mod prescriber;
 pub mod reminder_policy;
+pub mod renewal_request;
+pub mod rx_days_supply;
 pub mod rx_info;
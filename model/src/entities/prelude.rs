@@ -3,7 +3,15 @@
 
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.10.6
 
+pub use super::event_correction::Entity as EventCorrection;
 pub use super::events::Entity as Events;
 pub use super::fill_request::Entity as FillRequest;
+pub use super::imported_dispense::Entity as ImportedDispense;
+pub use super::person::Entity as Person;
+pub use super::pharmacy::Entity as Pharmacy;
+pub use super::pill_count::Entity as PillCount;
+pub use super::prescriber::Entity as Prescriber;
 pub use super::reminder_policy::Entity as ReminderPolicy;
+pub use super::renewal_request::Entity as RenewalRequest;
+pub use super::rx_days_supply::Entity as RxDaysSupply;
 pub use super::rx_info::Entity as RxInfo;
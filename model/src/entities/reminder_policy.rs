@@ -3,6 +3,7 @@
 
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.10.6
 
+use migration::EventType;
 use sea_orm::entity::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
@@ -11,7 +12,7 @@ pub struct Model {
     #[sea_orm(primary_key)]
     pub reminder_id: i32,
     pub rx_id: i32,
-    pub starting_date: Option<String>,
+    pub starting_date: Option<EventType>,
     pub include_rx_duration: bool,
     pub offset: i32,
     pub allow_saturday: bool,
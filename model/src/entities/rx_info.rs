@@ -12,16 +12,69 @@ pub struct Model {
     pub rx_id: i32,
     pub rx_name: String,
     pub hidden: bool,
+    pub person_id: Option<i32>,
+    pub retired_date: Option<TimeDate>,
+    pub retired_reason: Option<String>,
+    pub normalized_name: Option<String>,
+    pub pharmacy_id: Option<i32>,
+    pub prescriber_id: Option<i32>,
+    pub refills_remaining: Option<i32>,
+    pub script_expires: Option<TimeDate>,
+    pub refills_counted_from: Option<TimeDate>,
+    pub original_fill_pending: bool,
+    pub dose_units: Option<i32>,
+    pub dose_every_days: Option<i32>,
+    pub critical_days: Option<i32>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::event_correction::Entity")]
+    EventCorrection,
     #[sea_orm(has_many = "super::events::Entity")]
     Events,
     #[sea_orm(has_many = "super::fill_request::Entity")]
     FillRequest,
+    #[sea_orm(has_many = "super::imported_dispense::Entity")]
+    ImportedDispense,
+    #[sea_orm(
+        belongs_to = "super::person::Entity",
+        from = "Column::PersonId",
+        to = "super::person::Column::PersonId",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Person,
+    #[sea_orm(
+        belongs_to = "super::pharmacy::Entity",
+        from = "Column::PharmacyId",
+        to = "super::pharmacy::Column::PharmacyId",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Pharmacy,
+    #[sea_orm(
+        belongs_to = "super::prescriber::Entity",
+        from = "Column::PrescriberId",
+        to = "super::prescriber::Column::PrescriberId",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Prescriber,
+    #[sea_orm(has_many = "super::pill_count::Entity")]
+    PillCount,
     #[sea_orm(has_many = "super::reminder_policy::Entity")]
     ReminderPolicy,
+    #[sea_orm(has_many = "super::renewal_request::Entity")]
+    RenewalRequest,
+    #[sea_orm(has_many = "super::rx_days_supply::Entity")]
+    RxDaysSupply,
+}
+
+impl Related<super::event_correction::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::EventCorrection.def()
+    }
 }
 
 impl Related<super::events::Entity> for Entity {
@@ -36,10 +89,52 @@ impl Related<super::fill_request::Entity> for Entity {
     }
 }
 
+impl Related<super::imported_dispense::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ImportedDispense.def()
+    }
+}
+
+impl Related<super::person::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Person.def()
+    }
+}
+
+impl Related<super::pharmacy::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Pharmacy.def()
+    }
+}
+
+impl Related<super::prescriber::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Prescriber.def()
+    }
+}
+
+impl Related<super::pill_count::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PillCount.def()
+    }
+}
+
 impl Related<super::reminder_policy::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::ReminderPolicy.def()
     }
 }
 
+impl Related<super::renewal_request::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RenewalRequest.def()
+    }
+}
+
+impl Related<super::rx_days_supply::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RxDaysSupply.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,219 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Typed access to the generic events table.
+
+use std::ops::{Bound, RangeBounds};
+
+use sea_orm::{
+    prelude::TimeDate, ActiveValue::Set, ColumnTrait, Condition, ConnectionTrait, EntityTrait,
+    QueryFilter, QueryOrder,
+};
+
+use crate::{
+    entities::{event_correction, events},
+    Error, EventId, EventType, FillRequestId, PharmacyId, RxId,
+};
+
+/// A single dated event in the life of an rx.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub id: EventId,
+    pub rx_id: RxId,
+    pub event_type: EventType,
+    pub date: TimeDate,
+    /// The fill request this event records a step of, if any
+    pub fill_request_id: Option<FillRequestId>,
+    /// The pharmacy that handled this step, if known
+    pub pharmacy: Option<PharmacyId>,
+    /// The quantity dispensed, for a pick-up, if known
+    pub quantity: Option<u32>,
+}
+
+impl From<events::Model> for Event {
+    fn from(value: events::Model) -> Self {
+        Event {
+            id: EventId::from(value.id),
+            rx_id: RxId::from(value.rx_id),
+            event_type: value.event,
+            date: value.date,
+            fill_request_id: value.fill_request_id.map(FillRequestId::from),
+            pharmacy: value.pharmacy_id.map(PharmacyId::from),
+            quantity: value
+                .quantity
+                .and_then(|quantity| u32::try_from(quantity).ok()),
+        }
+    }
+}
+
+/// Record an event for an rx, receiving the ID.
+pub async fn record_event(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    event_type: EventType,
+    date: TimeDate,
+    pharmacy: Option<PharmacyId>,
+) -> Result<EventId, Error> {
+    insert_event(db, rx, event_type, date, pharmacy, None).await
+}
+
+/// Record an event for a step of a fill request, receiving the ID.
+///
+/// The event is linked to the fill request,
+/// so corrections to the step or the event can find the other by ID.
+pub(crate) async fn record_fill_request_event(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    event_type: EventType,
+    date: TimeDate,
+    pharmacy: Option<PharmacyId>,
+    fill_request: FillRequestId,
+) -> Result<EventId, Error> {
+    insert_event(db, rx, event_type, date, pharmacy, Some(fill_request)).await
+}
+
+async fn insert_event(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    event_type: EventType,
+    date: TimeDate,
+    pharmacy: Option<PharmacyId>,
+    fill_request: Option<FillRequestId>,
+) -> Result<EventId, Error> {
+    let event = events::ActiveModel {
+        rx_id: Set(rx.0),
+        event: Set(event_type),
+        date: Set(date),
+        pharmacy_id: Set(pharmacy.map(i32::from)),
+        fill_request_id: Set(fill_request.map(i32::from)),
+        ..Default::default()
+    };
+    let res = events::Entity::insert(event).exec(db).await?;
+    Ok(EventId::from(res.last_insert_id))
+}
+
+/// Turn a range of dates into a condition on a date column.
+pub(crate) fn date_condition(
+    col: impl ColumnTrait,
+    range: impl RangeBounds<TimeDate>,
+) -> Condition {
+    let cond = Condition::all();
+    let cond = match range.start_bound() {
+        Bound::Included(d) => cond.add(col.gte(*d)),
+        Bound::Excluded(d) => cond.add(col.gt(*d)),
+        Bound::Unbounded => cond,
+    };
+    match range.end_bound() {
+        Bound::Included(d) => cond.add(col.lte(*d)),
+        Bound::Excluded(d) => cond.add(col.lt(*d)),
+        Bound::Unbounded => cond,
+    }
+}
+
+/// List the events for an rx whose date falls in the given range, in date order.
+///
+/// Events on the same date are returned in the order they were recorded.
+/// Voided events are left out.
+/// Pass `..` to list all events.
+pub async fn list_events(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    range: impl RangeBounds<TimeDate>,
+) -> Result<Vec<Event>, Error> {
+    let result = events::Entity::find()
+        .filter(events::Column::RxId.eq(rx.0))
+        .filter(events::Column::Voided.eq(false))
+        .filter(date_condition(events::Column::Date, range))
+        .order_by_asc(events::Column::Date)
+        .order_by_asc(events::Column::Id)
+        .all(db)
+        .await?;
+    Ok(result.into_iter().map(Event::from).collect())
+}
+
+/// Delete a single event.
+///
+/// An event that was corrected is kept for the correction log to refer to:
+/// void it instead.
+pub async fn delete_event(db: &impl ConnectionTrait, id: EventId) -> Result<(), Error> {
+    let correction = event_correction::Entity::find()
+        .filter(event_correction::Column::EventId.eq(i32::from(id)))
+        .one(db)
+        .await?;
+    if correction.is_some() {
+        return Err(Error::EventHasCorrections(id));
+    }
+    let res = events::Entity::delete_by_id(i32::from(id)).exec(db).await?;
+    if res.rows_affected == 0 {
+        return Err(Error::NoSuchEvent(id));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::Database;
+    use time::{Date, Month};
+
+    use super::*;
+    use crate::{corrections::amend_event, rx::add_rx};
+
+    #[async_std::test]
+    async fn test_events() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let next_day = date.next_day().unwrap();
+
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        let pred_id = add_rx(&db, "prednisone").await?.id();
+
+        let pickup_id = record_event(&db, amox_id, EventType::PickUp, next_day, None).await?;
+        let request_id = record_event(&db, amox_id, EventType::RequestFill, date, None).await?;
+        let fill_id = record_event(&db, amox_id, EventType::Fill, next_day, None).await?;
+        record_event(&db, pred_id, EventType::RequestFill, date, None).await?;
+
+        let all = list_events(&db, amox_id, ..).await?;
+        assert_eq!(
+            all.iter().map(|e| e.id).collect::<Vec<_>>(),
+            vec![request_id, pickup_id, fill_id]
+        );
+        assert_eq!(
+            all[0],
+            Event {
+                id: request_id,
+                rx_id: amox_id,
+                event_type: EventType::RequestFill,
+                date,
+                pharmacy: None,
+                fill_request_id: None,
+                quantity: None,
+            }
+        );
+        assert_eq!(all[1].event_type, EventType::PickUp);
+
+        let later = list_events(&db, amox_id, next_day..).await?;
+        assert_eq!(later.len(), 2);
+        assert!(list_events(&db, amox_id, ..date).await?.is_empty());
+        assert_eq!(list_events(&db, amox_id, ..=date).await?.len(), 1);
+
+        delete_event(&db, pickup_id).await?;
+        assert_eq!(
+            delete_event(&db, pickup_id).await,
+            Err(Error::NoSuchEvent(pickup_id))
+        );
+        assert_eq!(list_events(&db, amox_id, ..).await?.len(), 2);
+        assert_eq!(list_events(&db, pred_id, ..).await?.len(), 1);
+
+        // A corrected event stays, for the correction log
+        amend_event(&db, fill_id, date, "").await?;
+        assert_eq!(
+            delete_event(&db, fill_id).await,
+            Err(Error::EventHasCorrections(fill_id))
+        );
+        assert_eq!(list_events(&db, amox_id, ..).await?.len(), 2);
+        Ok(())
+    }
+}
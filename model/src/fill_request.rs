@@ -1,26 +1,41 @@
 // Copyright 2022-2023, Ryan Pavlik <ryan@ryanpavlik.com>
 // SPDX-License-Identifier: GPL3+
 
-use crate::{entities::fill_request, Error, FillRequestId, RxId};
+use std::ops::{Bound, RangeBounds};
+
+use crate::{
+    authorization::use_refill,
+    entities::{fill_request, rx_info},
+    events::{date_condition, record_fill_request_event},
+    rx::{get_default_pharmacy, GetRxId},
+    CloseReason, Error, EventType, FillRequestId, PersonId, PharmacyId, RefillUse, RxId,
+};
 use sea_orm::{
     prelude::TimeDate, ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait,
-    EntityTrait, Order, QueryFilter, QueryOrder, TryIntoModel,
+    EntityTrait, Order, QueryFilter, QueryOrder, QuerySelect, TryIntoModel,
 };
 use time::Date;
 
-pub trait FillRequest {
-    fn fill_request_id(&self) -> FillRequestId;
+pub trait FillRequest: GetRxId {
     fn date_requested(&self) -> &Option<Date>;
     fn date_filled(&self) -> &Option<Date>;
     fn date_picked_up(&self) -> &Option<Date>;
     fn closed(&self) -> bool;
+    /// Why the request was closed, or `None` if it is open
+    fn close_reason(&self) -> Option<CloseReason>;
+    fn date_cancelled(&self) -> &Option<Date>;
+    fn cancel_reason(&self) -> Option<&str>;
+    /// The pharmacy that handled the request, if known
+    fn pharmacy_id(&self) -> Option<PharmacyId>;
 }
 
-impl FillRequest for fill_request::Model {
-    fn fill_request_id(&self) -> FillRequestId {
+impl fill_request::Model {
+    pub fn fill_request_id(&self) -> FillRequestId {
         self.id.into()
     }
+}
 
+impl FillRequest for fill_request::Model {
     fn date_requested(&self) -> &Option<Date> {
         &self.date_requested
     }
@@ -36,6 +51,47 @@ impl FillRequest for fill_request::Model {
     fn closed(&self) -> bool {
         self.closed
     }
+
+    fn close_reason(&self) -> Option<CloseReason> {
+        self.close_reason
+    }
+
+    fn date_cancelled(&self) -> &Option<Date> {
+        &self.date_cancelled
+    }
+
+    fn cancel_reason(&self) -> Option<&str> {
+        self.cancel_reason.as_deref()
+    }
+
+    fn pharmacy_id(&self) -> Option<PharmacyId> {
+        self.pharmacy_id.map(PharmacyId::from)
+    }
+}
+
+/// Where an rx is in the fill lifecycle, based on its open fill request (if any).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillState {
+    /// No open fill request
+    Idle,
+    /// Requested, waiting on the pharmacy
+    Requested { date_requested: Option<Date> },
+    /// Filled, ready to pick up
+    ReadyForPickup { date_filled: Date },
+}
+
+impl FillState {
+    pub fn from_open_request(request: Option<&impl FillRequest>) -> Self {
+        match request {
+            None => FillState::Idle,
+            Some(request) => match *request.date_filled() {
+                Some(date_filled) => FillState::ReadyForPickup { date_filled },
+                None => FillState::Requested {
+                    date_requested: *request.date_requested(),
+                },
+            },
+        }
+    }
 }
 
 /// Find an existing open fill request for a given rx, if any.
@@ -52,6 +108,193 @@ async fn find_existing_open_fill_request(
     Ok(request)
 }
 
+/// List all open fill requests, optionally only for the prescriptions of a single person.
+pub async fn list_open_fill_requests(
+    db: &impl ConnectionTrait,
+    person: Option<PersonId>,
+) -> Result<Vec<fill_request::Model>, Error> {
+    let query = fill_request::Entity::find().filter(fill_request::Column::Closed.eq(false));
+    let query = match person {
+        Some(person) => query
+            .inner_join(rx_info::Entity)
+            .filter(rx_info::Column::PersonId.eq(i32::from(person))),
+        None => query,
+    };
+    let requests = query
+        .order_by_asc(fill_request::Column::RxId)
+        .all(db)
+        .await?;
+    Ok(requests)
+}
+
+/// List all fill requests of an rx, oldest first.
+pub async fn list_fill_requests(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+) -> Result<Vec<fill_request::Model>, Error> {
+    FillRequestQuery::new().rx([rx]).list(db).await
+}
+
+/// Which date to sort fill requests by.
+///
+/// Requests are always sorted by ID after this, which is the order they were made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FillRequestSort {
+    /// The order the requests were made
+    #[default]
+    Id,
+    DateRequested,
+    DateFilled,
+    DatePickedUp,
+}
+
+type DateRange = (Bound<TimeDate>, Bound<TimeDate>);
+
+fn to_date_range(range: impl RangeBounds<TimeDate>) -> DateRange {
+    (range.start_bound().cloned(), range.end_bound().cloned())
+}
+
+/// Builder for queries of fill request history.
+///
+/// With no filters, lists every fill request, oldest first.
+/// Filtering on a date range excludes requests without that date.
+#[derive(Debug, Clone, Default)]
+pub struct FillRequestQuery {
+    rx: Option<Vec<RxId>>,
+    closed: Option<bool>,
+    close_reason: Option<CloseReason>,
+    requested: Option<DateRange>,
+    filled: Option<DateRange>,
+    picked_up: Option<DateRange>,
+    sort: FillRequestSort,
+    descending: bool,
+    offset: Option<u64>,
+    limit: Option<u64>,
+}
+
+impl FillRequestQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only include requests for these prescriptions.
+    pub fn rx(mut self, rx: impl IntoIterator<Item = RxId>) -> Self {
+        self.rx = Some(rx.into_iter().collect());
+        self
+    }
+
+    /// Only include open requests.
+    pub fn open(mut self) -> Self {
+        self.closed = Some(false);
+        self
+    }
+
+    /// Only include closed requests.
+    pub fn closed(mut self) -> Self {
+        self.closed = Some(true);
+        self
+    }
+
+    /// Only include requests closed for the given reason.
+    pub fn close_reason(mut self, reason: CloseReason) -> Self {
+        self.close_reason = Some(reason);
+        self
+    }
+
+    /// Only include requests made on a date in the range.
+    pub fn requested(mut self, range: impl RangeBounds<TimeDate>) -> Self {
+        self.requested = Some(to_date_range(range));
+        self
+    }
+
+    /// Only include requests filled on a date in the range.
+    pub fn filled(mut self, range: impl RangeBounds<TimeDate>) -> Self {
+        self.filled = Some(to_date_range(range));
+        self
+    }
+
+    /// Only include requests picked up on a date in the range.
+    pub fn picked_up(mut self, range: impl RangeBounds<TimeDate>) -> Self {
+        self.picked_up = Some(to_date_range(range));
+        self
+    }
+
+    /// Sort by the given date, in ascending or descending order.
+    pub fn sort(mut self, sort: FillRequestSort, order: Order) -> Self {
+        self.sort = sort;
+        self.descending = matches!(order, Order::Desc);
+        self
+    }
+
+    /// Skip this many requests, for pagination.
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Return at most this many requests, for pagination.
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Run the query.
+    pub async fn list(&self, db: &impl ConnectionTrait) -> Result<Vec<fill_request::Model>, Error> {
+        let mut query = fill_request::Entity::find();
+        if let Some(rx) = &self.rx {
+            query = query.filter(fill_request::Column::RxId.is_in(rx.iter().map(|rx| rx.0)));
+        }
+        if let Some(closed) = self.closed {
+            query = query.filter(fill_request::Column::Closed.eq(closed));
+        }
+        if let Some(reason) = self.close_reason {
+            query = query.filter(fill_request::Column::CloseReason.eq(reason));
+        }
+        for (col, range) in [
+            (fill_request::Column::DateRequested, self.requested),
+            (fill_request::Column::DateFilled, self.filled),
+            (fill_request::Column::DatePickedUp, self.picked_up),
+        ] {
+            if let Some(range) = range {
+                query = query
+                    .filter(col.is_not_null())
+                    .filter(date_condition(col, range));
+            }
+        }
+        let order = if self.descending {
+            Order::Desc
+        } else {
+            Order::Asc
+        };
+        let sort_col = match self.sort {
+            FillRequestSort::Id => None,
+            FillRequestSort::DateRequested => Some(fill_request::Column::DateRequested),
+            FillRequestSort::DateFilled => Some(fill_request::Column::DateFilled),
+            FillRequestSort::DatePickedUp => Some(fill_request::Column::DatePickedUp),
+        };
+        if let Some(sort_col) = sort_col {
+            query = query.order_by(sort_col, order.clone());
+        }
+        query = query.order_by(fill_request::Column::Id, order);
+        if let Some(offset) = self.offset {
+            // Sqlite does not accept an offset without a limit
+            query = query
+                .offset(offset)
+                .limit(self.limit.unwrap_or(i64::MAX as u64));
+        } else if let Some(limit) = self.limit {
+            query = query.limit(limit);
+        }
+        let requests = query.all(db).await?;
+        Ok(requests)
+    }
+}
+
+/// Get where an rx is in the fill lifecycle.
+pub async fn get_fill_state(db: &impl ConnectionTrait, rx: RxId) -> Result<FillState, Error> {
+    let request = find_existing_open_fill_request(db, rx).await?;
+    Ok(FillState::from_open_request(request.as_ref()))
+}
+
 /// Create a new fill request for an rx, closing any previous open one (if any).
 /// Returns the fill request ID.
 pub async fn record_fill_request(
@@ -65,29 +308,71 @@ pub async fn record_fill_request(
     if let Some(request) = existing_request {
         let mut request: fill_request::ActiveModel = request.into();
         request.closed = Set(true);
+        request.close_reason = Set(Some(CloseReason::Superseded));
         request.save(db).await?;
     }
 
+    let pharmacy = get_default_pharmacy(db, rx).await?;
     let request = fill_request::ActiveModel {
         rx_id: Set(rx.0),
         date_requested: Set(Some(request_date)),
+        pharmacy_id: Set(pharmacy.map(i32::from)),
         ..Default::default()
     };
 
     let res = fill_request::Entity::insert(request).exec(db).await?;
-    Ok(FillRequestId(res.last_insert_id))
+    let id = FillRequestId(res.last_insert_id);
+    record_fill_request_event(db, rx, EventType::RequestFill, request_date, pharmacy, id).await?;
+    Ok(id)
+}
+
+/// Records the fill of an rx, ready to pick up. If there is an open fill request, it is updated.
+/// Otherwise a new fill request is created.
+/// Returns the fill request ID.
+pub async fn record_fill(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    fill_date: TimeDate,
+) -> Result<FillRequestId, Error> {
+    let existing_request = find_existing_open_fill_request(db, rx).await?;
+
+    let mut request: fill_request::ActiveModel = match existing_request {
+        Some(request) => request.into(),
+        None => fill_request::ActiveModel {
+            rx_id: Set(rx.0),
+            pharmacy_id: Set(get_default_pharmacy(db, rx).await?.map(i32::from)),
+            ..Default::default()
+        },
+    };
+    request.date_filled = Set(Some(fill_date));
+
+    let request: fill_request::Model = request.save(db).await?.try_into_model()?;
+    let id = FillRequestId(request.id);
+    let pharmacy = request.pharmacy_id();
+    record_fill_request_event(db, rx, EventType::Fill, fill_date, pharmacy, id).await?;
+
+    Ok(id)
 }
 
-/// Records the fill and pick-up of an rx. If there is an open fill request, it is updated and closed.
+/// Records the pick-up of an rx. If there is an open fill request, it is updated and closed.
 /// Otherwise a new fill request is created and closed.
+/// A request that was already filled keeps its fill date.
+/// Otherwise it is filled on `fill_date`, or on the pick-up date if that is `None`.
+/// A refill is counted against the script of the rx, if its remaining refills are known
+/// and the pick-up is after the script was authorized
+/// (see [`set_authorization`](crate::authorization::set_authorization)).
 /// Returns the fill request ID.
 pub async fn record_pickup(
     db: &impl ConnectionTrait,
     rx: RxId,
-    fill_date: TimeDate,
+    fill_date: Option<TimeDate>,
     pickup_date: TimeDate,
 ) -> Result<FillRequestId, Error> {
     let existing_request = find_existing_open_fill_request(db, rx).await?;
+    let new_fill_date = match existing_request.as_ref().and_then(|r| r.date_filled) {
+        Some(_) => None,
+        None => Some(fill_date.unwrap_or(pickup_date)),
+    };
 
     let mut request: fill_request::ActiveModel = match existing_request {
         // Closing an existing request
@@ -95,17 +380,86 @@ pub async fn record_pickup(
         // Making and closing a new request
         None => fill_request::ActiveModel {
             rx_id: Set(rx.0),
+            pharmacy_id: Set(get_default_pharmacy(db, rx).await?.map(i32::from)),
             ..Default::default()
         },
     };
 
-    request.date_filled = Set(Some(fill_date));
+    if new_fill_date.is_some() {
+        request.date_filled = Set(new_fill_date);
+    }
     request.date_picked_up = Set(Some(pickup_date));
     request.closed = Set(true);
+    request.close_reason = Set(Some(CloseReason::Completed));
+    request.refill_use = Set(use_refill(db, rx, pickup_date).await?);
 
     let request: fill_request::Model = request.save(db).await?.try_into_model()?;
+    let id = FillRequestId(request.id);
+    let pharmacy = request.pharmacy_id();
+    if let Some(fill_date) = new_fill_date {
+        record_fill_request_event(db, rx, EventType::Fill, fill_date, pharmacy, id).await?;
+    }
+    record_fill_request_event(db, rx, EventType::PickUp, pickup_date, pharmacy, id).await?;
 
-    Ok(FillRequestId(request.id))
+    Ok(id)
+}
+
+/// Records a past dispense of an rx, e.g. from an imported history,
+/// as a fill request of its own that is already closed.
+/// Any open fill request is left alone,
+/// and no refill is counted, since the refills recorded for the script may already reflect it.
+/// Returns the fill request ID.
+pub async fn record_historical_fill_request(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    fill_date: Option<TimeDate>,
+    pickup_date: Option<TimeDate>,
+) -> Result<FillRequestId, Error> {
+    let pharmacy = get_default_pharmacy(db, rx).await?;
+    let request = fill_request::ActiveModel {
+        rx_id: Set(rx.0),
+        date_filled: Set(fill_date.or(pickup_date)),
+        date_picked_up: Set(pickup_date),
+        closed: Set(true),
+        close_reason: Set(Some(CloseReason::Completed)),
+        pharmacy_id: Set(pharmacy.map(i32::from)),
+        refill_use: Set(RefillUse::Imported),
+        ..Default::default()
+    };
+    let res = fill_request::Entity::insert(request).exec(db).await?;
+    let id = FillRequestId(res.last_insert_id);
+    if let Some(fill_date) = fill_date.or(pickup_date) {
+        record_fill_request_event(db, rx, EventType::Fill, fill_date, pharmacy, id).await?;
+    }
+    if let Some(pickup_date) = pickup_date {
+        record_fill_request_event(db, rx, EventType::PickUp, pickup_date, pharmacy, id).await?;
+    }
+    Ok(id)
+}
+
+/// Cancels the open fill request of an rx, closing it without a pick-up or a newer request.
+/// Returns the fill request ID.
+pub async fn cancel_fill_request(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    cancel_date: TimeDate,
+    reason: &str,
+) -> Result<FillRequestId, Error> {
+    let request = find_existing_open_fill_request(db, rx)
+        .await?
+        .ok_or(Error::NoOpenFillRequest(rx))?;
+    let id = FillRequestId(request.id);
+    let pharmacy = request.pharmacy_id();
+
+    let mut request: fill_request::ActiveModel = request.into();
+    request.closed = Set(true);
+    request.close_reason = Set(Some(CloseReason::Cancelled));
+    request.date_cancelled = Set(Some(cancel_date));
+    request.cancel_reason = Set(Some(reason.trim().to_owned()));
+    request.save(db).await?;
+    record_fill_request_event(db, rx, EventType::RefillCancel, cancel_date, pharmacy, id).await?;
+
+    Ok(id)
 }
 
 #[cfg(test)]
@@ -113,16 +467,25 @@ mod test {
 
     use migration::{Migrator, MigratorTrait};
     use sea_orm::{
-        ConnectionTrait, Database, DatabaseBackend, MockDatabase, Transaction, Value::Bool,
-        Value::*,
+        ActiveEnum, ActiveValue::Set, ConnectionTrait, Database, DatabaseBackend, EntityTrait,
+        MockDatabase, Order, QueryOrder, Transaction, Value::Bool, Value::*,
     };
-    use time::{Date, Month};
+    use time::{Date, Duration, Month};
 
     use crate::{
-        entities::fill_request, fill_request::FillRequest, rx::add_rx, Error, FillRequestId, RxId,
+        entities::{events, fill_request, imported_dispense, rx_info},
+        fill_request::FillRequest,
+        implicit_fill_request::find_legacy_mismatches,
+        person::add_person,
+        rx::{add_person_rx, add_rx, GetRxId},
+        CloseReason, Error, EventType, FillRequestId, RefillUse, RxId,
     };
 
-    use super::{find_existing_open_fill_request, record_fill_request};
+    use super::{
+        cancel_fill_request, find_existing_open_fill_request, get_fill_state, list_fill_requests,
+        list_open_fill_requests, record_fill, record_fill_request, record_pickup, FillRequestQuery,
+        FillRequestSort, FillState,
+    };
 
     // async fn setup_schema(db: &impl ConnectionTrait) -> Result<(), Error> {
     //     let schema = Schema::new(DatabaseBackend::Sqlite);
@@ -144,8 +507,8 @@ mod test {
     async fn make_inmemory_db() -> Result<Fixture<impl ConnectionTrait>, Error> {
         let db = Database::connect("sqlite::memory:").await?;
         Migrator::up(&db, None).await?;
-        let amox_id = add_rx(&db, "amoxicillin").await?;
-        let pred_id = add_rx(&db, "prednisone").await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        let pred_id = add_rx(&db, "prednisone").await?.id();
         Ok(Fixture {
             db,
             amox_id,
@@ -179,6 +542,10 @@ mod test {
         assert_eq!(*existing_2.date_requested(), date.next_day());
         assert!(existing_2.date_filled().is_none());
         assert!(existing_2.date_picked_up().is_none());
+        // Other rx unaffected
+        assert!(find_existing_open_fill_request(&db, pred_id)
+            .await?
+            .is_none());
         Ok(())
     }
 
@@ -203,6 +570,368 @@ mod test {
         assert_eq!(*existing_2.date_requested(), date.next_day());
         assert!(existing_2.date_filled().is_none());
         assert!(existing_2.date_picked_up().is_none());
+        // Other rx unaffected
+        assert!(find_existing_open_fill_request(&db, pred_id)
+            .await?
+            .is_none());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_list_open_fill_requests() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let Fixture {
+            db,
+            amox_id,
+            pred_id,
+        } = make_inmemory_db().await?;
+        let alice = add_person(&db, "Alice").await?;
+        let bob = add_person(&db, "Bob").await?;
+        let alice_amox_id = add_person_rx(&db, alice, "amoxicillin").await?.id();
+        let bob_amox_id = add_person_rx(&db, bob, "amoxicillin").await?.id();
+
+        record_fill_request(&db, amox_id, date).await?;
+        record_pickup(&db, pred_id, Some(date), date).await?;
+        let alice_request = record_fill_request(&db, alice_amox_id, date).await?;
+        let bob_request = record_fill_request(&db, bob_amox_id, date).await?;
+
+        let all: Vec<RxId> = list_open_fill_requests(&db, None)
+            .await?
+            .into_iter()
+            .map(RxId::from)
+            .collect();
+        assert_eq!(all, vec![amox_id, alice_amox_id, bob_amox_id]);
+
+        let alice_open = list_open_fill_requests(&db, Some(alice)).await?;
+        assert_eq!(alice_open.len(), 1);
+        assert_eq!(alice_open[0].fill_request_id(), alice_request);
+
+        let bob_open = list_open_fill_requests(&db, Some(bob)).await?;
+        assert_eq!(bob_open.len(), 1);
+        assert_eq!(bob_open[0].fill_request_id(), bob_request);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_fill_request_query() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let day = |n| date + time::Duration::days(n);
+        let Fixture {
+            db,
+            amox_id,
+            pred_id,
+        } = make_inmemory_db().await?;
+        let vitd_id = add_rx(&db, "vitamin d").await?.id();
+
+        let amox_1 = record_pickup(&db, amox_id, Some(day(0)), day(1)).await?;
+        let amox_2 = record_fill_request(&db, amox_id, day(28)).await?;
+        record_fill(&db, amox_id, day(29)).await?;
+        let amox_3 = record_pickup(&db, amox_id, Some(day(29)), day(31)).await?;
+        assert_eq!(amox_2, amox_3);
+        let pred_1 = record_fill_request(&db, pred_id, day(5)).await?;
+        let vitd_1 = record_pickup(&db, vitd_id, Some(day(10)), day(10)).await?;
+
+        let ids = |requests: Vec<fill_request::Model>| -> Vec<FillRequestId> {
+            requests.iter().map(|r| r.fill_request_id()).collect()
+        };
+
+        let amox = list_fill_requests(&db, amox_id).await?;
+        assert_eq!(ids(amox), vec![amox_1, amox_2]);
+        assert!(list_fill_requests(&db, RxId(42)).await?.is_empty());
+
+        let all = FillRequestQuery::new().list(&db).await?;
+        assert_eq!(ids(all), vec![amox_1, amox_2, pred_1, vitd_1]);
+
+        let amox_and_pred = FillRequestQuery::new()
+            .rx([amox_id, pred_id])
+            .list(&db)
+            .await?;
+        assert_eq!(amox_and_pred.len(), 3);
+        assert!(amox_and_pred
+            .iter()
+            .all(|r| r.rx_id() == amox_id || r.rx_id() == pred_id));
+
+        let open = FillRequestQuery::new().open().list(&db).await?;
+        assert_eq!(ids(open), vec![pred_1]);
+        let closed = FillRequestQuery::new().closed().list(&db).await?;
+        assert_eq!(closed.len(), 3);
+
+        // Requests without a request date are excluded by a requested range
+        let requested = FillRequestQuery::new()
+            .requested(day(0)..=day(28))
+            .list(&db)
+            .await?;
+        assert_eq!(ids(requested), vec![amox_2, pred_1]);
+        let filled = FillRequestQuery::new().filled(day(10)..).list(&db).await?;
+        assert_eq!(ids(filled), vec![amox_2, vitd_1]);
+        let picked_up = FillRequestQuery::new()
+            .picked_up(..day(31))
+            .list(&db)
+            .await?;
+        assert_eq!(ids(picked_up), vec![amox_1, vitd_1]);
+
+        let by_pickup = FillRequestQuery::new()
+            .closed()
+            .sort(FillRequestSort::DatePickedUp, Order::Desc)
+            .list(&db)
+            .await?;
+        assert_eq!(ids(by_pickup), vec![amox_2, vitd_1, amox_1]);
+
+        // Pagination
+        let query = FillRequestQuery::new().sort(FillRequestSort::Id, Order::Desc);
+        let first_page = query.clone().limit(3).list(&db).await?;
+        assert_eq!(ids(first_page), vec![vitd_1, pred_1, amox_2]);
+        let second_page = query.clone().offset(3).limit(3).list(&db).await?;
+        assert_eq!(ids(second_page), vec![amox_1]);
+        let rest = query.offset(1).list(&db).await?;
+        assert_eq!(rest.len(), 3);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_close_reason() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let day = |n| date + Duration::days(n);
+        let Fixture {
+            db,
+            amox_id,
+            pred_id,
+        } = make_inmemory_db().await?;
+
+        let superseded = record_fill_request(&db, amox_id, day(0)).await?;
+        let completed = record_fill_request(&db, amox_id, day(2)).await?;
+        record_pickup(&db, amox_id, Some(day(3)), day(4)).await?;
+        let cancelled = record_fill_request(&db, amox_id, day(30)).await?;
+        cancel_fill_request(&db, amox_id, day(31), "switched pharmacy").await?;
+        let open = record_fill_request(&db, pred_id, day(5)).await?;
+
+        let amox = list_fill_requests(&db, amox_id).await?;
+        let reasons: Vec<_> = amox.iter().map(|r| r.close_reason()).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                Some(CloseReason::Superseded),
+                Some(CloseReason::Completed),
+                Some(CloseReason::Cancelled)
+            ]
+        );
+        assert!(amox[0].date_cancelled().is_none());
+        assert!(amox[0].cancel_reason().is_none());
+        assert_eq!(*amox[2].date_cancelled(), Some(day(31)));
+        assert_eq!(amox[2].cancel_reason(), Some("switched pharmacy"));
+        let pred = list_fill_requests(&db, pred_id).await?;
+        assert_eq!(pred[0].close_reason(), None);
+
+        let ids = |requests: Vec<fill_request::Model>| -> Vec<FillRequestId> {
+            requests.iter().map(|r| r.fill_request_id()).collect()
+        };
+        for (reason, id) in [
+            (CloseReason::Superseded, superseded),
+            (CloseReason::Completed, completed),
+            (CloseReason::Cancelled, cancelled),
+        ] {
+            let found = FillRequestQuery::new()
+                .close_reason(reason)
+                .list(&db)
+                .await?;
+            assert_eq!(ids(found), vec![id]);
+        }
+        assert_eq!(
+            ids(FillRequestQuery::new().open().list(&db).await?),
+            vec![open]
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_close_reason_migration() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let day = |n| Some(date + Duration::days(n));
+        let db = Database::connect("sqlite::memory:").await?;
+        // Everything before close reasons
+        let before = Migrator::steps_before("m20230325_000001_close_reason").unwrap();
+        Migrator::up(&db, Some(before)).await?;
+        rx_info::Entity::insert(rx_info::ActiveModel {
+            rx_id: Set(1),
+            rx_name: Set("amoxicillin".to_owned()),
+            ..Default::default()
+        })
+        .exec(&db)
+        .await?;
+        // Superseded, picked up, cancelled before the next request, superseded,
+        // then the last request, cancelled without a cancel event
+        for (date_requested, date_picked_up) in [
+            (day(0), None),
+            (day(2), day(4)),
+            (day(30), None),
+            (day(40), None),
+            (day(50), None),
+        ] {
+            fill_request::Entity::insert(fill_request::ActiveModel {
+                rx_id: Set(1),
+                date_requested: Set(date_requested),
+                date_picked_up: Set(date_picked_up),
+                closed: Set(true),
+                ..Default::default()
+            })
+            .exec(&db)
+            .await?;
+        }
+        // Dated on the next request, as backfilled for a superseded request, then a real cancel
+        for date in [day(2), day(35)] {
+            events::Entity::insert(events::ActiveModel {
+                rx_id: Set(1),
+                event: Set(EventType::RefillCancel),
+                date: Set(date.unwrap()),
+                ..Default::default()
+            })
+            .exec(&db)
+            .await?;
+        }
+        // An imported dispense without a pick-up date, then a fill that was never picked up
+        rx_info::Entity::insert(rx_info::ActiveModel {
+            rx_id: Set(2),
+            rx_name: Set("prednisone".to_owned()),
+            ..Default::default()
+        })
+        .exec(&db)
+        .await?;
+        for date_filled in [day(10), day(20)] {
+            fill_request::Entity::insert(fill_request::ActiveModel {
+                rx_id: Set(2),
+                date_filled: Set(date_filled),
+                closed: Set(true),
+                ..Default::default()
+            })
+            .exec(&db)
+            .await?;
+        }
+        imported_dispense::Entity::insert(imported_dispense::ActiveModel {
+            rx_id: Set(2),
+            import_key: Set(format!("prednisone|123456|{}|", day(10).unwrap())),
+            ..Default::default()
+        })
+        .exec(&db)
+        .await?;
+
+        Migrator::up(&db, None).await?;
+        let requests = fill_request::Entity::find()
+            .order_by_asc(fill_request::Column::Id)
+            .all(&db)
+            .await?;
+        let reasons: Vec<_> = requests
+            .iter()
+            .map(|r| (r.close_reason, r.date_cancelled))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                (Some(CloseReason::Superseded), None),
+                (Some(CloseReason::Completed), None),
+                (Some(CloseReason::Cancelled), day(35)),
+                (Some(CloseReason::Superseded), None),
+                (Some(CloseReason::Cancelled), None),
+                (Some(CloseReason::Completed), None),
+                (Some(CloseReason::Cancelled), None),
+            ]
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_fill_lifecycle() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let Fixture {
+            db,
+            amox_id,
+            pred_id,
+        } = make_inmemory_db().await?;
+
+        assert_eq!(get_fill_state(&db, amox_id).await?, FillState::Idle);
+        assert_eq!(
+            cancel_fill_request(&db, amox_id, date, "").await,
+            Err(Error::NoOpenFillRequest(amox_id))
+        );
+
+        let request_id = record_fill_request(&db, amox_id, date).await?;
+        assert_eq!(
+            get_fill_state(&db, amox_id).await?,
+            FillState::Requested {
+                date_requested: Some(date)
+            }
+        );
+        assert_eq!(
+            record_fill(&db, amox_id, date.next_day().unwrap()).await?,
+            request_id
+        );
+        assert_eq!(
+            get_fill_state(&db, amox_id).await?,
+            FillState::ReadyForPickup {
+                date_filled: date.next_day().unwrap()
+            }
+        );
+        assert_eq!(
+            record_pickup(&db, amox_id, None, date.next_day().unwrap()).await?,
+            request_id
+        );
+        assert_eq!(get_fill_state(&db, amox_id).await?, FillState::Idle);
+
+        let pred_request_id = record_fill_request(&db, pred_id, date).await?;
+        assert_eq!(
+            cancel_fill_request(&db, pred_id, date, " no longer needed ").await?,
+            pred_request_id
+        );
+        assert_eq!(get_fill_state(&db, pred_id).await?, FillState::Idle);
+        let pred = list_fill_requests(&db, pred_id).await?;
+        assert_eq!(pred[0].close_reason(), Some(CloseReason::Cancelled));
+        assert_eq!(*pred[0].date_cancelled(), Some(date));
+        assert_eq!(pred[0].cancel_reason(), Some("no longer needed"));
+
+        // Events are recorded to match
+        assert!(find_legacy_mismatches(&db).await?.is_empty());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_pickup_keeps_fill_date() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let day = |n| date + Duration::days(n);
+        let Fixture {
+            db,
+            amox_id,
+            pred_id,
+        } = make_inmemory_db().await?;
+
+        let request_id = record_fill_request(&db, amox_id, day(0)).await?;
+        record_fill(&db, amox_id, day(2)).await?;
+        assert_eq!(record_pickup(&db, amox_id, None, day(9)).await?, request_id);
+
+        let requests = list_fill_requests(&db, amox_id).await?;
+        assert_eq!(requests.len(), 1);
+        assert_eq!(*requests[0].date_filled(), Some(day(2)));
+        assert_eq!(*requests[0].date_picked_up(), Some(day(9)));
+        let event_types: Vec<EventType> = events::Entity::find()
+            .order_by_asc(events::Column::Id)
+            .all(&db)
+            .await?
+            .into_iter()
+            .map(|event| event.event)
+            .collect();
+        assert_eq!(
+            event_types,
+            vec![EventType::RequestFill, EventType::Fill, EventType::PickUp]
+        );
+
+        // Nothing filled yet: filled on the pick-up date unless given
+        record_fill_request(&db, pred_id, day(0)).await?;
+        record_pickup(&db, pred_id, None, day(3)).await?;
+        let requests = list_fill_requests(&db, pred_id).await?;
+        assert_eq!(*requests[0].date_filled(), Some(day(3)));
+        record_pickup(&db, pred_id, Some(day(4)), day(5)).await?;
+        let requests = list_fill_requests(&db, pred_id).await?;
+        assert_eq!(*requests[1].date_filled(), Some(day(4)));
+
+        assert!(find_legacy_mismatches(&db).await?.is_empty());
         Ok(())
     }
 
@@ -214,17 +943,48 @@ mod test {
         // };
         let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
         let db = MockDatabase::new(DatabaseBackend::Postgres)
-            .append_query_results(vec![
-                vec![],
-                vec![fill_request::Model {
-                    id: 1,
-                    rx_id: 5,
-                    date_requested: Some(date),
-                    date_filled: None,
-                    date_picked_up: None,
-                    closed: false,
-                }],
-            ])
+            .append_query_results(vec![Vec::<fill_request::Model>::new()])
+            .append_query_results(vec![vec![rx_info::Model {
+                rx_id: 5,
+                rx_name: "fake".to_owned(),
+                hidden: false,
+                person_id: None,
+                retired_date: None,
+                retired_reason: None,
+                normalized_name: None,
+                pharmacy_id: None,
+                prescriber_id: None,
+                refills_remaining: None,
+                script_expires: None,
+                refills_counted_from: None,
+                original_fill_pending: false,
+                dose_units: None,
+                dose_every_days: None,
+                critical_days: None,
+            }]])
+            .append_query_results(vec![vec![fill_request::Model {
+                id: 1,
+                rx_id: 5,
+                date_requested: Some(date),
+                date_filled: None,
+                date_picked_up: None,
+                closed: false,
+                close_reason: None,
+                date_cancelled: None,
+                cancel_reason: None,
+                pharmacy_id: None,
+                refill_use: RefillUse::Nothing,
+            }]])
+            .append_query_results(vec![vec![events::Model {
+                id: 1,
+                rx_id: 5,
+                event: EventType::RequestFill,
+                date,
+                voided: false,
+                fill_request_id: Some(1),
+                pharmacy_id: None,
+                quantity: None,
+            }]])
             .into_connection();
         let result = record_fill_request(&db, RxId(5), date).await?;
         assert_eq!(result, FillRequestId(1));
@@ -237,13 +997,29 @@ mod test {
             vec![
                 Transaction::from_sql_and_values(
                     DatabaseBackend::Postgres,
-                    r#"SELECT "fill_request"."id", "fill_request"."rx_id", "fill_request"."date_requested", "fill_request"."date_filled", "fill_request"."date_picked_up", "fill_request"."closed" FROM "fill_request" WHERE "fill_request"."closed" = $1 AND "fill_request"."rx_id" = $2 ORDER BY "fill_request"."date_requested" DESC LIMIT $3"#,
+                    r#"SELECT "fill_request"."id", "fill_request"."rx_id", "fill_request"."date_requested", "fill_request"."date_filled", "fill_request"."date_picked_up", "fill_request"."closed", "fill_request"."close_reason", "fill_request"."date_cancelled", "fill_request"."cancel_reason", "fill_request"."pharmacy_id", "fill_request"."refill_use" FROM "fill_request" WHERE "fill_request"."closed" = $1 AND "fill_request"."rx_id" = $2 ORDER BY "fill_request"."date_requested" DESC LIMIT $3"#,
                     vec![Bool(Some(false)), Int(Some(5)), BigUnsigned(Some(1))]
                 ),
                 Transaction::from_sql_and_values(
                     DatabaseBackend::Postgres,
-                    r#"INSERT INTO "fill_request" ("rx_id", "date_requested") VALUES ($1, $2) RETURNING "id""#,
-                    vec![Int(Some(5)), TimeDate(Some(Box::new(date)))]
+                    r#"SELECT "rx_info"."rx_id", "rx_info"."rx_name", "rx_info"."hidden", "rx_info"."person_id", "rx_info"."retired_date", "rx_info"."retired_reason", "rx_info"."normalized_name", "rx_info"."pharmacy_id", "rx_info"."prescriber_id", "rx_info"."refills_remaining", "rx_info"."script_expires", "rx_info"."refills_counted_from", "rx_info"."original_fill_pending", "rx_info"."dose_units", "rx_info"."dose_every_days", "rx_info"."critical_days" FROM "rx_info" WHERE "rx_info"."rx_id" = $1 LIMIT $2"#,
+                    vec![Int(Some(5)), BigUnsigned(Some(1))]
+                ),
+                Transaction::from_sql_and_values(
+                    DatabaseBackend::Postgres,
+                    r#"INSERT INTO "fill_request" ("rx_id", "date_requested", "pharmacy_id") VALUES ($1, $2, $3) RETURNING "id""#,
+                    vec![Int(Some(5)), TimeDate(Some(Box::new(date))), Int(None)]
+                ),
+                Transaction::from_sql_and_values(
+                    DatabaseBackend::Postgres,
+                    r#"INSERT INTO "events" ("rx_id", "event", "date", "fill_request_id", "pharmacy_id") VALUES ($1, $2, $3, $4, $5) RETURNING "id""#,
+                    vec![
+                        Int(Some(5)),
+                        Int(Some(EventType::RequestFill.into_value())),
+                        TimeDate(Some(Box::new(date))),
+                        Int(Some(1)),
+                        Int(None)
+                    ]
                 )
             ]
         );
@@ -0,0 +1,373 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Export upcoming reminders and run-out dates as an iCalendar (RFC 5545) feed.
+
+use sea_orm::{prelude::TimeDate, ConnectionTrait};
+use time::OffsetDateTime;
+
+use crate::{
+    reminders::{list_reminders, run_out_date},
+    renewal::list_open_renewals,
+    rx::list_rx,
+    Error, PersonId, ReminderPolicyId, RenewalId, RenewalStatus, RxId,
+};
+
+/// What a calendar entry is about, which determines its UID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalendarEntryKind {
+    Reminder(ReminderPolicyId),
+    RunOut,
+    /// Following up on an open renewal request
+    RenewalFollowUp(RenewalId),
+}
+
+/// A single all-day entry for the calendar.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CalendarEntry {
+    pub rx_id: RxId,
+    pub kind: CalendarEntryKind,
+    pub date: TimeDate,
+    pub summary: String,
+}
+
+impl CalendarEntry {
+    /// A UID that stays the same as the date changes,
+    /// so calendar apps update the entry rather than duplicating it.
+    pub fn uid(&self) -> String {
+        match self.kind {
+            CalendarEntryKind::Reminder(policy) => format!(
+                "rx-{}-reminder-{}@rxtrack",
+                i32::from(self.rx_id),
+                i32::from(policy)
+            ),
+            CalendarEntryKind::RunOut => format!("rx-{}-run-out@rxtrack", i32::from(self.rx_id)),
+            CalendarEntryKind::RenewalFollowUp(renewal) => format!(
+                "rx-{}-renewal-{}@rxtrack",
+                i32::from(self.rx_id),
+                i32::from(renewal)
+            ),
+        }
+    }
+}
+
+/// Which iCalendar component to use for each entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IcsComponent {
+    /// All-day events, shown by any calendar
+    #[default]
+    Event,
+    /// To-do items with a due date, for apps that support tasks
+    Todo,
+}
+
+/// List the reminders, run-out dates, and renewal follow-ups of the prescriptions that are not hidden,
+/// optionally only those of a single person, in date order.
+///
+/// Entries dated before `from` are left out, so a feed does not fill up with
+/// reminders that are already past.
+pub async fn list_calendar_entries(
+    db: &impl ConnectionTrait,
+    person: Option<PersonId>,
+    from: TimeDate,
+) -> Result<Vec<CalendarEntry>, Error> {
+    let mut entries = vec![];
+    let renewals = list_open_renewals(db, person).await?;
+    for rx in list_rx(db, person).await? {
+        for renewal in renewals.iter().filter(|renewal| renewal.rx_id == rx.id) {
+            let Some(date) = renewal.follow_up else {
+                continue;
+            };
+            let waiting_on = match renewal.status {
+                RenewalStatus::Approved => "new script at the pharmacy",
+                _ => "renewal with the prescriber",
+            };
+            entries.push(CalendarEntry {
+                rx_id: rx.id,
+                kind: CalendarEntryKind::RenewalFollowUp(renewal.id),
+                date,
+                summary: format!("{}: Follow up on {}", rx.name, waiting_on),
+            });
+        }
+        for reminder in list_reminders(db, rx.id).await? {
+            let mut summary = format!("{}: {}", rx.name, reminder.description);
+            if let Some(warning) = reminder.warning {
+                summary.push_str(&format!(" (renew first: {})", warning));
+            }
+            entries.push(CalendarEntry {
+                rx_id: rx.id,
+                kind: CalendarEntryKind::Reminder(reminder.policy_id),
+                date: reminder.date,
+                summary,
+            });
+        }
+        if let Some(date) = run_out_date(db, rx.id).await? {
+            entries.push(CalendarEntry {
+                rx_id: rx.id,
+                kind: CalendarEntryKind::RunOut,
+                date,
+                summary: format!("{}: runs out", rx.name),
+            });
+        }
+    }
+    entries.retain(|entry| entry.date >= from);
+    entries.sort_by_key(|entry| entry.date);
+    Ok(entries)
+}
+
+fn format_date(date: TimeDate) -> String {
+    format!(
+        "{:04}{:02}{:02}",
+        date.year(),
+        u8::from(date.month()),
+        date.day()
+    )
+}
+
+fn format_timestamp(timestamp: OffsetDateTime) -> String {
+    let utc = timestamp.to_offset(time::UtcOffset::UTC);
+    format!(
+        "{}T{:02}{:02}{:02}Z",
+        format_date(utc.date()),
+        utc.hour(),
+        utc.minute(),
+        utc.second()
+    )
+}
+
+/// Escape a TEXT value.
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Append a content line, folded to at most 75 octets per line.
+fn push_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+/// Render calendar entries as an iCalendar document.
+///
+/// `stamp` is recorded as the time each entry was generated.
+pub fn render_ics(
+    entries: &[CalendarEntry],
+    component: IcsComponent,
+    stamp: OffsetDateTime,
+) -> String {
+    let name = match component {
+        IcsComponent::Event => "VEVENT",
+        IcsComponent::Todo => "VTODO",
+    };
+    let stamp = format_timestamp(stamp);
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, "PRODID:-//rxtrack//rxtrack//EN");
+    push_line(&mut out, "CALSCALE:GREGORIAN");
+    push_line(&mut out, "X-WR-CALNAME:Prescriptions");
+    for entry in entries {
+        push_line(&mut out, &format!("BEGIN:{}", name));
+        push_line(&mut out, &format!("UID:{}", entry.uid()));
+        push_line(&mut out, &format!("DTSTAMP:{}", stamp));
+        match component {
+            IcsComponent::Event => {
+                push_line(
+                    &mut out,
+                    &format!("DTSTART;VALUE=DATE:{}", format_date(entry.date)),
+                );
+                if let Some(next_day) = entry.date.next_day() {
+                    push_line(
+                        &mut out,
+                        &format!("DTEND;VALUE=DATE:{}", format_date(next_day)),
+                    );
+                }
+                push_line(&mut out, "TRANSP:TRANSPARENT");
+            }
+            IcsComponent::Todo => {
+                push_line(
+                    &mut out,
+                    &format!("DUE;VALUE=DATE:{}", format_date(entry.date)),
+                );
+            }
+        }
+        push_line(
+            &mut out,
+            &format!("SUMMARY:{}", escape_text(&entry.summary)),
+        );
+        push_line(&mut out, &format!("END:{}", name));
+    }
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+/// Render the reminders and run-out dates of the prescriptions that are not hidden,
+/// optionally only those of a single person, dated on or after `from`,
+/// as an iCalendar document.
+pub async fn export_ics(
+    db: &impl ConnectionTrait,
+    person: Option<PersonId>,
+    from: TimeDate,
+    component: IcsComponent,
+    stamp: OffsetDateTime,
+) -> Result<String, Error> {
+    let entries = list_calendar_entries(db, person, from).await?;
+    Ok(render_ics(&entries, component, stamp))
+}
+
+#[cfg(test)]
+mod test {
+
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::Database;
+    use time::{Date, Duration, Month, PrimitiveDateTime, Time, UtcOffset};
+
+    use super::*;
+    use crate::{
+        days_supply::set_days_supply,
+        fill_request::record_pickup,
+        reminder_policy::{add_reminder_policy, ReminderPolicy},
+        renewal::{deny_renewal, request_renewal, DEFAULT_FOLLOW_UP_DAYS},
+        rx::add_rx,
+        EventType,
+    };
+
+    #[test]
+    fn test_render_ics() {
+        let date = Date::from_calendar_date(2023, Month::January, 24).unwrap();
+        let entries = [CalendarEntry {
+            rx_id: RxId::from(3),
+            kind: CalendarEntryKind::Reminder(ReminderPolicyId::from(7)),
+            date,
+            summary: "amoxicillin; 500mg, twice daily: Request a refill from the pharmacy before it runs out"
+                .to_owned(),
+        }];
+        let stamp = PrimitiveDateTime::new(
+            Date::from_calendar_date(2023, Month::January, 20).unwrap(),
+            Time::from_hms(12, 30, 5).unwrap(),
+        )
+        .assume_offset(UtcOffset::from_hms(-5, 0, 0).unwrap());
+        let ics = render_ics(&entries, IcsComponent::Event, stamp);
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.ends_with("END:VEVENT\r\nEND:VCALENDAR\r\n"));
+        assert!(ics.contains("\r\nUID:rx-3-reminder-7@rxtrack\r\n"));
+        assert!(ics.contains("\r\nDTSTAMP:20230120T173005Z\r\n"));
+        assert!(ics.contains("\r\nDTSTART;VALUE=DATE:20230124\r\nDTEND;VALUE=DATE:20230125\r\n"));
+        // Long lines are folded, continuing with a space
+        assert!(ics.lines().all(|line| line.len() <= 75));
+        assert!(ics.replace("\r\n ", "").contains(
+            "\r\nSUMMARY:amoxicillin\\; 500mg\\, twice daily: Request a refill from the pharmacy before it runs out\r\n"
+        ));
+
+        let ics = render_ics(&entries, IcsComponent::Todo, stamp);
+        assert!(ics.contains("\r\nBEGIN:VTODO\r\n"));
+        assert!(ics.contains("\r\nDUE;VALUE=DATE:20230124\r\n"));
+        assert!(!ics.contains("DTSTART"));
+    }
+
+    #[async_std::test]
+    async fn test_list_calendar_entries() -> Result<(), Error> {
+        let monday = Date::from_calendar_date(2023, Month::January, 2).unwrap();
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        let pred_id = add_rx(&db, "prednisone").await?.id();
+        assert!(list_calendar_entries(&db, None, monday).await?.is_empty());
+
+        let policy_id = add_reminder_policy(
+            &db,
+            amox_id,
+            &ReminderPolicy {
+                starting_event: Some(EventType::PickUp),
+                include_rx_duration: true,
+                offset_days: -7,
+                allow_saturday: false,
+                allow_sunday: false,
+                description: "Request refill".to_owned(),
+            },
+        )
+        .await?;
+        set_days_supply(&db, amox_id, 30, monday).await?;
+        record_pickup(&db, amox_id, Some(monday), monday).await?;
+        // Picked up, but no days supply known
+        record_pickup(&db, pred_id, Some(monday), monday).await?;
+
+        let entries = list_calendar_entries(&db, None, monday).await?;
+        assert_eq!(
+            entries,
+            vec![
+                CalendarEntry {
+                    rx_id: amox_id,
+                    kind: CalendarEntryKind::Reminder(policy_id),
+                    date: monday + Duration::days(23),
+                    summary: "amoxicillin: Request refill".to_owned(),
+                },
+                CalendarEntry {
+                    rx_id: amox_id,
+                    kind: CalendarEntryKind::RunOut,
+                    date: monday + Duration::days(30),
+                    summary: "amoxicillin: runs out".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(
+            entries[1].uid(),
+            format!("rx-{}-run-out@rxtrack", amox_id.0)
+        );
+
+        // The UID stays the same when the date moves
+        record_pickup(&db, amox_id, Some(monday), monday + Duration::days(7)).await?;
+        let moved = list_calendar_entries(&db, None, monday).await?;
+        assert_eq!(moved[0].uid(), entries[0].uid());
+        assert_eq!(moved[0].date, monday + Duration::days(30));
+
+        // Only entries on or after the given date are listed
+        let later = list_calendar_entries(&db, None, monday + Duration::days(31)).await?;
+        assert_eq!(later.len(), 1);
+        assert_eq!(later[0].kind, CalendarEntryKind::RunOut);
+        assert!(
+            list_calendar_entries(&db, None, monday + Duration::days(38))
+                .await?
+                .is_empty()
+        );
+
+        // Open renewals follow up on their own dates
+        let renewal = request_renewal(&db, pred_id, monday, None).await?;
+        let entries = list_calendar_entries(&db, None, monday).await?;
+        assert_eq!(
+            entries[0],
+            CalendarEntry {
+                rx_id: pred_id,
+                kind: CalendarEntryKind::RenewalFollowUp(renewal),
+                date: monday + Duration::days(DEFAULT_FOLLOW_UP_DAYS),
+                summary: "prednisone: Follow up on renewal with the prescriber".to_owned(),
+            }
+        );
+        assert_eq!(
+            entries[0].uid(),
+            format!("rx-{}-renewal-{}@rxtrack", pred_id.0, i32::from(renewal))
+        );
+        deny_renewal(&db, renewal, monday, "").await?;
+        assert_eq!(list_calendar_entries(&db, None, monday).await?.len(), 2);
+        Ok(())
+    }
+}
@@ -39,6 +39,34 @@ impl From<entities::fill_request::Model> for RxId {
     }
 }
 
+/// Person ID
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct PersonId(pub(crate) i32);
+
+impl Display for PersonId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PersonId({})", self.0)
+    }
+}
+
+impl From<i32> for PersonId {
+    fn from(value: i32) -> Self {
+        Self(value)
+    }
+}
+
+impl From<PersonId> for i32 {
+    fn from(value: PersonId) -> Self {
+        value.0
+    }
+}
+
+impl From<entities::person::Model> for PersonId {
+    fn from(value: entities::person::Model) -> Self {
+        PersonId(value.person_id)
+    }
+}
+
 /// Fill Request ID
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub struct FillRequestId(pub(crate) i32);
@@ -78,3 +106,75 @@ impl Display for EventId {
         write!(f, "EventId({})", self.0)
     }
 }
+
+/// Reminder Policy ID
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, derive_more::Into, derive_more::From,
+)]
+pub struct ReminderPolicyId(i32);
+
+impl Display for ReminderPolicyId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ReminderPolicyId({})", self.0)
+    }
+}
+
+/// Correction ID
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, derive_more::Into, derive_more::From,
+)]
+pub struct CorrectionId(i32);
+
+impl Display for CorrectionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CorrectionId({})", self.0)
+    }
+}
+
+/// Pharmacy ID
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, derive_more::Into, derive_more::From,
+)]
+pub struct PharmacyId(i32);
+
+impl Display for PharmacyId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PharmacyId({})", self.0)
+    }
+}
+
+/// Prescriber ID
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, derive_more::Into, derive_more::From,
+)]
+pub struct PrescriberId(i32);
+
+impl Display for PrescriberId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PrescriberId({})", self.0)
+    }
+}
+
+/// Renewal Request ID
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, derive_more::Into, derive_more::From,
+)]
+pub struct RenewalId(i32);
+
+impl Display for RenewalId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RenewalId({})", self.0)
+    }
+}
+
+/// Pill Count ID
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, derive_more::Into, derive_more::From,
+)]
+pub struct PillCountId(i32);
+
+impl Display for PillCountId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PillCountId({})", self.0)
+    }
+}
@@ -1,28 +1,77 @@
 // Copyright 2022-2023, Ryan Pavlik <ryan@ryanpavlik.com>
 // SPDX-License-Identifier: GPL3+
 
-/// Fill request constructed from discrete events.
-use crate::{entities::{prelude::*, events}, fill_request::FillRequest, Error, FillRequestId, RxId};
-use migration::EventType;
-use sea_orm::{
-    prelude::TimeDate, ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait,
-    EntityTrait, Order, QueryFilter, QueryOrder, TryIntoModel, Iterable,
+//! Fill request constructed from discrete events.
+//!
+//! Rather than keeping mutable state in the `fill_request` table,
+//! the fill requests for an rx are reconstructed by folding over its events in date order.
+//! An implicit fill request is identified by the event that started it,
+//! which is not the ID of a row in the `fill_request` table.
+//!
+//! For now the `fill_request` table stays authoritative:
+//! the API in [`crate::fill_request`] is the only way to record fill requests,
+//! and writes both the table and the events that this view is reconstructed from,
+//! until the table can be retired.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    entities::fill_request,
+    events::{list_events, Event},
+    fill_request::FillRequest,
+    rx::{list_all_rx, GetRxId},
+    CloseReason, Error, EventId, EventType, FillRequestId, PersonId, PharmacyId, RxId,
 };
+use sea_orm::{ConnectionTrait, EntityTrait, QueryOrder};
 use time::Date;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ImplicitFillRequest {
-    id: FillRequestId,
+    first_event_id: EventId,
+    fill_request_id: Option<FillRequestId>,
+    rx_id: RxId,
     date_requested: Option<Date>,
     date_filled: Option<Date>,
     date_picked_up: Option<Date>,
-    closed: bool,
+    close_reason: Option<CloseReason>,
+    date_cancelled: Option<Date>,
+    pharmacy_id: Option<PharmacyId>,
 }
 
-impl FillRequest for ImplicitFillRequest {
-    fn fill_request_id(&self) -> FillRequestId {
-        self.id.into()
+impl ImplicitFillRequest {
+    fn new(event: &Event) -> Self {
+        ImplicitFillRequest {
+            first_event_id: event.id,
+            fill_request_id: event.fill_request_id,
+            rx_id: event.rx_id,
+            date_requested: None,
+            date_filled: None,
+            date_picked_up: None,
+            close_reason: None,
+            date_cancelled: None,
+            pharmacy_id: None,
+        }
+    }
+
+    /// Take the fill request of an event folded into this request, if not known yet.
+    fn link(&mut self, event: &Event) {
+        self.fill_request_id = self.fill_request_id.or(event.fill_request_id);
+    }
+}
+
+impl ImplicitFillRequest {
+    /// The ID of the event that started this fill request.
+    pub fn first_event_id(&self) -> EventId {
+        self.first_event_id
     }
 
+    /// The fill request that the events of this request are linked to, if any.
+    pub fn fill_request_id(&self) -> Option<FillRequestId> {
+        self.fill_request_id
+    }
+}
+
+impl FillRequest for ImplicitFillRequest {
     fn date_requested(&self) -> &Option<Date> {
         &self.date_requested
     }
@@ -36,107 +85,191 @@ impl FillRequest for ImplicitFillRequest {
     }
 
     fn closed(&self) -> bool {
-        self.closed
+        self.close_reason.is_some()
+    }
+
+    fn close_reason(&self) -> Option<CloseReason> {
+        self.close_reason
+    }
+
+    fn date_cancelled(&self) -> &Option<Date> {
+        &self.date_cancelled
+    }
+
+    /// Events do not record why a request was cancelled.
+    fn cancel_reason(&self) -> Option<&str> {
+        None
+    }
+
+    /// The pharmacy of the latest event of the request that records one.
+    fn pharmacy_id(&self) -> Option<PharmacyId> {
+        self.pharmacy_id
     }
 }
 
+impl GetRxId for ImplicitFillRequest {
+    fn rx_id(&self) -> RxId {
+        self.rx_id
+    }
+}
+
+/// Fold the events of a single rx, sorted by date, into fill requests, oldest first.
+///
+/// - A fill request supersedes (closes) any open request.
+/// - A fill updates the open request, or starts a new one if there is none.
+/// - A pick-up closes the open request, or starts and closes a new one if there is none.
+/// - A refill cancel closes the open request, if any.
+///
+/// At most one request, the last one, is open.
+pub fn fold_events<'a>(events: impl IntoIterator<Item = &'a Event>) -> Vec<ImplicitFillRequest> {
+    let mut requests = Vec::new();
+    let mut open: Option<ImplicitFillRequest> = None;
+    for event in events {
+        match event.event_type {
+            EventType::RequestFill => {
+                if let Some(mut superseded) = open.take() {
+                    superseded.close_reason = Some(CloseReason::Superseded);
+                    requests.push(superseded);
+                }
+                let mut request = ImplicitFillRequest::new(event);
+                request.date_requested = Some(event.date);
+                request.pharmacy_id = event.pharmacy;
+                open = Some(request);
+            }
+            EventType::Fill => {
+                let request = open.get_or_insert_with(|| ImplicitFillRequest::new(event));
+                request.link(event);
+                request.date_filled = Some(event.date);
+                request.pharmacy_id = event.pharmacy.or(request.pharmacy_id);
+            }
+            EventType::PickUp => {
+                let mut request = open
+                    .take()
+                    .unwrap_or_else(|| ImplicitFillRequest::new(event));
+                request.link(event);
+                request.date_picked_up = Some(event.date);
+                request.close_reason = Some(CloseReason::Completed);
+                request.pharmacy_id = event.pharmacy.or(request.pharmacy_id);
+                requests.push(request);
+            }
+            EventType::RefillCancel => {
+                if let Some(mut cancelled) = open.take() {
+                    cancelled.link(event);
+                    cancelled.close_reason = Some(CloseReason::Cancelled);
+                    cancelled.date_cancelled = Some(event.date);
+                    cancelled.pharmacy_id = event.pharmacy.or(cancelled.pharmacy_id);
+                    requests.push(cancelled);
+                }
+            }
+        }
+    }
+    requests.extend(open);
+    requests
+}
+
+/// Reconstruct all fill requests for a given rx, oldest first.
+pub async fn list_fill_requests(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+) -> Result<Vec<ImplicitFillRequest>, Error> {
+    let events = list_events(db, rx, ..).await?;
+    Ok(fold_events(&events))
+}
+
 /// Find an existing open fill request for a given rx, if any.
-async fn find_existing_open_fill_request(
+pub async fn find_existing_open_fill_request(
     db: &impl ConnectionTrait,
     rx: RxId,
 ) -> Result<Option<ImplicitFillRequest>, Error> {
-    let x = EventType::iter().map(|event_type| Events::find().filter(events::Column::RxId.eq(i32::from(rx))).order_by_desc(col), ord))
-    let request = fill_request::Entity::find()
-        .filter(fill_request::Column::Closed.eq(false))
-        .filter(fill_request::Column::RxId.eq(rx.0))
-        .order_by(fill_request::Column::DateRequested, Order::Desc)
-        .one(db)
-        .await?;
+    let request = list_fill_requests(db, rx)
+        .await?
+        .pop()
+        .filter(|request| !request.closed());
     Ok(request)
 }
 
-/// Create a new fill request for an rx, closing any previous open one (if any).
-/// Returns the fill request ID.
-pub async fn record_fill_request(
+/// List all open fill requests, optionally only for the prescriptions of a single person.
+pub async fn list_open_fill_requests(
     db: &impl ConnectionTrait,
-    rx: RxId,
-    request_date: TimeDate,
-) -> Result<FillRequestId, Error> {
-    // Close any existing request for this prescription
-    let existing_request = find_existing_open_fill_request(db, rx).await?;
-
-    if let Some(request) = existing_request {
-        let mut request: fill_request::ActiveModel = request.into();
-        request.closed = Set(true);
-        request.save(db).await?;
+    person: Option<PersonId>,
+) -> Result<Vec<ImplicitFillRequest>, Error> {
+    let mut requests = Vec::new();
+    for rx in list_all_rx(db, person).await? {
+        requests.extend(find_existing_open_fill_request(db, rx.id).await?);
     }
-
-    let request = fill_request::ActiveModel {
-        rx_id: Set(rx.0),
-        date_requested: Set(Some(request_date)),
-        ..Default::default()
-    };
-
-    let res = fill_request::Entity::insert(request).exec(db).await?;
-    Ok(FillRequestId(res.last_insert_id))
+    Ok(requests)
 }
 
-/// Records the fill and pick-up of an rx. If there is an open fill request, it is updated and closed.
-/// Otherwise a new fill request is created and closed.
-/// Returns the fill request ID.
-pub async fn record_pickup(
-    db: &impl ConnectionTrait,
-    rx: RxId,
-    fill_date: TimeDate,
-    pickup_date: TimeDate,
-) -> Result<FillRequestId, Error> {
-    let existing_request = find_existing_open_fill_request(db, rx).await?;
-
-    let mut request: fill_request::ActiveModel = match existing_request {
-        // Closing an existing request
-        Some(request) => request.into(),
-        // Making and closing a new request
-        None => fill_request::ActiveModel {
-            rx_id: Set(rx.0),
-            ..Default::default()
-        },
-    };
-
-    request.date_filled = Set(Some(fill_date));
-    request.date_picked_up = Set(Some(pickup_date));
-    request.closed = Set(true);
+/// The parts of a fill request that should match between the legacy table and the events,
+/// which is everything but the ID.
+fn fill_request_dates(
+    request: &impl FillRequest,
+) -> (Option<Date>, Option<Date>, Option<Date>, bool) {
+    (
+        *request.date_requested(),
+        *request.date_filled(),
+        *request.date_picked_up(),
+        request.closed(),
+    )
+}
 
-    let request: fill_request::Model = request.save(db).await?.try_into_model()?;
+/// Check the fill requests reconstructed from events against the legacy `fill_request` table,
+/// such as after backfilling the events from it.
+///
+/// The backfill does not stop at rows that fold back differently,
+/// such as one filled before it was requested, so this is how to find them.
+///
+/// Returns the ID of each rx whose reconstructed fill requests do not match its legacy rows.
+pub async fn find_legacy_mismatches(db: &impl ConnectionTrait) -> Result<Vec<RxId>, Error> {
+    let mut legacy: BTreeMap<RxId, Vec<fill_request::Model>> = BTreeMap::new();
+    for row in fill_request::Entity::find()
+        .order_by_asc(fill_request::Column::Id)
+        .all(db)
+        .await?
+    {
+        legacy.entry(RxId(row.rx_id)).or_default().push(row);
+    }
 
-    Ok(FillRequestId(request.id))
+    let mut mismatches = Vec::new();
+    for rx in list_all_rx(db, None).await? {
+        let legacy_dates: Vec<_> = legacy
+            .remove(&rx.id)
+            .unwrap_or_default()
+            .iter()
+            .map(fill_request_dates)
+            .collect();
+        let implicit_dates: Vec<_> = list_fill_requests(db, rx.id)
+            .await?
+            .iter()
+            .map(fill_request_dates)
+            .collect();
+        if legacy_dates != implicit_dates {
+            mismatches.push(rx.id);
+        }
+    }
+    Ok(mismatches)
 }
 
 #[cfg(test)]
 mod test {
 
     use migration::{Migrator, MigratorTrait};
-    use sea_orm::{
-        ConnectionTrait, Database, DatabaseBackend, MockDatabase, Transaction, Value::Bool,
-        Value::*,
-    };
+    use sea_orm::{ActiveValue::Set, ConnectionTrait, Database, EntityTrait};
     use time::{Date, Month};
 
     use crate::{
-        entities::fill_request, fill_request::FillRequest, rx::add_rx, Error, FillRequestId, RxId,
+        entities::{fill_request, rx_info},
+        events::{list_events, record_event, Event},
+        fill_request::{record_fill_request, record_pickup, FillRequest},
+        person::add_person,
+        rx::{add_person_rx, add_rx, GetRxId},
+        CloseReason, Error, EventId, EventType, RxId,
     };
 
-    use super::{find_existing_open_fill_request, record_fill_request};
-
-    // async fn setup_schema(db: &impl ConnectionTrait) -> Result<(), Error> {
-    //     let schema = Schema::new(DatabaseBackend::Sqlite);
-    //     // let stmt = ;
-    //     let stmt = schema.create_table_from_entity(rx_info::Entity);
-    //     db.execute(db.get_database_backend().build(&stmt)).await?;
-
-    //     let stmt = schema.create_table_from_entity(fill_request::Entity);
-    //     db.execute(db.get_database_backend().build(&stmt)).await?;
-    //     Ok(())
-    // }
+    use super::{
+        find_existing_open_fill_request, find_legacy_mismatches, fold_events, list_fill_requests,
+        list_open_fill_requests,
+    };
 
     struct Fixture<D> {
         db: D,
@@ -147,8 +280,8 @@ mod test {
     async fn make_inmemory_db() -> Result<Fixture<impl ConnectionTrait>, Error> {
         let db = Database::connect("sqlite::memory:").await?;
         Migrator::up(&db, None).await?;
-        let amox_id = add_rx(&db, "amoxicillin").await?;
-        let pred_id = add_rx(&db, "prednisone").await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        let pred_id = add_rx(&db, "prednisone").await?.id();
         Ok(Fixture {
             db,
             amox_id,
@@ -156,6 +289,57 @@ mod test {
         })
     }
 
+    fn make_event(id: i32, event_type: EventType, date: Date) -> Event {
+        Event {
+            id: EventId::from(id),
+            rx_id: RxId(1),
+            event_type,
+            date,
+            fill_request_id: None,
+            pharmacy: None,
+            quantity: None,
+        }
+    }
+
+    #[test]
+    fn test_fold_events() {
+        let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        assert!(fold_events(&[]).is_empty());
+
+        // pick-up without a request
+        let requests = fold_events(&[
+            make_event(1, EventType::Fill, date),
+            make_event(2, EventType::PickUp, date),
+        ]);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].first_event_id(), EventId::from(1));
+        assert!(requests[0].date_requested().is_none());
+        assert_eq!(*requests[0].date_filled(), Some(date));
+        assert_eq!(*requests[0].date_picked_up(), Some(date));
+        assert!(requests[0].closed());
+
+        // superseded, cancelled, then open
+        let requests = fold_events(&[
+            make_event(1, EventType::RequestFill, date),
+            make_event(2, EventType::RequestFill, date.next_day().unwrap()),
+            make_event(3, EventType::RefillCancel, date.next_day().unwrap()),
+            make_event(4, EventType::RefillCancel, date.next_day().unwrap()),
+            make_event(5, EventType::RequestFill, date.next_day().unwrap()),
+            make_event(6, EventType::Fill, date.next_day().unwrap()),
+        ]);
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].first_event_id(), EventId::from(1));
+        assert_eq!(requests[0].close_reason(), Some(CloseReason::Superseded));
+        assert_eq!(requests[1].first_event_id(), EventId::from(2));
+        assert_eq!(requests[1].close_reason(), Some(CloseReason::Cancelled));
+        assert_eq!(*requests[1].date_cancelled(), date.next_day());
+        assert!(requests[1].date_filled().is_none());
+        assert_eq!(requests[2].first_event_id(), EventId::from(5));
+        assert!(!requests[2].closed());
+        assert_eq!(*requests[2].date_filled(), date.next_day());
+        assert!(requests[2].date_picked_up().is_none());
+    }
+
     #[async_std::test]
     async fn test_record_fill_request() -> Result<(), Error> {
         let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
@@ -168,25 +352,38 @@ mod test {
         let existing = find_existing_open_fill_request(&db, amox_id).await?;
         assert!(existing.is_none());
 
-        let request_id = record_fill_request(&db, amox_id, date).await?;
+        let first_id = record_fill_request(&db, amox_id, date).await?;
 
-        let existing = find_existing_open_fill_request(&db, amox_id).await?;
-        assert_eq!(existing.map(FillRequestId::from), Some(request_id));
+        let existing = find_existing_open_fill_request(&db, amox_id)
+            .await?
+            .unwrap();
+        assert_eq!(*existing.date_requested(), Some(date));
+        // Linked to the fill request its events record
+        assert_eq!(existing.fill_request_id(), Some(first_id));
 
-        let request_id_2 = record_fill_request(&db, amox_id, date.next_day().unwrap()).await?;
+        let second_id = record_fill_request(&db, amox_id, date.next_day().unwrap()).await?;
 
         let existing_2 = find_existing_open_fill_request(&db, amox_id)
             .await?
             .unwrap();
-        assert_eq!(existing_2.fill_request_id(), request_id_2);
+        assert_ne!(existing.first_event_id(), existing_2.first_event_id());
+        assert_eq!(existing_2.fill_request_id(), Some(second_id));
         assert_eq!(*existing_2.date_requested(), date.next_day());
         assert!(existing_2.date_filled().is_none());
         assert!(existing_2.date_picked_up().is_none());
+
+        let all = list_fill_requests(&db, amox_id).await?;
+        assert_eq!(all.len(), 2);
+        assert!(all[0].closed());
+        assert!(!all[1].closed());
+
+        // Other rx unaffected
+        assert!(list_fill_requests(&db, pred_id).await?.is_empty());
         Ok(())
     }
 
     #[async_std::test]
-    async fn test_record_second_fill_request() -> Result<(), Error> {
+    async fn test_record_pickup() -> Result<(), Error> {
         let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
         let Fixture {
             db,
@@ -194,63 +391,141 @@ mod test {
             pred_id,
         } = make_inmemory_db().await?;
 
-        let request_id = record_fill_request(&db, amox_id, date).await?;
+        record_fill_request(&db, amox_id, date).await?;
+        record_pickup(
+            &db,
+            amox_id,
+            Some(date.next_day().unwrap()),
+            date.next_day().unwrap().next_day().unwrap(),
+        )
+        .await?;
+        assert!(find_existing_open_fill_request(&db, amox_id)
+            .await?
+            .is_none());
 
-        let request_id_2 = record_fill_request(&db, amox_id, date.next_day().unwrap()).await?;
-        assert_ne!(request_id, request_id_2);
+        let all = list_fill_requests(&db, amox_id).await?;
+        assert_eq!(all.len(), 1);
+        assert_eq!(*all[0].date_requested(), Some(date));
+        assert_eq!(*all[0].date_filled(), date.next_day());
+        assert_eq!(
+            *all[0].date_picked_up(),
+            date.next_day().unwrap().next_day()
+        );
+        assert!(all[0].closed());
+
+        // Pick-up without a request
+        record_pickup(&db, pred_id, Some(date), date).await?;
+        let all = list_fill_requests(&db, pred_id).await?;
+        assert_eq!(all.len(), 1);
+        assert!(all[0].date_requested().is_none());
+        assert_eq!(*all[0].date_filled(), Some(date));
+        assert!(all[0].closed());
+        Ok(())
+    }
 
-        let existing_2 = find_existing_open_fill_request(&db, amox_id)
-            .await?
-            .unwrap();
-        assert_eq!(existing_2.fill_request_id(), request_id_2);
-        assert_eq!(*existing_2.date_requested(), date.next_day());
-        assert!(existing_2.date_filled().is_none());
-        assert!(existing_2.date_picked_up().is_none());
+    /// Insert a `fill_request` row directly, the way it was recorded before events existed.
+    async fn insert_legacy(
+        db: &impl ConnectionTrait,
+        rx: RxId,
+        dates: [Option<Date>; 3],
+        closed: bool,
+    ) -> Result<(), Error> {
+        let row = fill_request::ActiveModel {
+            rx_id: Set(rx.into()),
+            date_requested: Set(dates[0]),
+            date_filled: Set(dates[1]),
+            date_picked_up: Set(dates[2]),
+            closed: Set(closed),
+            ..Default::default()
+        };
+        fill_request::Entity::insert(row).exec(db).await?;
         Ok(())
     }
 
     #[async_std::test]
-    async fn test_record_request() -> Result<(), Error> {
-        // let make_empty_results = || {
-        //     let empty_results: Vec<Vec<rx_info::Model>> = vec![vec![]];
-        //     empty_results
-        // };
+    async fn test_list_open_fill_requests() -> Result<(), Error> {
         let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
-        let db = MockDatabase::new(DatabaseBackend::Postgres)
-            .append_query_results(vec![
-                vec![],
-                vec![fill_request::Model {
-                    id: 1,
-                    rx_id: 5,
-                    date_requested: Some(date),
-                    date_filled: None,
-                    date_picked_up: None,
-                    closed: false,
-                }],
-            ])
-            .into_connection();
-        let result = record_fill_request(&db, RxId(5), date).await?;
-        assert_eq!(result, FillRequestId(1));
-
-        let log = db.into_transaction_log();
-        // println!("{:?}", log);
+        let Fixture {
+            db,
+            amox_id,
+            pred_id,
+        } = make_inmemory_db().await?;
+        let alice = add_person(&db, "Alice").await?;
+        let alice_amox_id = add_person_rx(&db, alice, "amoxicillin").await?.id();
+
+        record_fill_request(&db, amox_id, date).await?;
+        record_pickup(&db, pred_id, Some(date), date).await?;
+        record_fill_request(&db, alice_amox_id, date.next_day().unwrap()).await?;
 
+        let all = list_open_fill_requests(&db, None).await?;
         assert_eq!(
-            log,
-            vec![
-                Transaction::from_sql_and_values(
-                    DatabaseBackend::Postgres,
-                    r#"SELECT "fill_request"."id", "fill_request"."rx_id", "fill_request"."date_requested", "fill_request"."date_filled", "fill_request"."date_picked_up", "fill_request"."closed" FROM "fill_request" WHERE "fill_request"."closed" = $1 AND "fill_request"."rx_id" = $2 ORDER BY "fill_request"."date_requested" DESC LIMIT $3"#,
-                    vec![Bool(Some(false)), Int(Some(5)), BigUnsigned(Some(1))]
-                ),
-                Transaction::from_sql_and_values(
-                    DatabaseBackend::Postgres,
-                    r#"INSERT INTO "fill_request" ("rx_id", "date_requested") VALUES ($1, $2) RETURNING "id""#,
-                    vec![Int(Some(5)), TimeDate(Some(Box::new(date)))]
-                )
-            ]
+            all.iter().map(|r| r.rx_id()).collect::<Vec<_>>(),
+            vec![amox_id, alice_amox_id]
         );
+        let alice_open = list_open_fill_requests(&db, Some(alice)).await?;
+        assert_eq!(alice_open.len(), 1);
+        assert_eq!(alice_open[0].rx_id(), alice_amox_id);
+        assert_eq!(*alice_open[0].date_requested(), date.next_day());
+        Ok(())
+    }
+
+    /// Add an rx with only the columns of the original schema.
+    async fn insert_legacy_rx(db: &impl ConnectionTrait, name: &str) -> Result<RxId, Error> {
+        let res = rx_info::Entity::insert(rx_info::ActiveModel {
+            rx_name: Set(name.to_owned()),
+            ..Default::default()
+        })
+        .exec(db)
+        .await?;
+        Ok(RxId::from(res.last_insert_id))
+    }
 
+    #[async_std::test]
+    async fn test_backfill_from_legacy() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let day = |n| Some(date + time::Duration::days(n));
+
+        // Migrate up to just before the backfill, and fill in some history the old way
+        let db = Database::connect("sqlite::memory:").await?;
+        let steps = Migrator::steps_before("m20230204_000001_backfill_events").unwrap();
+        Migrator::up(&db, Some(steps)).await?;
+        let amox_id = insert_legacy_rx(&db, "amoxicillin").await?;
+        let pred_id = insert_legacy_rx(&db, "prednisone").await?;
+        let vitd_id = insert_legacy_rx(&db, "vitamin d").await?;
+        let insulin_id = insert_legacy_rx(&db, "insulin").await?;
+
+        // superseded, then picked up
+        insert_legacy(&db, amox_id, [day(0), None, None], true).await?;
+        insert_legacy(&db, amox_id, [day(2), day(3), day(4)], true).await?;
+        // picked up without a request, then open
+        insert_legacy(&db, pred_id, [None, day(0), day(0)], true).await?;
+        insert_legacy(&db, pred_id, [day(30), None, None], false).await?;
+        // filled and picked up before it was requested
+        insert_legacy(&db, insulin_id, [day(10), day(5), day(6)], true).await?;
+
+        // Odd rows do not stop the backfill, but are reported
+        Migrator::up(&db, None).await?;
+        assert_eq!(find_legacy_mismatches(&db).await?, vec![insulin_id]);
+        assert_eq!(list_events(&db, insulin_id, ..).await?.len(), 3);
+
+        let amox = list_fill_requests(&db, amox_id).await?;
+        assert_eq!(amox.len(), 2);
+        assert!(amox[0].closed());
+        assert!(amox[0].date_picked_up().is_none());
+        assert_eq!(*amox[1].date_requested(), day(2));
+        assert_eq!(*amox[1].date_picked_up(), day(4));
+
+        let pred = list_fill_requests(&db, pred_id).await?;
+        assert_eq!(pred.len(), 2);
+        assert!(!pred[1].closed());
+        assert!(list_fill_requests(&db, vitd_id).await?.is_empty());
+
+        // Diverging from the legacy table is detected
+        record_event(&db, vitd_id, EventType::RequestFill, date, None).await?;
+        assert_eq!(
+            find_legacy_mismatches(&db).await?,
+            vec![vitd_id, insulin_id]
+        );
         Ok(())
     }
 }
@@ -0,0 +1,280 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Import dispensing history, e.g. exported from a pharmacy portal.
+
+use sea_orm::{
+    prelude::TimeDate, ActiveValue::Set, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter,
+};
+
+use crate::{
+    entities::imported_dispense,
+    fill_request::record_historical_fill_request,
+    rx::{add_person_rx, add_rx, normalize_rx_name, RxAddOutcome},
+    Error, PersonId, RxId,
+};
+
+/// A single dispense of an rx by the pharmacy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dispense {
+    pub rx_name: String,
+    /// The pharmacy's number for the prescription, if known
+    pub rx_number: Option<String>,
+    pub date_filled: Option<TimeDate>,
+    pub date_picked_up: Option<TimeDate>,
+}
+
+impl Dispense {
+    /// The date the dispense is ordered by: picked up if known, otherwise filled.
+    pub fn date(&self) -> Option<TimeDate> {
+        self.date_picked_up.or(self.date_filled)
+    }
+
+    /// Identifies this dispense within its rx, to recognize it when imported again.
+    pub fn import_key(&self) -> String {
+        let format_date = |date: Option<TimeDate>| date.map(|d| d.to_string()).unwrap_or_default();
+        format!(
+            "{}|{}|{}|{}",
+            normalize_rx_name(&self.rx_name),
+            self.rx_number.as_deref().unwrap_or_default().trim(),
+            format_date(self.date_filled),
+            format_date(self.date_picked_up)
+        )
+    }
+}
+
+/// What importing a dispense did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportAction {
+    /// Skipped, because it was imported before
+    AlreadyImported,
+    /// Recorded as filled, without a pick-up date
+    Filled,
+    /// Recorded as filled and picked up
+    PickedUp,
+}
+
+/// The result of importing a single dispense.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportOutcome {
+    pub dispense: Dispense,
+    /// The matching or newly-created rx
+    pub rx: RxAddOutcome,
+    pub action: ImportAction,
+}
+
+async fn is_imported(db: &impl ConnectionTrait, rx: RxId, key: &str) -> Result<bool, Error> {
+    let existing = imported_dispense::Entity::find()
+        .filter(imported_dispense::Column::RxId.eq(i32::from(rx)))
+        .filter(imported_dispense::Column::ImportKey.eq(key))
+        .one(db)
+        .await?;
+    Ok(existing.is_some())
+}
+
+/// Import dispenses, optionally for a single person, in date order.
+///
+/// Each is matched to an existing rx by name (see [`add_rx`]), or a new rx is added,
+/// then recorded as a closed fill request of its own (see [`record_historical_fill_request`]),
+/// so an open fill request of the rx is left as it is.
+/// Dispenses imported before into the same rx are skipped,
+/// so the same history can be imported for each person on the same drug.
+///
+/// Run this in a transaction, and roll it back to preview the outcome without changing anything.
+pub async fn import_dispenses(
+    db: &impl ConnectionTrait,
+    person: Option<PersonId>,
+    dispenses: impl IntoIterator<Item = Dispense>,
+) -> Result<Vec<ImportOutcome>, Error> {
+    let mut dispenses: Vec<(TimeDate, Dispense)> = dispenses
+        .into_iter()
+        .map(|dispense| match dispense.date() {
+            Some(date) => Ok((date, dispense)),
+            None => Err(Error::DispenseWithoutDate(dispense.rx_name)),
+        })
+        .collect::<Result<_, _>>()?;
+    dispenses.sort_by_key(|(date, _)| *date);
+
+    let mut outcomes = Vec::with_capacity(dispenses.len());
+    for (_, dispense) in dispenses {
+        let rx = match person {
+            Some(person) => add_person_rx(db, person, &dispense.rx_name).await?,
+            None => add_rx(db, &dispense.rx_name).await?,
+        };
+        let key = dispense.import_key();
+        if is_imported(db, rx.id(), &key).await? {
+            outcomes.push(ImportOutcome {
+                dispense,
+                rx,
+                action: ImportAction::AlreadyImported,
+            });
+            continue;
+        }
+
+        record_historical_fill_request(db, rx.id(), dispense.date_filled, dispense.date_picked_up)
+            .await?;
+        let action = if dispense.date_picked_up.is_some() {
+            ImportAction::PickedUp
+        } else {
+            ImportAction::Filled
+        };
+        imported_dispense::Entity::insert(imported_dispense::ActiveModel {
+            rx_id: Set(rx.id().into()),
+            import_key: Set(key),
+            ..Default::default()
+        })
+        .exec(db)
+        .await?;
+        outcomes.push(ImportOutcome {
+            dispense,
+            rx,
+            action,
+        });
+    }
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod test {
+
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::{Database, TransactionTrait};
+    use time::{Date, Duration, Month};
+
+    use super::*;
+    use crate::{
+        fill_request::{
+            get_fill_state, list_fill_requests, record_fill_request, FillRequest, FillState,
+        },
+        person::add_person,
+        rx::list_rx,
+        CloseReason,
+    };
+
+    fn dispense(
+        rx_name: &str,
+        date_filled: Option<TimeDate>,
+        date_picked_up: Option<TimeDate>,
+    ) -> Dispense {
+        Dispense {
+            rx_name: rx_name.to_owned(),
+            rx_number: Some("123456".to_owned()),
+            date_filled,
+            date_picked_up,
+        }
+    }
+
+    #[async_std::test]
+    async fn test_import_dispenses() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let day = |n| Some(date + Duration::days(n));
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+
+        let history = vec![
+            dispense("Amoxicillin", day(30), day(31)),
+            dispense("amoxicillin", day(0), day(2)),
+            dispense("prednisone", None, day(5)),
+            dispense("prednisone", day(35), None),
+        ];
+        assert_eq!(
+            import_dispenses(&db, None, vec![dispense("ibuprofen", None, None)]).await,
+            Err(Error::DispenseWithoutDate("ibuprofen".to_owned()))
+        );
+
+        // Preview without changing anything
+        let txn = db.begin().await?;
+        let preview = import_dispenses(&txn, None, history.clone()).await?;
+        txn.rollback().await?;
+        assert_eq!(preview.len(), 4);
+        assert_eq!(list_rx(&db, None).await?.len(), 1);
+
+        let outcomes = import_dispenses(&db, None, history.clone()).await?;
+        assert_eq!(outcomes, preview);
+        // In date order
+        assert_eq!(outcomes[0].dispense.date_picked_up, day(2));
+        assert_eq!(outcomes[0].rx, RxAddOutcome::AlreadyExists(amox_id));
+        assert_eq!(outcomes[0].action, ImportAction::PickedUp);
+        assert!(matches!(outcomes[1].rx, RxAddOutcome::Created(_)));
+        let pred_id = outcomes[1].rx.id();
+        assert_eq!(outcomes[3].rx, RxAddOutcome::AlreadyExists(pred_id));
+        assert_eq!(outcomes[3].action, ImportAction::Filled);
+
+        let amox = list_fill_requests(&db, amox_id).await?;
+        assert_eq!(amox.len(), 2);
+        assert_eq!(*amox[1].date_filled(), day(30));
+        assert_eq!(*amox[1].date_picked_up(), day(31));
+        let pred = list_fill_requests(&db, pred_id).await?;
+        assert_eq!(*pred[0].date_filled(), day(5));
+        // Dispenses without a pick-up are history too, rather than waiting to be picked up
+        assert_eq!(*pred[1].date_picked_up(), None);
+        assert_eq!(pred[1].close_reason(), Some(CloseReason::Completed));
+
+        // Importing again skips everything
+        let again = import_dispenses(&db, None, history).await?;
+        assert!(again
+            .iter()
+            .all(|outcome| outcome.action == ImportAction::AlreadyImported));
+        assert_eq!(again[0].rx, RxAddOutcome::AlreadyExists(amox_id));
+        assert_eq!(list_fill_requests(&db, amox_id).await?.len(), 2);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_import_for_each_person() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let alice = add_person(&db, "Alice").await?;
+        let bob = add_person(&db, "Bob").await?;
+        let history = vec![dispense("amoxicillin", Some(date), Some(date))];
+
+        let alice_outcomes = import_dispenses(&db, Some(alice), history.clone()).await?;
+        assert_eq!(alice_outcomes[0].action, ImportAction::PickedUp);
+        let bob_outcomes = import_dispenses(&db, Some(bob), history.clone()).await?;
+        assert_eq!(bob_outcomes[0].action, ImportAction::PickedUp);
+        let alice_amox = alice_outcomes[0].rx.id();
+        let bob_amox = bob_outcomes[0].rx.id();
+        assert_ne!(alice_amox, bob_amox);
+        assert_eq!(list_fill_requests(&db, bob_amox).await?.len(), 1);
+
+        let again = import_dispenses(&db, Some(bob), history).await?;
+        assert_eq!(again[0].rx, RxAddOutcome::AlreadyExists(bob_amox));
+        assert_eq!(again[0].action, ImportAction::AlreadyImported);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_import_keeps_open_request() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let day = |n| Some(date + Duration::days(n));
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        let request_id = record_fill_request(&db, amox_id, date + Duration::days(20)).await?;
+
+        // Dispenses from before and after the request, one never picked up
+        let history = vec![
+            dispense("amoxicillin", day(0), day(2)),
+            dispense("amoxicillin", day(10), None),
+            dispense("amoxicillin", day(25), day(26)),
+        ];
+        import_dispenses(&db, None, history).await?;
+
+        assert_eq!(
+            get_fill_state(&db, amox_id).await?,
+            FillState::Requested {
+                date_requested: day(20)
+            }
+        );
+        let requests = list_fill_requests(&db, amox_id).await?;
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[0].fill_request_id(), request_id);
+        assert!(!requests[0].closed());
+        assert_eq!(*requests[0].date_filled(), None);
+        assert!(requests[1..].iter().all(|request| request.closed()));
+        assert_eq!(*requests[3].date_picked_up(), day(26));
+        Ok(())
+    }
+}
@@ -0,0 +1,364 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Units of each rx on hand, estimated from the quantities picked up and its dose rate,
+//! and reset by manual counts, which record how far off the estimate was.
+
+use sea_orm::{
+    prelude::TimeDate, ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait,
+    EntityTrait, QueryFilter, QueryOrder,
+};
+
+use crate::{
+    dosing::DoseRate,
+    entities::{pill_count, rx_info},
+    events::{list_events, Event},
+    rx::{get_rx, list_rx, KnownRx},
+    Error, EventType, PersonId, PillCountId, RxId,
+};
+
+/// A manual count of the units of an rx on hand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PillCount {
+    pub id: PillCountId,
+    pub rx_id: RxId,
+    pub date: TimeDate,
+    pub counted: u32,
+    /// The estimate at the time of the count, if there was one
+    pub expected: Option<u32>,
+}
+
+impl PillCount {
+    /// How many more units were counted than expected, negative if fewer, e.g. after spilling some.
+    pub fn discrepancy(&self) -> Option<i64> {
+        self.expected
+            .map(|expected| i64::from(self.counted) - i64::from(expected))
+    }
+}
+
+impl From<pill_count::Model> for PillCount {
+    fn from(value: pill_count::Model) -> Self {
+        PillCount {
+            id: PillCountId::from(value.id),
+            rx_id: RxId::from(value.rx_id),
+            date: value.date,
+            counted: u32::try_from(value.counted).unwrap_or_default(),
+            expected: value
+                .expected
+                .and_then(|expected| u32::try_from(expected).ok()),
+        }
+    }
+}
+
+/// The estimated supply of an rx on hand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OnHand {
+    pub units: u32,
+    /// Whole days the units last at the dose rate
+    pub days: u32,
+}
+
+/// Units used over a number of days at a dose rate, rounded down.
+fn units_used(dose: DoseRate, days: i64) -> u64 {
+    let days = u64::try_from(days).unwrap_or_default();
+    days * u64::from(dose.units) / u64::from(dose.every_days.max(1))
+}
+
+/// Estimate the units of an rx on hand on a date, given its events and counts in date order, and its dose rate.
+///
+/// Starts from the latest count on or before the date, which is assumed to include any pick-ups that day,
+/// adds the quantity of each later pick-up, and subtracts the units used each day, never going below zero.
+/// Without a count, starts from nothing before the first pick-up.
+/// Returns `None` if nothing is known, or a pick-up since the count has no quantity recorded.
+pub fn estimate_on_hand(
+    events: &[Event],
+    counts: &[PillCount],
+    dose: DoseRate,
+    date: TimeDate,
+) -> Option<u32> {
+    let count = counts.iter().rev().find(|count| count.date <= date);
+    // Units on hand as of a date, if known
+    let mut state: Option<(u64, TimeDate)> =
+        count.map(|count| (u64::from(count.counted), count.date));
+    for event in events.iter().filter(|event| {
+        event.event_type == EventType::PickUp
+            && event.date <= date
+            && count.is_none_or(|count| event.date > count.date)
+    }) {
+        state = event.quantity.map(|quantity| {
+            let left = state.map_or(0, |(units, since)| {
+                units.saturating_sub(units_used(dose, (event.date - since).whole_days()))
+            });
+            (left + u64::from(quantity), event.date)
+        });
+    }
+    let (units, since) = state?;
+    let units = units.saturating_sub(units_used(dose, (date - since).whole_days()));
+    Some(u32::try_from(units).unwrap_or(u32::MAX))
+}
+
+/// List the counts of an rx, oldest first.
+pub async fn list_pill_counts(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+) -> Result<Vec<PillCount>, Error> {
+    let counts = pill_count::Entity::find()
+        .filter(pill_count::Column::RxId.eq(i32::from(rx)))
+        .order_by_asc(pill_count::Column::Date)
+        .order_by_asc(pill_count::Column::Id)
+        .all(db)
+        .await?;
+    Ok(counts.into_iter().map(PillCount::from).collect())
+}
+
+/// Estimate the supply of an rx on hand on a date, if its dose rate and enough pick-up quantities are known.
+pub async fn on_hand(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    date: TimeDate,
+) -> Result<Option<OnHand>, Error> {
+    let known = get_rx(db, rx).await?.ok_or(Error::NoSuchRx(rx))?;
+    let Some(dose) = known.dose else {
+        return Ok(None);
+    };
+    let events = list_events(db, rx, ..=date).await?;
+    let counts = list_pill_counts(db, rx).await?;
+    Ok(
+        estimate_on_hand(&events, &counts, dose, date).map(|units| OnHand {
+            units,
+            days: dose.days_for(units),
+        }),
+    )
+}
+
+/// Record counting the units of an rx on hand, which resets the estimate from then on.
+///
+/// Keeps the estimate just before the count, to show any discrepancy.
+pub async fn record_pill_count(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    date: TimeDate,
+    counted: u32,
+) -> Result<PillCount, Error> {
+    let expected = on_hand(db, rx, date).await?.map(|on_hand| on_hand.units);
+    let to_column = |units: u32| i32::try_from(units).unwrap_or(i32::MAX);
+    let count = pill_count::ActiveModel {
+        rx_id: Set(i32::from(rx)),
+        date: Set(date),
+        counted: Set(to_column(counted)),
+        expected: Set(expected.map(to_column)),
+        ..Default::default()
+    };
+    let res = pill_count::Entity::insert(count).exec(db).await?;
+    Ok(PillCount {
+        id: PillCountId::from(res.last_insert_id),
+        rx_id: rx,
+        date,
+        counted,
+        expected,
+    })
+}
+
+/// Mark an rx as critical, alerting when fewer than the given days are on hand, or unmark it with `None`.
+pub async fn set_critical_days(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    days: Option<u32>,
+) -> Result<(), Error> {
+    let days = match days.map(i32::try_from) {
+        Some(Ok(d)) if d > 0 => Some(d),
+        Some(_) => return Err(Error::InvalidCriticalDays(days.unwrap_or_default())),
+        None => None,
+    };
+    let existing = rx_info::Entity::find_by_id(i32::from(rx))
+        .one(db)
+        .await?
+        .ok_or(Error::NoSuchRx(rx))?;
+    let mut model: rx_info::ActiveModel = existing.into();
+    model.critical_days = Set(days);
+    model.update(db).await?;
+    Ok(())
+}
+
+/// Check whether a critical rx is running low on the given date, returning its supply on hand if so.
+///
+/// An rx whose supply on hand cannot be estimated is not reported.
+pub async fn check_low_on_hand(
+    db: &impl ConnectionTrait,
+    rx: &KnownRx,
+    date: TimeDate,
+) -> Result<Option<OnHand>, Error> {
+    let Some(critical_days) = rx.critical_days else {
+        return Ok(None);
+    };
+    let supply = on_hand(db, rx.id, date).await?;
+    Ok(supply.filter(|supply| supply.days < critical_days))
+}
+
+/// List the critical prescriptions that are not hidden and are running low on the given date,
+/// optionally only those of a single person.
+pub async fn list_low_on_hand(
+    db: &impl ConnectionTrait,
+    person: Option<PersonId>,
+    date: TimeDate,
+) -> Result<Vec<(KnownRx, OnHand)>, Error> {
+    let mut low = vec![];
+    for rx in list_rx(db, person).await? {
+        if let Some(supply) = check_low_on_hand(db, &rx, date).await? {
+            low.push((rx, supply));
+        }
+    }
+    Ok(low)
+}
+
+#[cfg(test)]
+mod test {
+
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::Database;
+    use time::{Date, Duration, Month};
+
+    use super::*;
+    use crate::{
+        dosing::{set_dose_rate, set_pickup_quantity},
+        fill_request::record_pickup,
+        rx::add_rx,
+        EventId,
+    };
+
+    fn make_pickup(id: i32, date: Date, quantity: Option<u32>) -> Event {
+        Event {
+            id: EventId::from(id),
+            rx_id: RxId::from(1),
+            event_type: EventType::PickUp,
+            date,
+            fill_request_id: None,
+            pharmacy: None,
+            quantity,
+        }
+    }
+
+    fn make_count(id: i32, date: Date, counted: u32) -> PillCount {
+        PillCount {
+            id: PillCountId::from(id),
+            rx_id: RxId::from(1),
+            date,
+            counted,
+            expected: None,
+        }
+    }
+
+    #[test]
+    fn test_estimate_on_hand() {
+        let date = Date::from_calendar_date(2023, Month::May, 13).unwrap();
+        let day = |n| date + Duration::days(n);
+        let twice_daily = DoseRate::daily(2);
+        assert_eq!(estimate_on_hand(&[], &[], twice_daily, date), None);
+
+        let events = [
+            make_pickup(1, date, Some(60)),
+            make_pickup(2, day(25), Some(60)),
+        ];
+        assert_eq!(estimate_on_hand(&events, &[], twice_daily, day(-1)), None);
+        assert_eq!(estimate_on_hand(&events, &[], twice_daily, date), Some(60));
+        assert_eq!(
+            estimate_on_hand(&events, &[], twice_daily, day(10)),
+            Some(40)
+        );
+        // Leftover carries over to the next pick-up
+        assert_eq!(
+            estimate_on_hand(&events, &[], twice_daily, day(25)),
+            Some(70)
+        );
+        // Never below zero
+        assert_eq!(
+            estimate_on_hand(&events, &[], twice_daily, day(90)),
+            Some(0)
+        );
+
+        // A count resets the estimate, including pick-ups that day
+        let counts = [make_count(1, day(10), 30)];
+        assert_eq!(
+            estimate_on_hand(&events, &counts, twice_daily, day(12)),
+            Some(26)
+        );
+        assert_eq!(
+            estimate_on_hand(&events, &counts, twice_daily, day(25)),
+            Some(60)
+        );
+        assert_eq!(
+            estimate_on_hand(&events[..1], &[make_count(1, date, 55)], twice_daily, date),
+            Some(55)
+        );
+        // Unknown quantities since the count make the estimate unknown
+        let unknown = [
+            make_pickup(1, date, Some(60)),
+            make_pickup(2, day(25), None),
+        ];
+        assert_eq!(
+            estimate_on_hand(&unknown, &counts, twice_daily, day(24)),
+            Some(2)
+        );
+        assert_eq!(
+            estimate_on_hand(&unknown, &counts, twice_daily, day(25)),
+            None
+        );
+    }
+
+    #[async_std::test]
+    async fn test_counts_and_low_on_hand() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::May, 13).unwrap();
+        let day = |n| date + Duration::days(n);
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        let pred_id = add_rx(&db, "prednisone").await?.id();
+
+        // Nothing known yet
+        let first = record_pill_count(&db, amox_id, date, 10).await?;
+        assert_eq!(first.expected, None);
+        assert_eq!(first.discrepancy(), None);
+
+        set_dose_rate(&db, amox_id, Some(DoseRate::daily(2))).await?;
+        let picked_up = record_pickup(&db, amox_id, Some(day(1)), day(1)).await?;
+        set_pickup_quantity(&db, picked_up, Some(60)).await?;
+        assert_eq!(
+            on_hand(&db, amox_id, day(5)).await?,
+            Some(OnHand {
+                units: 60,
+                days: 30
+            })
+        );
+
+        // Some were spilled
+        let count = record_pill_count(&db, amox_id, day(5), 50).await?;
+        assert_eq!(count.expected, Some(60));
+        assert_eq!(count.discrepancy(), Some(-10));
+        assert_eq!(list_pill_counts(&db, amox_id).await?, vec![first, count]);
+        assert_eq!(
+            on_hand(&db, amox_id, day(20)).await?,
+            Some(OnHand {
+                units: 20,
+                days: 10
+            })
+        );
+
+        // Only critical prescriptions alert, below their number of days
+        assert!(list_low_on_hand(&db, None, day(20)).await?.is_empty());
+        set_critical_days(&db, amox_id, Some(7)).await?;
+        set_critical_days(&db, pred_id, Some(7)).await?;
+        assert!(list_low_on_hand(&db, None, day(20)).await?.is_empty());
+        let low = list_low_on_hand(&db, None, day(24)).await?;
+        assert_eq!(low.len(), 1);
+        assert_eq!(low[0].0.id, amox_id);
+        assert_eq!(low[0].1, OnHand { units: 12, days: 6 });
+
+        set_critical_days(&db, amox_id, None).await?;
+        assert!(list_low_on_hand(&db, None, day(24)).await?.is_empty());
+        assert_eq!(
+            set_critical_days(&db, amox_id, Some(0)).await,
+            Err(Error::InvalidCriticalDays(0))
+        );
+        Ok(())
+    }
+}
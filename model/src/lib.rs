@@ -1,21 +1,129 @@
 // Copyright 2022-2023, Ryan Pavlik <ryan@ryanpavlik.com>
 // SPDX-License-Identifier: GPL3+
 
-use sea_orm::DbErr;
+use sea_orm::{prelude::TimeDate, DbErr};
 
+pub mod authorization;
+pub mod backup;
+pub mod corrections;
+pub mod days_supply;
+pub mod dosing;
+pub mod due;
 pub mod entities;
+pub mod events;
 pub mod fill_request;
+pub mod ics;
 mod ids;
 pub mod implicit_fill_request;
+pub mod import;
+pub mod inventory;
+pub mod person;
+pub mod pharmacy;
+pub mod prescriber;
+pub mod reminder_policy;
+pub mod reminders;
+pub mod renewal;
 pub mod rx;
+pub mod stats;
 
-pub use ids::{FillRequestId, RxId};
+pub use ids::{
+    CorrectionId, EventId, FillRequestId, PersonId, PharmacyId, PillCountId, PrescriberId,
+    ReminderPolicyId, RenewalId, RxId,
+};
+pub use migration::{CloseReason, EventType, RefillUse, RenewalStatus};
 
 #[derive(Debug, PartialEq, Eq, thiserror::Error)]
 pub enum Error {
     #[error("Prescription name cannot be empty")]
     EmptyRxName,
 
+    #[error("Person name cannot be empty")]
+    EmptyPersonName,
+
+    #[error("Pharmacy name cannot be empty")]
+    EmptyPharmacyName,
+
+    #[error("No such pharmacy: {0}")]
+    NoSuchPharmacy(PharmacyId),
+
+    #[error("Prescriber name cannot be empty")]
+    EmptyPrescriberName,
+
+    #[error("No such prescriber: {0}")]
+    NoSuchPrescriber(PrescriberId),
+
+    #[error("No such renewal request: {0}")]
+    NoSuchRenewal(RenewalId),
+
+    #[error("Prescription already has an open renewal request: {0}")]
+    RenewalAlreadyOpen(RenewalId),
+
+    #[error("Renewal request {0} is {1:?} and cannot be {2:?}")]
+    InvalidRenewalTransition(RenewalId, RenewalStatus, RenewalStatus),
+
+    #[error("No such prescription: {0}")]
+    NoSuchRx(RxId),
+
+    #[error("No such person: {0}")]
+    NoSuchPerson(PersonId),
+
+    #[error("No open fill request for {0}")]
+    NoOpenFillRequest(RxId),
+
+    #[error("Prescription {0} already has that name")]
+    DuplicateRxName(RxId),
+
+    #[error("Prescription {0} is retired")]
+    RxRetired(RxId),
+
+    #[error("No such reminder policy: {0}")]
+    NoSuchReminderPolicy(ReminderPolicyId),
+
+    #[error("Reminder description cannot be empty")]
+    EmptyReminderDescription,
+
+    #[error("Reminder offset of {0} days is out of range")]
+    ReminderOffsetOutOfRange(i32),
+
+    #[error("Days supply must be a positive number of days, not {0}")]
+    InvalidDaysSupply(u32),
+
+    #[error("Dose rate must be a positive number of units every positive number of days, not {0} every {1}")]
+    InvalidDoseRate(u32, u32),
+
+    #[error("Critical days on hand must be a positive number of days, not {0}")]
+    InvalidCriticalDays(u32),
+
+    #[error("Dispense of {0} has neither a fill nor a pick-up date")]
+    DispenseWithoutDate(String),
+
+    #[error("Date range from {0} to {1} is empty")]
+    InvalidDateRange(TimeDate, TimeDate),
+
+    #[error("No such event: {0}")]
+    NoSuchEvent(EventId),
+
+    #[error("Event {0} was voided")]
+    EventVoided(EventId),
+
+    #[error("Event {0} has corrections, so void it instead of deleting it")]
+    EventHasCorrections(EventId),
+
+    #[error("No such fill request: {0}")]
+    NoSuchFillRequest(FillRequestId),
+
+    #[error("Fill request {0} has no {1:?} date")]
+    NoSuchFillRequestStep(FillRequestId, EventType),
+
+    #[error("Backup is from a newer version of rxtrack (schema version {0})")]
+    NewerBackupVersion(u32),
+
+    #[error("Invalid backup: {0}")]
+    InvalidBackup(String),
+
+    #[error("Can only restore a backup into an empty database")]
+    RestoreTargetNotEmpty,
+
     #[error("Database error: {0}")]
     DbError(#[from] DbErr),
 }
@@ -0,0 +1,84 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+use sea_orm::{ActiveValue::Set, ConnectionTrait, EntityTrait, QueryOrder};
+
+use crate::{entities::person, Error, PersonId};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownPerson {
+    pub id: PersonId,
+    pub name: String,
+}
+
+impl From<person::Model> for KnownPerson {
+    fn from(value: person::Model) -> Self {
+        KnownPerson {
+            id: PersonId::from(value.person_id),
+            name: value.name,
+        }
+    }
+}
+
+/// Add a new person, receiving the ID.
+pub async fn add_person(db: &impl ConnectionTrait, name: &str) -> Result<PersonId, Error> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(Error::EmptyPersonName);
+    }
+    let person = person::ActiveModel {
+        name: Set(name.to_owned()),
+        ..Default::default()
+    };
+    let res = person::Entity::insert(person).exec(db).await?;
+    Ok(PersonId(res.last_insert_id))
+}
+
+pub async fn list_people(db: &impl ConnectionTrait) -> Result<Vec<KnownPerson>, sea_orm::DbErr> {
+    let result = person::Entity::find()
+        .order_by_asc(person::Column::PersonId)
+        .all(db)
+        .await?;
+    Ok(result.into_iter().map(KnownPerson::from).collect())
+}
+
+pub async fn get_person(
+    db: &impl ConnectionTrait,
+    id: PersonId,
+) -> Result<Option<KnownPerson>, sea_orm::DbErr> {
+    let person = person::Entity::find_by_id(i32::from(id)).one(db).await?;
+    Ok(person.map(KnownPerson::from))
+}
+
+#[cfg(test)]
+mod test {
+
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::Database;
+
+    use super::*;
+
+    #[async_std::test]
+    async fn test_people() -> Result<(), Error> {
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        assert!(list_people(&db).await?.is_empty());
+        assert_eq!(add_person(&db, " ").await, Err(Error::EmptyPersonName));
+
+        let alice = add_person(&db, " Alice ").await?;
+        let bob = add_person(&db, "Bob").await?;
+        assert_ne!(alice, bob);
+
+        assert_eq!(
+            get_person(&db, alice).await?,
+            Some(KnownPerson {
+                id: alice,
+                name: "Alice".to_owned()
+            })
+        );
+        let people = list_people(&db).await?;
+        assert_eq!(people.len(), 2);
+        assert_eq!(people[1].name, "Bob");
+        Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Pharmacies that fill prescriptions, with a default pharmacy per rx.
+
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait, EntityTrait,
+    QueryFilter, QueryOrder,
+};
+
+use crate::{
+    entities::{events, fill_request, pharmacy, rx_info},
+    Error, FillRequestId, PharmacyId, RxId,
+};
+
+/// Contact details of a pharmacy.
+///
+/// Blank optional fields are stored as `None`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Pharmacy {
+    pub name: String,
+    pub phone: Option<String>,
+    pub address: Option<String>,
+    pub hours: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownPharmacy {
+    pub id: PharmacyId,
+    pub pharmacy: Pharmacy,
+}
+
+impl From<pharmacy::Model> for KnownPharmacy {
+    fn from(value: pharmacy::Model) -> Self {
+        KnownPharmacy {
+            id: PharmacyId::from(value.pharmacy_id),
+            pharmacy: Pharmacy {
+                name: value.name,
+                phone: value.phone,
+                address: value.address,
+                hours: value.hours,
+            },
+        }
+    }
+}
+
+/// Trim an optional text field, treating a blank one as missing.
+pub(crate) fn trimmed(value: &Option<String>) -> Option<String> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_owned)
+}
+
+/// Check the name and trim all fields of a pharmacy.
+fn normalize(pharmacy: &Pharmacy) -> Result<Pharmacy, Error> {
+    let name = pharmacy.name.trim();
+    if name.is_empty() {
+        return Err(Error::EmptyPharmacyName);
+    }
+    Ok(Pharmacy {
+        name: name.to_owned(),
+        phone: trimmed(&pharmacy.phone),
+        address: trimmed(&pharmacy.address),
+        hours: trimmed(&pharmacy.hours),
+    })
+}
+
+/// Add a new pharmacy, receiving the ID.
+pub async fn add_pharmacy(
+    db: &impl ConnectionTrait,
+    pharmacy: &Pharmacy,
+) -> Result<PharmacyId, Error> {
+    let pharmacy = normalize(pharmacy)?;
+    let model = pharmacy::ActiveModel {
+        name: Set(pharmacy.name),
+        phone: Set(pharmacy.phone),
+        address: Set(pharmacy.address),
+        hours: Set(pharmacy.hours),
+        ..Default::default()
+    };
+    let res = pharmacy::Entity::insert(model).exec(db).await?;
+    Ok(PharmacyId::from(res.last_insert_id))
+}
+
+/// Replace the details of a pharmacy.
+pub async fn update_pharmacy(
+    db: &impl ConnectionTrait,
+    id: PharmacyId,
+    pharmacy: &Pharmacy,
+) -> Result<(), Error> {
+    let pharmacy = normalize(pharmacy)?;
+    let existing = pharmacy::Entity::find_by_id(i32::from(id))
+        .one(db)
+        .await?
+        .ok_or(Error::NoSuchPharmacy(id))?;
+    let mut model: pharmacy::ActiveModel = existing.into();
+    model.name = Set(pharmacy.name);
+    model.phone = Set(pharmacy.phone);
+    model.address = Set(pharmacy.address);
+    model.hours = Set(pharmacy.hours);
+    model.update(db).await?;
+    Ok(())
+}
+
+pub async fn list_pharmacies(
+    db: &impl ConnectionTrait,
+) -> Result<Vec<KnownPharmacy>, sea_orm::DbErr> {
+    let result = pharmacy::Entity::find()
+        .order_by_asc(pharmacy::Column::PharmacyId)
+        .all(db)
+        .await?;
+    Ok(result.into_iter().map(KnownPharmacy::from).collect())
+}
+
+pub async fn get_pharmacy(
+    db: &impl ConnectionTrait,
+    id: PharmacyId,
+) -> Result<Option<KnownPharmacy>, sea_orm::DbErr> {
+    let pharmacy = pharmacy::Entity::find_by_id(i32::from(id)).one(db).await?;
+    Ok(pharmacy.map(KnownPharmacy::from))
+}
+
+async fn require_pharmacy(db: &impl ConnectionTrait, id: Option<PharmacyId>) -> Result<(), Error> {
+    if let Some(id) = id {
+        get_pharmacy(db, id)
+            .await?
+            .ok_or(Error::NoSuchPharmacy(id))?;
+    }
+    Ok(())
+}
+
+/// Set or clear the pharmacy an rx is usually filled at.
+///
+/// New fill requests of the rx are attributed to it.
+pub async fn set_default_pharmacy(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    pharmacy: Option<PharmacyId>,
+) -> Result<(), Error> {
+    require_pharmacy(db, pharmacy).await?;
+    let existing = rx_info::Entity::find_by_id(i32::from(rx))
+        .one(db)
+        .await?
+        .ok_or(Error::NoSuchRx(rx))?;
+    let mut model: rx_info::ActiveModel = existing.into();
+    model.pharmacy_id = Set(pharmacy.map(i32::from));
+    model.update(db).await?;
+    Ok(())
+}
+
+/// Attribute a fill request, and the events of its steps, to a different pharmacy (or none).
+pub async fn set_fill_request_pharmacy(
+    db: &impl ConnectionTrait,
+    id: FillRequestId,
+    pharmacy: Option<PharmacyId>,
+) -> Result<(), Error> {
+    require_pharmacy(db, pharmacy).await?;
+    let request = fill_request::Entity::find_by_id(i32::from(id))
+        .one(db)
+        .await?
+        .ok_or(Error::NoSuchFillRequest(id))?;
+    let pharmacy = pharmacy.map(i32::from);
+    events::Entity::update_many()
+        .col_expr(events::Column::PharmacyId, Expr::value(pharmacy))
+        .filter(events::Column::FillRequestId.eq(request.id))
+        .exec(db)
+        .await?;
+    let mut model: fill_request::ActiveModel = request.into();
+    model.pharmacy_id = Set(pharmacy);
+    model.update(db).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::Database;
+    use time::{Date, Month};
+
+    use super::*;
+    use crate::{
+        events::list_events,
+        fill_request::{
+            list_fill_requests, record_fill, record_fill_request, record_pickup, FillRequest,
+        },
+        rx::{add_rx, get_rx},
+    };
+
+    #[async_std::test]
+    async fn test_pharmacies() -> Result<(), Error> {
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        assert!(list_pharmacies(&db).await?.is_empty());
+        assert_eq!(
+            add_pharmacy(&db, &Pharmacy::default()).await,
+            Err(Error::EmptyPharmacyName)
+        );
+
+        let corner = add_pharmacy(
+            &db,
+            &Pharmacy {
+                name: " Corner Drug ".to_owned(),
+                phone: Some("555-0100".to_owned()),
+                address: Some(" ".to_owned()),
+                hours: None,
+            },
+        )
+        .await?;
+        let corner_data = get_pharmacy(&db, corner).await?.unwrap();
+        assert_eq!(corner_data.pharmacy.name, "Corner Drug");
+        assert_eq!(corner_data.pharmacy.address, None);
+
+        let mail = Pharmacy {
+            name: "Mail order".to_owned(),
+            ..Default::default()
+        };
+        let mail_id = add_pharmacy(&db, &mail).await?;
+        let updated = Pharmacy {
+            hours: Some("24 hours".to_owned()),
+            ..mail
+        };
+        update_pharmacy(&db, mail_id, &updated).await?;
+        assert_eq!(get_pharmacy(&db, mail_id).await?.unwrap().pharmacy, updated);
+        let missing = PharmacyId::from(42);
+        assert_eq!(
+            update_pharmacy(&db, missing, &updated).await,
+            Err(Error::NoSuchPharmacy(missing))
+        );
+        assert_eq!(list_pharmacies(&db).await?.len(), 2);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_fill_pharmacy() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::April, 8).unwrap();
+        let next_day = date.next_day().unwrap();
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        let corner = add_pharmacy(
+            &db,
+            &Pharmacy {
+                name: "Corner Drug".to_owned(),
+                ..Default::default()
+            },
+        )
+        .await?;
+        let mail = add_pharmacy(
+            &db,
+            &Pharmacy {
+                name: "Mail order".to_owned(),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+        // No default: nothing recorded
+        record_pickup(&db, amox_id, Some(date), date).await?;
+
+        set_default_pharmacy(&db, amox_id, Some(corner)).await?;
+        assert_eq!(get_rx(&db, amox_id).await?.unwrap().pharmacy, Some(corner));
+        assert_eq!(
+            set_default_pharmacy(&db, amox_id, Some(PharmacyId::from(42))).await,
+            Err(Error::NoSuchPharmacy(PharmacyId::from(42)))
+        );
+
+        // The default is used for new requests
+        let request = record_fill_request(&db, amox_id, date).await?;
+        // Moving the open request to another pharmacy keeps it there for later steps
+        set_fill_request_pharmacy(&db, request, Some(mail)).await?;
+        record_fill(&db, amox_id, next_day).await?;
+
+        let requests = list_fill_requests(&db, amox_id).await?;
+        assert_eq!(requests[0].pharmacy_id(), None);
+        assert_eq!(requests[1].pharmacy_id(), Some(mail));
+        let pharmacies: Vec<_> = list_events(&db, amox_id, ..)
+            .await?
+            .into_iter()
+            .map(|event| event.pharmacy)
+            .collect();
+        assert_eq!(pharmacies, vec![None, None, Some(mail), Some(mail)]);
+        Ok(())
+    }
+}
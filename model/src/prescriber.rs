@@ -0,0 +1,282 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Prescribers, who write and renew prescriptions, with the current prescriber of each rx.
+
+use std::collections::BTreeMap;
+
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ConnectionTrait, EntityTrait, QueryOrder};
+
+use crate::{
+    entities::{prescriber, rx_info},
+    pharmacy::trimmed,
+    rx::{list_rx, KnownRx},
+    Error, PersonId, PrescriberId, RxId,
+};
+
+/// Contact details of a prescriber.
+///
+/// Blank optional fields are stored as `None`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Prescriber {
+    pub name: String,
+    /// Name of the practice or clinic
+    pub practice: Option<String>,
+    pub phone: Option<String>,
+    pub fax: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownPrescriber {
+    pub id: PrescriberId,
+    pub prescriber: Prescriber,
+}
+
+impl From<prescriber::Model> for KnownPrescriber {
+    fn from(value: prescriber::Model) -> Self {
+        KnownPrescriber {
+            id: PrescriberId::from(value.prescriber_id),
+            prescriber: Prescriber {
+                name: value.name,
+                practice: value.practice,
+                phone: value.phone,
+                fax: value.fax,
+            },
+        }
+    }
+}
+
+/// Check the name and trim all fields of a prescriber.
+fn normalize(prescriber: &Prescriber) -> Result<Prescriber, Error> {
+    let name = prescriber.name.trim();
+    if name.is_empty() {
+        return Err(Error::EmptyPrescriberName);
+    }
+    Ok(Prescriber {
+        name: name.to_owned(),
+        practice: trimmed(&prescriber.practice),
+        phone: trimmed(&prescriber.phone),
+        fax: trimmed(&prescriber.fax),
+    })
+}
+
+/// Add a new prescriber, receiving the ID.
+pub async fn add_prescriber(
+    db: &impl ConnectionTrait,
+    prescriber: &Prescriber,
+) -> Result<PrescriberId, Error> {
+    let prescriber = normalize(prescriber)?;
+    let model = prescriber::ActiveModel {
+        name: Set(prescriber.name),
+        practice: Set(prescriber.practice),
+        phone: Set(prescriber.phone),
+        fax: Set(prescriber.fax),
+        ..Default::default()
+    };
+    let res = prescriber::Entity::insert(model).exec(db).await?;
+    Ok(PrescriberId::from(res.last_insert_id))
+}
+
+/// Replace the details of a prescriber.
+pub async fn update_prescriber(
+    db: &impl ConnectionTrait,
+    id: PrescriberId,
+    prescriber: &Prescriber,
+) -> Result<(), Error> {
+    let prescriber = normalize(prescriber)?;
+    let existing = prescriber::Entity::find_by_id(i32::from(id))
+        .one(db)
+        .await?
+        .ok_or(Error::NoSuchPrescriber(id))?;
+    let mut model: prescriber::ActiveModel = existing.into();
+    model.name = Set(prescriber.name);
+    model.practice = Set(prescriber.practice);
+    model.phone = Set(prescriber.phone);
+    model.fax = Set(prescriber.fax);
+    model.update(db).await?;
+    Ok(())
+}
+
+pub async fn list_prescribers(
+    db: &impl ConnectionTrait,
+) -> Result<Vec<KnownPrescriber>, sea_orm::DbErr> {
+    let result = prescriber::Entity::find()
+        .order_by_asc(prescriber::Column::PrescriberId)
+        .all(db)
+        .await?;
+    Ok(result.into_iter().map(KnownPrescriber::from).collect())
+}
+
+pub async fn get_prescriber(
+    db: &impl ConnectionTrait,
+    id: PrescriberId,
+) -> Result<Option<KnownPrescriber>, sea_orm::DbErr> {
+    let prescriber = prescriber::Entity::find_by_id(i32::from(id))
+        .one(db)
+        .await?;
+    Ok(prescriber.map(KnownPrescriber::from))
+}
+
+/// Set or clear the current prescriber of an rx.
+pub async fn set_rx_prescriber(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    prescriber: Option<PrescriberId>,
+) -> Result<(), Error> {
+    if let Some(id) = prescriber {
+        get_prescriber(db, id)
+            .await?
+            .ok_or(Error::NoSuchPrescriber(id))?;
+    }
+    let existing = rx_info::Entity::find_by_id(i32::from(rx))
+        .one(db)
+        .await?
+        .ok_or(Error::NoSuchRx(rx))?;
+    let mut model: rx_info::ActiveModel = existing.into();
+    model.prescriber_id = Set(prescriber.map(i32::from));
+    model.update(db).await?;
+    Ok(())
+}
+
+/// List prescriptions that are not hidden, optionally only those of a single person,
+/// grouped by their current prescriber.
+///
+/// Groups are in prescriber ID order, with prescriptions without a prescriber last.
+/// Prescribers without any prescriptions are left out.
+pub async fn list_rx_by_prescriber(
+    db: &impl ConnectionTrait,
+    person: Option<PersonId>,
+) -> Result<Vec<(Option<KnownPrescriber>, Vec<KnownRx>)>, Error> {
+    let mut groups: BTreeMap<Option<PrescriberId>, Vec<KnownRx>> = BTreeMap::new();
+    for rx in list_rx(db, person).await? {
+        groups.entry(rx.prescriber).or_default().push(rx);
+    }
+
+    let mut result = Vec::new();
+    let mut unassigned = None;
+    for (prescriber, rxs) in groups {
+        match prescriber {
+            Some(id) => result.push((get_prescriber(db, id).await?, rxs)),
+            None => unassigned = Some(rxs),
+        }
+    }
+    result.extend(unassigned.map(|rxs| (None, rxs)));
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::Database;
+
+    use super::*;
+    use crate::rx::{add_rx, get_rx, set_rx_hidden};
+
+    #[async_std::test]
+    async fn test_prescribers() -> Result<(), Error> {
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        assert!(list_prescribers(&db).await?.is_empty());
+        assert_eq!(
+            add_prescriber(&db, &Prescriber::default()).await,
+            Err(Error::EmptyPrescriberName)
+        );
+
+        let smith = Prescriber {
+            name: " Dr. Smith ".to_owned(),
+            practice: Some("Main Street Clinic".to_owned()),
+            phone: Some("555-0150".to_owned()),
+            fax: Some("".to_owned()),
+        };
+        let smith_id = add_prescriber(&db, &smith).await?;
+        let smith_data = get_prescriber(&db, smith_id).await?.unwrap();
+        assert_eq!(smith_data.prescriber.name, "Dr. Smith");
+        assert_eq!(smith_data.prescriber.fax, None);
+
+        let updated = Prescriber {
+            fax: Some("555-0151".to_owned()),
+            ..smith_data.prescriber
+        };
+        update_prescriber(&db, smith_id, &updated).await?;
+        assert_eq!(
+            get_prescriber(&db, smith_id).await?.unwrap().prescriber,
+            updated
+        );
+        let missing = PrescriberId::from(42);
+        assert_eq!(
+            update_prescriber(&db, missing, &updated).await,
+            Err(Error::NoSuchPrescriber(missing))
+        );
+        assert_eq!(list_prescribers(&db).await?.len(), 1);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_list_rx_by_prescriber() -> Result<(), Error> {
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        let pred_id = add_rx(&db, "prednisone").await?.id();
+        let vitd_id = add_rx(&db, "vitamin d").await?.id();
+        let hidden_id = add_rx(&db, "hidden").await?.id();
+        let smith = add_prescriber(
+            &db,
+            &Prescriber {
+                name: "Dr. Smith".to_owned(),
+                ..Default::default()
+            },
+        )
+        .await?;
+        let jones = add_prescriber(
+            &db,
+            &Prescriber {
+                name: "Dr. Jones".to_owned(),
+                ..Default::default()
+            },
+        )
+        .await?;
+        // Unused prescribers are left out
+        add_prescriber(
+            &db,
+            &Prescriber {
+                name: "Dr. Who".to_owned(),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+        set_rx_prescriber(&db, amox_id, Some(jones)).await?;
+        set_rx_prescriber(&db, pred_id, Some(smith)).await?;
+        set_rx_prescriber(&db, vitd_id, Some(smith)).await?;
+        set_rx_prescriber(&db, hidden_id, Some(smith)).await?;
+        set_rx_hidden(&db, hidden_id, true).await?;
+        // Changing prescriber
+        set_rx_prescriber(&db, vitd_id, None).await?;
+        assert_eq!(get_rx(&db, vitd_id).await?.unwrap().prescriber, None);
+        assert_eq!(
+            set_rx_prescriber(&db, vitd_id, Some(PrescriberId::from(42))).await,
+            Err(Error::NoSuchPrescriber(PrescriberId::from(42)))
+        );
+
+        let groups: Vec<_> = list_rx_by_prescriber(&db, None)
+            .await?
+            .into_iter()
+            .map(|(prescriber, rxs)| {
+                (
+                    prescriber.map(|prescriber| prescriber.id),
+                    rxs.into_iter().map(|rx| rx.id).collect::<Vec<_>>(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                (Some(smith), vec![pred_id]),
+                (Some(jones), vec![amox_id]),
+                (None, vec![vitd_id]),
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,285 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Configure the reminder policies of each rx.
+
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter,
+    QueryOrder,
+};
+
+use crate::{entities::reminder_policy, Error, EventType, ReminderPolicyId, RxId};
+
+/// Largest reminder offset accepted, in days either way.
+pub const MAX_OFFSET_DAYS: i32 = 366;
+
+/// How to compute a reminder date for an rx.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReminderPolicy {
+    /// The event whose latest date the reminder is computed from.
+    /// A policy without one never produces a reminder.
+    pub starting_event: Option<EventType>,
+    /// Whether to add the days supply of the rx
+    pub include_rx_duration: bool,
+    /// Number of days to add (or subtract, if negative)
+    pub offset_days: i32,
+    /// Whether a reminder may fall on a Saturday, rather than backing up to an earlier day
+    pub allow_saturday: bool,
+    /// Whether a reminder may fall on a Sunday, rather than backing up to an earlier day
+    pub allow_sunday: bool,
+    pub description: String,
+}
+
+impl From<reminder_policy::Model> for ReminderPolicy {
+    fn from(value: reminder_policy::Model) -> Self {
+        ReminderPolicy {
+            starting_event: value.starting_date,
+            include_rx_duration: value.include_rx_duration,
+            offset_days: value.offset,
+            allow_saturday: value.allow_saturday,
+            allow_sunday: value.allow_sunday,
+            description: value.description,
+        }
+    }
+}
+
+/// A reminder policy as stored for an rx.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownReminderPolicy {
+    pub id: ReminderPolicyId,
+    pub rx_id: RxId,
+    pub policy: ReminderPolicy,
+}
+
+impl From<reminder_policy::Model> for KnownReminderPolicy {
+    fn from(value: reminder_policy::Model) -> Self {
+        KnownReminderPolicy {
+            id: ReminderPolicyId::from(value.reminder_id),
+            rx_id: RxId::from(value.rx_id),
+            policy: value.into(),
+        }
+    }
+}
+
+/// Check a policy, returning the trimmed description.
+fn validate(policy: &ReminderPolicy) -> Result<String, Error> {
+    let description = policy.description.trim();
+    if description.is_empty() {
+        return Err(Error::EmptyReminderDescription);
+    }
+    if !(-MAX_OFFSET_DAYS..=MAX_OFFSET_DAYS).contains(&policy.offset_days) {
+        return Err(Error::ReminderOffsetOutOfRange(policy.offset_days));
+    }
+    Ok(description.to_owned())
+}
+
+/// Fill in the values of an active model from a policy, after validating it.
+fn set_values(
+    model: &mut reminder_policy::ActiveModel,
+    policy: &ReminderPolicy,
+) -> Result<(), Error> {
+    let description = validate(policy)?;
+    model.starting_date = Set(policy.starting_event);
+    model.include_rx_duration = Set(policy.include_rx_duration);
+    model.offset = Set(policy.offset_days);
+    model.allow_saturday = Set(policy.allow_saturday);
+    model.allow_sunday = Set(policy.allow_sunday);
+    model.description = Set(description);
+    Ok(())
+}
+
+/// Add a reminder policy to an rx, receiving the ID.
+pub async fn add_reminder_policy(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    policy: &ReminderPolicy,
+) -> Result<ReminderPolicyId, Error> {
+    let mut model = reminder_policy::ActiveModel {
+        rx_id: Set(rx.0),
+        ..Default::default()
+    };
+    set_values(&mut model, policy)?;
+    let res = reminder_policy::Entity::insert(model).exec(db).await?;
+    Ok(ReminderPolicyId::from(res.last_insert_id))
+}
+
+/// List the reminder policies of an rx, in the order they were added.
+pub async fn list_reminder_policies(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+) -> Result<Vec<KnownReminderPolicy>, Error> {
+    let result = reminder_policy::Entity::find()
+        .filter(reminder_policy::Column::RxId.eq(rx.0))
+        .order_by_asc(reminder_policy::Column::ReminderId)
+        .all(db)
+        .await?;
+    Ok(result.into_iter().map(KnownReminderPolicy::from).collect())
+}
+
+pub async fn get_reminder_policy(
+    db: &impl ConnectionTrait,
+    id: ReminderPolicyId,
+) -> Result<Option<KnownReminderPolicy>, Error> {
+    let policy = reminder_policy::Entity::find_by_id(i32::from(id))
+        .one(db)
+        .await?;
+    Ok(policy.map(KnownReminderPolicy::from))
+}
+
+/// Replace the settings of an existing reminder policy.
+pub async fn update_reminder_policy(
+    db: &impl ConnectionTrait,
+    id: ReminderPolicyId,
+    policy: &ReminderPolicy,
+) -> Result<(), Error> {
+    let existing = reminder_policy::Entity::find_by_id(i32::from(id))
+        .one(db)
+        .await?
+        .ok_or(Error::NoSuchReminderPolicy(id))?;
+    let mut model: reminder_policy::ActiveModel = existing.into();
+    set_values(&mut model, policy)?;
+    model.update(db).await?;
+    Ok(())
+}
+
+/// Remove a reminder policy.
+pub async fn remove_reminder_policy(
+    db: &impl ConnectionTrait,
+    id: ReminderPolicyId,
+) -> Result<(), Error> {
+    let res = reminder_policy::Entity::delete_by_id(i32::from(id))
+        .exec(db)
+        .await?;
+    if res.rows_affected == 0 {
+        return Err(Error::NoSuchReminderPolicy(id));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::{Database, Statement};
+
+    use super::*;
+    use crate::{entities::rx_info, rx::add_rx};
+
+    fn make_policy(offset_days: i32, description: &str) -> ReminderPolicy {
+        ReminderPolicy {
+            starting_event: Some(EventType::PickUp),
+            include_rx_duration: true,
+            offset_days,
+            allow_saturday: false,
+            allow_sunday: false,
+            description: description.to_owned(),
+        }
+    }
+
+    #[async_std::test]
+    async fn test_reminder_policies() -> Result<(), Error> {
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        let pred_id = add_rx(&db, "prednisone").await?.id();
+
+        assert_eq!(
+            add_reminder_policy(&db, amox_id, &make_policy(-7, "  ")).await,
+            Err(Error::EmptyReminderDescription)
+        );
+        assert_eq!(
+            add_reminder_policy(&db, amox_id, &make_policy(-1000, "Refill")).await,
+            Err(Error::ReminderOffsetOutOfRange(-1000))
+        );
+
+        let refill_id =
+            add_reminder_policy(&db, amox_id, &make_policy(-7, " Request refill ")).await?;
+        let call_id = add_reminder_policy(&db, amox_id, &make_policy(-3, "Call")).await?;
+        add_reminder_policy(&db, pred_id, &make_policy(-3, "Call")).await?;
+
+        let policies = list_reminder_policies(&db, amox_id).await?;
+        assert_eq!(policies.len(), 2);
+        assert_eq!(
+            policies[0],
+            KnownReminderPolicy {
+                id: refill_id,
+                rx_id: amox_id,
+                policy: make_policy(-7, "Request refill")
+            }
+        );
+        assert_eq!(policies[1].id, call_id);
+
+        let mut updated = make_policy(-2, "Call the pharmacy");
+        updated.starting_event = Some(EventType::RequestFill);
+        updated.allow_saturday = true;
+        update_reminder_policy(&db, call_id, &updated).await?;
+        assert_eq!(
+            get_reminder_policy(&db, call_id).await?.unwrap().policy,
+            updated
+        );
+        assert_eq!(
+            update_reminder_policy(&db, call_id, &make_policy(400, "Call")).await,
+            Err(Error::ReminderOffsetOutOfRange(400))
+        );
+
+        remove_reminder_policy(&db, call_id).await?;
+        assert_eq!(
+            remove_reminder_policy(&db, call_id).await,
+            Err(Error::NoSuchReminderPolicy(call_id))
+        );
+        assert_eq!(
+            update_reminder_policy(&db, call_id, &updated).await,
+            Err(Error::NoSuchReminderPolicy(call_id))
+        );
+        assert_eq!(list_reminder_policies(&db, amox_id).await?.len(), 1);
+        assert_eq!(list_reminder_policies(&db, pred_id).await?.len(), 1);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_convert_text_starting_date() -> Result<(), Error> {
+        // Migrate up to just before the conversion, and add policies the old way
+        let db = Database::connect("sqlite::memory:").await?;
+        let steps = Migrator::steps_before("m20230225_000001_integer_reminder_start").unwrap();
+        Migrator::up(&db, Some(steps)).await?;
+        let res = rx_info::Entity::insert(rx_info::ActiveModel {
+            rx_name: Set("amoxicillin".to_owned()),
+            ..Default::default()
+        })
+        .exec(&db)
+        .await?;
+        let amox_id = RxId::from(res.last_insert_id);
+        for starting_date in ["pick_up", "0", "whenever"] {
+            db.execute(Statement::from_sql_and_values(
+                db.get_database_backend(),
+                r#"INSERT INTO "reminder_policy" ("rx_id", "starting_date", "include_rx_duration", "offset", "allow_saturday", "allow_sunday", "description") VALUES (?, ?, ?, ?, ?, ?, ?)"#,
+                [
+                    i32::from(amox_id).into(),
+                    starting_date.into(),
+                    true.into(),
+                    (-7).into(),
+                    false.into(),
+                    false.into(),
+                    starting_date.into(),
+                ],
+            ))
+            .await?;
+        }
+
+        Migrator::up(&db, None).await?;
+        let starting_events: Vec<_> = list_reminder_policies(&db, amox_id)
+            .await?
+            .into_iter()
+            .map(|policy| (policy.policy.description, policy.policy.starting_event))
+            .collect();
+        assert_eq!(
+            starting_events,
+            vec![
+                ("pick_up".to_owned(), Some(EventType::PickUp)),
+                ("0".to_owned(), Some(EventType::RequestFill)),
+                ("whenever".to_owned(), None),
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,391 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Compute reminder dates for an rx from its reminder policies and its events.
+
+use sea_orm::{prelude::TimeDate, ConnectionTrait};
+use time::{Duration, Weekday};
+
+use crate::{
+    authorization::AuthorizationProblem,
+    days_supply::{days_supply_on, list_days_supply, DaysSupply},
+    dosing::{compute_supply_run_out, DoseRate},
+    events::{list_events, Event},
+    reminder_policy::{list_reminder_policies, ReminderPolicy},
+    rx::get_rx,
+    Error, EventType, ReminderPolicyId, RxId,
+};
+
+/// A reminder date computed from a single reminder policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reminder {
+    pub rx_id: RxId,
+    pub policy_id: ReminderPolicyId,
+    pub description: String,
+    pub date: TimeDate,
+    /// Why the refill cannot happen on that date without renewing the script first, if so
+    pub warning: Option<AuthorizationProblem>,
+}
+
+/// Move a date earlier until it no longer lands on a disallowed weekend day.
+fn back_up_from_weekend(date: TimeDate, allow_saturday: bool, allow_sunday: bool) -> TimeDate {
+    let mut date = date;
+    loop {
+        let disallowed = match date.weekday() {
+            Weekday::Saturday => !allow_saturday,
+            Weekday::Sunday => !allow_sunday,
+            _ => false,
+        };
+        if !disallowed {
+            return date;
+        }
+        date = date.previous_day().expect("date out of range");
+    }
+}
+
+/// Compute the reminder date for a single policy,
+/// given the events and days supply history of its rx in date order, and its dose rate if known.
+///
+/// Starts from the date of the latest event of the type the policy names,
+/// adds the days supply in effect on that date if requested, applies the offset in days,
+/// then backs up off of any disallowed weekend day.
+/// For a policy starting from a pick-up, the projected run-out date is used instead
+/// of adding the days supply, as it accounts for leftover supply.
+///
+/// Returns `None` if there is no such event yet, the policy does not name a starting event,
+/// or it requires an rx duration and no days supply is known.
+pub fn compute_reminder_date(
+    policy: &ReminderPolicy,
+    events: &[Event],
+    days_supply: &[DaysSupply],
+    dose: Option<DoseRate>,
+) -> Option<TimeDate> {
+    let starting_event = policy.starting_event?;
+    let start = events
+        .iter()
+        .rev()
+        .find(|event| event.event_type == starting_event)?
+        .date;
+    let date = match (policy.include_rx_duration, starting_event) {
+        (true, EventType::PickUp) => compute_run_out_date(events, days_supply, dose)?,
+        (true, _) => start.checked_add(days_supply_on(days_supply, start)?.duration())?,
+        (false, _) => start,
+    };
+    let date = date.checked_add(Duration::days(policy.offset_days.into()))?;
+    Some(back_up_from_weekend(
+        date,
+        policy.allow_saturday,
+        policy.allow_sunday,
+    ))
+}
+
+/// Compute the date an rx is expected to run out, given its events and days supply history in date order,
+/// and its dose rate if known.
+///
+/// With a dose rate and the quantity of the latest pick-up, this is projected from the quantities
+/// picked up (see [`compute_supply_run_out`]).
+/// Otherwise, it is the latest pick-up plus the days supply in effect on that date.
+///
+/// Returns `None` if it has never been picked up or neither is known.
+pub fn compute_run_out_date(
+    events: &[Event],
+    days_supply: &[DaysSupply],
+    dose: Option<DoseRate>,
+) -> Option<TimeDate> {
+    if let Some(run_out) = dose.and_then(|dose| compute_supply_run_out(events, dose)) {
+        return Some(run_out);
+    }
+    let pickup = events
+        .iter()
+        .rev()
+        .find(|event| event.event_type == EventType::PickUp)?
+        .date;
+    pickup.checked_add(days_supply_on(days_supply, pickup)?.duration())
+}
+
+/// Compute the date an rx is expected to run out, if known.
+pub async fn run_out_date(db: &impl ConnectionTrait, rx: RxId) -> Result<Option<TimeDate>, Error> {
+    let events = list_events(db, rx, ..).await?;
+    let days_supply = list_days_supply(db, rx).await?;
+    let dose = get_rx(db, rx).await?.and_then(|rx| rx.dose);
+    Ok(compute_run_out_date(&events, &days_supply, dose))
+}
+
+/// Compute the reminder dates for all policies of an rx that currently apply, soonest first.
+///
+/// Each is checked against the refills and expiry of the current script of the rx.
+pub async fn list_reminders(db: &impl ConnectionTrait, rx: RxId) -> Result<Vec<Reminder>, Error> {
+    let policies = list_reminder_policies(db, rx).await?;
+    if policies.is_empty() {
+        return Ok(vec![]);
+    }
+    let events = list_events(db, rx, ..).await?;
+    let days_supply = list_days_supply(db, rx).await?;
+    let known = get_rx(db, rx).await?;
+    let authorization = known
+        .as_ref()
+        .map(|rx| rx.authorization)
+        .unwrap_or_default();
+    let dose = known.and_then(|rx| rx.dose);
+    let mut reminders: Vec<Reminder> = policies
+        .into_iter()
+        .filter_map(|policy| {
+            let date = compute_reminder_date(&policy.policy, &events, &days_supply, dose)?;
+            Some(Reminder {
+                rx_id: rx,
+                policy_id: policy.id,
+                description: policy.policy.description,
+                date,
+                warning: authorization.problem_on(date),
+            })
+        })
+        .collect();
+    reminders.sort_by_key(|reminder| reminder.date);
+    Ok(reminders)
+}
+
+/// Compute the soonest reminder for an rx, if any.
+pub async fn next_reminder(db: &impl ConnectionTrait, rx: RxId) -> Result<Option<Reminder>, Error> {
+    Ok(list_reminders(db, rx).await?.into_iter().next())
+}
+
+#[cfg(test)]
+mod test {
+
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::Database;
+    use time::{Date, Month};
+
+    use super::*;
+    use crate::{
+        authorization::{set_authorization, Authorization},
+        days_supply::set_days_supply,
+        events::record_event,
+        reminder_policy::add_reminder_policy,
+        rx::add_rx,
+        EventId,
+    };
+
+    fn make_policy(
+        starting_event: EventType,
+        include_rx_duration: bool,
+        offset_days: i32,
+    ) -> ReminderPolicy {
+        ReminderPolicy {
+            starting_event: Some(starting_event),
+            include_rx_duration,
+            offset_days,
+            allow_saturday: false,
+            allow_sunday: false,
+            description: "Request refill".to_owned(),
+        }
+    }
+
+    fn make_event(id: i32, event_type: EventType, date: Date) -> Event {
+        Event {
+            id: EventId::from(id),
+            rx_id: RxId(1),
+            event_type,
+            date,
+            fill_request_id: None,
+            pharmacy: None,
+            quantity: None,
+        }
+    }
+
+    #[test]
+    fn test_back_up_from_weekend() {
+        // 2023-01-07 is a Saturday
+        let saturday = Date::from_calendar_date(2023, Month::January, 7).unwrap();
+        let sunday = saturday.next_day().unwrap();
+        let friday = saturday.previous_day().unwrap();
+        assert_eq!(back_up_from_weekend(friday, false, false), friday);
+        assert_eq!(back_up_from_weekend(saturday, true, true), saturday);
+        assert_eq!(back_up_from_weekend(saturday, false, true), friday);
+        assert_eq!(back_up_from_weekend(sunday, true, false), saturday);
+        assert_eq!(back_up_from_weekend(sunday, false, false), friday);
+    }
+
+    #[test]
+    fn test_compute_reminder_date() {
+        // 2023-01-02 is a Monday
+        let monday = Date::from_calendar_date(2023, Month::January, 2).unwrap();
+        let events = vec![
+            make_event(1, EventType::PickUp, monday - Duration::days(30)),
+            make_event(2, EventType::RequestFill, monday),
+            make_event(3, EventType::PickUp, monday),
+        ];
+        let thirty_days = [DaysSupply {
+            effective_date: monday,
+            days: 30,
+        }];
+
+        assert_eq!(
+            compute_reminder_date(
+                &make_policy(EventType::PickUp, false, 0),
+                &events,
+                &[],
+                None
+            ),
+            Some(monday)
+        );
+        // Latest pick-up, plus 30 days, minus 7 days: a Wednesday
+        assert_eq!(
+            compute_reminder_date(
+                &make_policy(EventType::PickUp, true, -7),
+                &events,
+                &thirty_days,
+                None
+            ),
+            Some(monday + Duration::days(23))
+        );
+        // Landing on a Sunday backs up to Friday
+        assert_eq!(
+            compute_reminder_date(
+                &make_policy(EventType::PickUp, true, -10),
+                &events,
+                &thirty_days,
+                None
+            ),
+            Some(monday + Duration::days(18))
+        );
+        // Cannot compute without a days supply, a matching event, or a starting event
+        assert_eq!(
+            compute_reminder_date(
+                &make_policy(EventType::PickUp, true, -7),
+                &events,
+                &[],
+                None
+            ),
+            None
+        );
+        assert_eq!(
+            compute_reminder_date(&make_policy(EventType::Fill, false, 0), &events, &[], None),
+            None
+        );
+        let mut policy = make_policy(EventType::PickUp, false, 0);
+        policy.starting_event = None;
+        assert_eq!(compute_reminder_date(&policy, &events, &[], None), None);
+    }
+
+    #[test]
+    fn test_compute_run_out_date() {
+        let monday = Date::from_calendar_date(2023, Month::January, 2).unwrap();
+        let thirty_days = [DaysSupply {
+            effective_date: monday,
+            days: 30,
+        }];
+        let events = vec![
+            make_event(1, EventType::PickUp, monday - Duration::days(30)),
+            make_event(2, EventType::PickUp, monday),
+            make_event(3, EventType::RequestFill, monday + Duration::days(20)),
+        ];
+        assert_eq!(
+            compute_run_out_date(&events, &thirty_days, None),
+            Some(monday + Duration::days(30))
+        );
+        assert_eq!(compute_run_out_date(&events, &[], None), None);
+        assert_eq!(compute_run_out_date(&events[2..], &thirty_days, None), None);
+
+        // With quantities, leftover supply from an early pick-up carries over
+        let mut events = events;
+        events[0].date = monday - Duration::days(25);
+        events[0].quantity = Some(60);
+        events[1].quantity = Some(60);
+        let twice_daily = Some(DoseRate::daily(2));
+        assert_eq!(
+            compute_run_out_date(&events, &thirty_days, twice_daily),
+            Some(monday + Duration::days(35))
+        );
+        // Without the latest quantity, falls back to the days supply
+        events[1].quantity = None;
+        assert_eq!(
+            compute_run_out_date(&events, &thirty_days, twice_daily),
+            Some(monday + Duration::days(30))
+        );
+        // The reminder follows the projected run-out date
+        events[1].quantity = Some(90);
+        assert_eq!(
+            compute_reminder_date(
+                &make_policy(EventType::PickUp, true, -7),
+                &events,
+                &thirty_days,
+                twice_daily
+            ),
+            Some(monday + Duration::days(43))
+        );
+    }
+
+    #[async_std::test]
+    async fn test_list_reminders() -> Result<(), Error> {
+        let monday = Date::from_calendar_date(2023, Month::January, 2).unwrap();
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        let pred_id = add_rx(&db, "prednisone").await?.id();
+
+        assert!(next_reminder(&db, amox_id).await?.is_none());
+
+        for (offset, description) in [(-7, "Request refill"), (-3, "Call pharmacy")] {
+            let mut policy = make_policy(EventType::PickUp, true, offset);
+            policy.description = description.to_owned();
+            add_reminder_policy(&db, amox_id, &policy).await?;
+        }
+        record_event(&db, amox_id, EventType::PickUp, monday, None).await?;
+        // No days supply yet
+        assert!(list_reminders(&db, amox_id).await?.is_empty());
+
+        set_days_supply(&db, amox_id, 30, monday).await?;
+        let reminders = list_reminders(&db, amox_id).await?;
+        assert_eq!(reminders.len(), 2);
+        assert_eq!(reminders[0].description, "Request refill");
+        assert_eq!(reminders[0].date, monday + Duration::days(23));
+        assert_eq!(reminders[1].description, "Call pharmacy");
+        // Sunday backs up to Friday
+        assert_eq!(reminders[1].date, monday + Duration::days(25));
+        assert_eq!(
+            next_reminder(&db, amox_id).await?,
+            reminders.into_iter().next()
+        );
+        assert!(list_reminders(&db, pred_id).await?.is_empty());
+        assert_eq!(next_reminder(&db, amox_id).await?.unwrap().warning, None);
+
+        // The script expires between the two reminders
+        let expires = monday + Duration::days(24);
+        set_authorization(
+            &db,
+            amox_id,
+            &Authorization {
+                refills_remaining: Some(2),
+                expires: Some(expires),
+                original_fill_pending: false,
+            },
+            monday,
+        )
+        .await?;
+        let warnings: Vec<_> = list_reminders(&db, amox_id)
+            .await?
+            .into_iter()
+            .map(|reminder| reminder.warning)
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![None, Some(AuthorizationProblem::Expired(expires))]
+        );
+
+        // A new script without refills still has its original fill to come
+        set_authorization(
+            &db,
+            amox_id,
+            &Authorization {
+                refills_remaining: Some(0),
+                expires: None,
+                original_fill_pending: true,
+            },
+            monday,
+        )
+        .await?;
+        assert_eq!(next_reminder(&db, amox_id).await?.unwrap().warning, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,419 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Requests to a prescriber to renew the script of an rx, once it has expired or run out of refills.
+//!
+//! A renewal is requested, then either denied, or approved and later received at the pharmacy.
+//! While it is open, it carries a follow-up date as its own reminder.
+
+use sea_orm::{
+    prelude::TimeDate, ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait,
+    EntityTrait, QueryFilter, QueryOrder,
+};
+use time::Duration;
+
+use crate::{
+    authorization::{set_authorization, Authorization},
+    entities::{renewal_request, rx_info},
+    rx::get_rx,
+    Error, PersonId, PrescriberId, RenewalId, RenewalStatus, RxId,
+};
+
+/// Days to wait for the next step of a renewal before following up, unless given.
+pub const DEFAULT_FOLLOW_UP_DAYS: i64 = 3;
+
+/// A request to renew the script of an rx.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Renewal {
+    pub id: RenewalId,
+    pub rx_id: RxId,
+    /// The prescriber asked, if known
+    pub prescriber: Option<PrescriberId>,
+    pub status: RenewalStatus,
+    pub date_requested: TimeDate,
+    pub date_approved: Option<TimeDate>,
+    pub date_denied: Option<TimeDate>,
+    pub deny_reason: Option<String>,
+    pub date_received: Option<TimeDate>,
+    /// When to follow up if nothing has happened, while the renewal is open
+    pub follow_up: Option<TimeDate>,
+    /// The refills and expiry of the new script, once approved
+    pub authorization: Option<Authorization>,
+}
+
+impl Renewal {
+    /// Whether the renewal is still waiting on the prescriber or the pharmacy.
+    pub fn is_open(&self) -> bool {
+        matches!(
+            self.status,
+            RenewalStatus::Requested | RenewalStatus::Approved
+        )
+    }
+}
+
+impl From<renewal_request::Model> for Renewal {
+    fn from(value: renewal_request::Model) -> Self {
+        let authorization = value.date_approved.map(|_| Authorization {
+            refills_remaining: value.refills.map(|refills| refills.max(0) as u32),
+            expires: value.expires,
+            original_fill_pending: true,
+        });
+        Renewal {
+            id: RenewalId::from(value.id),
+            rx_id: RxId::from(value.rx_id),
+            prescriber: value.prescriber_id.map(PrescriberId::from),
+            status: value.status,
+            date_requested: value.date_requested,
+            date_approved: value.date_approved,
+            date_denied: value.date_denied,
+            deny_reason: value.deny_reason,
+            date_received: value.date_received,
+            follow_up: value.follow_up_date,
+            authorization,
+        }
+    }
+}
+
+fn default_follow_up(date: TimeDate) -> Option<TimeDate> {
+    date.checked_add(Duration::days(DEFAULT_FOLLOW_UP_DAYS))
+}
+
+/// Find a renewal and check that it may move to the target status.
+async fn find_for_transition(
+    db: &impl ConnectionTrait,
+    id: RenewalId,
+    target: RenewalStatus,
+) -> Result<renewal_request::Model, Error> {
+    let renewal = renewal_request::Entity::find_by_id(i32::from(id))
+        .one(db)
+        .await?
+        .ok_or(Error::NoSuchRenewal(id))?;
+    let allowed_from = match target {
+        RenewalStatus::Requested => None,
+        RenewalStatus::Approved | RenewalStatus::Denied => Some(RenewalStatus::Requested),
+        RenewalStatus::Received => Some(RenewalStatus::Approved),
+    };
+    if allowed_from != Some(renewal.status) {
+        return Err(Error::InvalidRenewalTransition(id, renewal.status, target));
+    }
+    Ok(renewal)
+}
+
+/// The open renewal of an rx, if any.
+async fn find_open_renewal(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+) -> Result<Option<renewal_request::Model>, Error> {
+    let renewal = renewal_request::Entity::find()
+        .filter(renewal_request::Column::RxId.eq(i32::from(rx)))
+        .filter(
+            renewal_request::Column::Status
+                .is_in([RenewalStatus::Requested, RenewalStatus::Approved]),
+        )
+        .one(db)
+        .await?;
+    Ok(renewal)
+}
+
+/// Record asking the current prescriber of an rx to renew its script, receiving the ID.
+///
+/// Follows up after [`DEFAULT_FOLLOW_UP_DAYS`] unless another follow-up date is given.
+/// Fails if the rx already has an open renewal.
+pub async fn request_renewal(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    date: TimeDate,
+    follow_up: Option<TimeDate>,
+) -> Result<RenewalId, Error> {
+    let known = get_rx(db, rx).await?.ok_or(Error::NoSuchRx(rx))?;
+    if let Some(existing) = find_open_renewal(db, rx).await? {
+        return Err(Error::RenewalAlreadyOpen(RenewalId::from(existing.id)));
+    }
+    let renewal = renewal_request::ActiveModel {
+        rx_id: Set(i32::from(rx)),
+        prescriber_id: Set(known.prescriber.map(i32::from)),
+        status: Set(RenewalStatus::Requested),
+        date_requested: Set(date),
+        follow_up_date: Set(follow_up.or_else(|| default_follow_up(date))),
+        ..Default::default()
+    };
+    let res = renewal_request::Entity::insert(renewal).exec(db).await?;
+    Ok(RenewalId::from(res.last_insert_id))
+}
+
+/// Record that the prescriber approved a renewal, with the refills and expiry of the new script.
+///
+/// The new script becomes the authorization of the rx,
+/// and the renewal follows up on it reaching the pharmacy.
+/// Its first pick-up, from the day it was approved on, is the original fill,
+/// so its refills are only used after that.
+pub async fn approve_renewal(
+    db: &impl ConnectionTrait,
+    id: RenewalId,
+    date: TimeDate,
+    authorization: &Authorization,
+) -> Result<(), Error> {
+    let renewal = find_for_transition(db, id, RenewalStatus::Approved).await?;
+    let rx = RxId::from(renewal.rx_id);
+    let mut renewal: renewal_request::ActiveModel = renewal.into();
+    renewal.status = Set(RenewalStatus::Approved);
+    renewal.date_approved = Set(Some(date));
+    renewal.refills = Set(authorization
+        .refills_remaining
+        .map(|refills| refills.try_into().unwrap_or(i32::MAX)));
+    renewal.expires = Set(authorization.expires);
+    renewal.follow_up_date = Set(default_follow_up(date));
+    renewal.update(db).await?;
+    let authorization = Authorization {
+        original_fill_pending: true,
+        ..*authorization
+    };
+    set_authorization(db, rx, &authorization, date).await
+}
+
+/// Record that the prescriber denied a renewal, closing it.
+pub async fn deny_renewal(
+    db: &impl ConnectionTrait,
+    id: RenewalId,
+    date: TimeDate,
+    reason: &str,
+) -> Result<(), Error> {
+    let renewal = find_for_transition(db, id, RenewalStatus::Denied).await?;
+    let mut renewal: renewal_request::ActiveModel = renewal.into();
+    renewal.status = Set(RenewalStatus::Denied);
+    renewal.date_denied = Set(Some(date));
+    renewal.deny_reason = Set(Some(reason.trim().to_owned()));
+    renewal.follow_up_date = Set(None);
+    renewal.update(db).await?;
+    Ok(())
+}
+
+/// Record that the new script of an approved renewal reached the pharmacy, closing it.
+pub async fn record_renewal_received(
+    db: &impl ConnectionTrait,
+    id: RenewalId,
+    date: TimeDate,
+) -> Result<(), Error> {
+    let renewal = find_for_transition(db, id, RenewalStatus::Received).await?;
+    let mut renewal: renewal_request::ActiveModel = renewal.into();
+    renewal.status = Set(RenewalStatus::Received);
+    renewal.date_received = Set(Some(date));
+    renewal.follow_up_date = Set(None);
+    renewal.update(db).await?;
+    Ok(())
+}
+
+/// Move the follow-up reminder of an open renewal, e.g. when the prescriber asks for more time.
+pub async fn set_renewal_follow_up(
+    db: &impl ConnectionTrait,
+    id: RenewalId,
+    date: TimeDate,
+) -> Result<(), Error> {
+    let renewal = get_renewal(db, id).await?.ok_or(Error::NoSuchRenewal(id))?;
+    if !renewal.is_open() {
+        return Err(Error::InvalidRenewalTransition(
+            id,
+            renewal.status,
+            renewal.status,
+        ));
+    }
+    renewal_request::ActiveModel {
+        id: Set(i32::from(id)),
+        follow_up_date: Set(Some(date)),
+        ..Default::default()
+    }
+    .update(db)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_renewal(
+    db: &impl ConnectionTrait,
+    id: RenewalId,
+) -> Result<Option<Renewal>, Error> {
+    let renewal = renewal_request::Entity::find_by_id(i32::from(id))
+        .one(db)
+        .await?;
+    Ok(renewal.map(Renewal::from))
+}
+
+/// List the renewals of an rx, oldest first.
+pub async fn list_renewals(db: &impl ConnectionTrait, rx: RxId) -> Result<Vec<Renewal>, Error> {
+    let renewals = renewal_request::Entity::find()
+        .filter(renewal_request::Column::RxId.eq(i32::from(rx)))
+        .order_by_asc(renewal_request::Column::Id)
+        .all(db)
+        .await?;
+    Ok(renewals.into_iter().map(Renewal::from).collect())
+}
+
+/// List all open renewals, optionally only for the prescriptions of a single person.
+pub async fn list_open_renewals(
+    db: &impl ConnectionTrait,
+    person: Option<PersonId>,
+) -> Result<Vec<Renewal>, Error> {
+    let query = renewal_request::Entity::find().filter(
+        renewal_request::Column::Status.is_in([RenewalStatus::Requested, RenewalStatus::Approved]),
+    );
+    let query = match person {
+        Some(person) => query
+            .inner_join(rx_info::Entity)
+            .filter(rx_info::Column::PersonId.eq(i32::from(person))),
+        None => query,
+    };
+    let renewals = query
+        .order_by_asc(renewal_request::Column::RxId)
+        .all(db)
+        .await?;
+    Ok(renewals.into_iter().map(Renewal::from).collect())
+}
+
+#[cfg(test)]
+mod test {
+
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::Database;
+    use time::{Date, Month};
+
+    use super::*;
+    use crate::{
+        corrections::void_fill_request_step,
+        fill_request::record_pickup,
+        prescriber::{add_prescriber, set_rx_prescriber, Prescriber},
+        rx::add_rx,
+        EventType,
+    };
+
+    #[async_std::test]
+    async fn test_renewal_lifecycle() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::April, 29).unwrap();
+        let day = |n| date + Duration::days(n);
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        let smith = add_prescriber(
+            &db,
+            &Prescriber {
+                name: "Dr. Smith".to_owned(),
+                ..Default::default()
+            },
+        )
+        .await?;
+        set_rx_prescriber(&db, amox_id, Some(smith)).await?;
+
+        let id = request_renewal(&db, amox_id, date, None).await?;
+        let renewal = get_renewal(&db, id).await?.unwrap();
+        assert_eq!(renewal.status, RenewalStatus::Requested);
+        assert_eq!(renewal.prescriber, Some(smith));
+        assert_eq!(renewal.follow_up, Some(day(DEFAULT_FOLLOW_UP_DAYS)));
+        assert!(renewal.is_open());
+        assert_eq!(
+            request_renewal(&db, amox_id, date, None).await,
+            Err(Error::RenewalAlreadyOpen(id))
+        );
+        assert_eq!(
+            record_renewal_received(&db, id, day(1)).await,
+            Err(Error::InvalidRenewalTransition(
+                id,
+                RenewalStatus::Requested,
+                RenewalStatus::Received
+            ))
+        );
+
+        set_renewal_follow_up(&db, id, day(7)).await?;
+        assert_eq!(get_renewal(&db, id).await?.unwrap().follow_up, Some(day(7)));
+
+        let authorization = Authorization {
+            refills_remaining: Some(5),
+            expires: Some(day(365)),
+            original_fill_pending: true,
+        };
+        approve_renewal(&db, id, day(2), &authorization).await?;
+        let renewal = get_renewal(&db, id).await?.unwrap();
+        assert_eq!(renewal.status, RenewalStatus::Approved);
+        assert_eq!(renewal.authorization, Some(authorization));
+        assert_eq!(renewal.follow_up, Some(day(2 + DEFAULT_FOLLOW_UP_DAYS)));
+        // The new script is now the authorization of the rx
+        assert_eq!(
+            get_rx(&db, amox_id).await?.unwrap().authorization,
+            authorization
+        );
+        assert_eq!(list_open_renewals(&db, None).await?.len(), 1);
+
+        record_renewal_received(&db, id, day(3)).await?;
+        let renewal = get_renewal(&db, id).await?.unwrap();
+        assert!(!renewal.is_open());
+        assert_eq!(renewal.date_received, Some(day(3)));
+        assert_eq!(renewal.follow_up, None);
+        assert!(list_open_renewals(&db, None).await?.is_empty());
+
+        // A closed renewal does not block the next one
+        let second = request_renewal(&db, amox_id, day(300), Some(day(301))).await?;
+        deny_renewal(&db, second, day(302), " Needs an appointment ").await?;
+        let renewal = get_renewal(&db, second).await?.unwrap();
+        assert_eq!(renewal.status, RenewalStatus::Denied);
+        assert_eq!(renewal.deny_reason.as_deref(), Some("Needs an appointment"));
+        assert_eq!(renewal.authorization, None);
+        assert_eq!(
+            approve_renewal(&db, second, day(303), &authorization).await,
+            Err(Error::InvalidRenewalTransition(
+                second,
+                RenewalStatus::Denied,
+                RenewalStatus::Approved
+            ))
+        );
+        assert_eq!(list_renewals(&db, amox_id).await?.len(), 2);
+        let missing = RenewalId::from(42);
+        assert_eq!(
+            deny_renewal(&db, missing, date, "").await,
+            Err(Error::NoSuchRenewal(missing))
+        );
+        Ok(())
+    }
+
+    async fn current(db: &impl ConnectionTrait, rx: RxId) -> Result<Authorization, Error> {
+        Ok(get_rx(db, rx)
+            .await?
+            .ok_or(Error::NoSuchRx(rx))?
+            .authorization)
+    }
+
+    #[async_std::test]
+    async fn test_original_fill() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::April, 29).unwrap();
+        let day = |n| date + Duration::days(n);
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+
+        let id = request_renewal(&db, amox_id, date, None).await?;
+        // Approving always leaves the original fill to be picked up
+        let renewed = Authorization {
+            refills_remaining: Some(2),
+            expires: None,
+            original_fill_pending: false,
+        };
+        approve_renewal(&db, id, day(2), &renewed).await?;
+        assert!(current(&db, amox_id).await?.original_fill_pending);
+
+        // Picked up the day it was approved, it is the original fill
+        let original = record_pickup(&db, amox_id, Some(day(2)), day(2)).await?;
+        let script = current(&db, amox_id).await?;
+        assert!(!script.original_fill_pending);
+        assert_eq!(script.refills_remaining, Some(2));
+        // The next pick-up is the first refill
+        record_pickup(&db, amox_id, Some(day(32)), day(32)).await?;
+        assert_eq!(current(&db, amox_id).await?.refills_remaining, Some(1));
+
+        // Voiding the original fill leaves it to be picked up again
+        void_fill_request_step(&db, original, EventType::PickUp, "").await?;
+        let script = current(&db, amox_id).await?;
+        assert!(script.original_fill_pending);
+        assert_eq!(script.refills_remaining, Some(1));
+        record_pickup(&db, amox_id, Some(day(33)), day(33)).await?;
+        let script = current(&db, amox_id).await?;
+        assert!(!script.original_fill_pending);
+        assert_eq!(script.refills_remaining, Some(1));
+        Ok(())
+    }
+}
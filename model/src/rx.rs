@@ -1,11 +1,16 @@
 // Copyright 2022, Ryan Pavlik <ryan@ryanpavlik.com>
 // SPDX-License-Identifier: GPL3+
 
-use sea_orm::{ActiveValue::Set, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter};
+use sea_orm::{
+    prelude::TimeDate, sea_query::Expr, ActiveModelTrait, ActiveValue::Set, ColumnTrait,
+    ConnectionTrait, EntityTrait, QueryFilter, Select,
+};
 
 use crate::{
+    authorization::Authorization,
+    dosing::DoseRate,
     entities::{self, rx_info},
-    Error, RxId,
+    Error, PersonId, PharmacyId, PrescriberId, RxId,
 };
 
 pub trait GetRxId {
@@ -38,30 +43,101 @@ impl RxInfo for rx_info::Model {
     }
 }
 
-// pub enum RxAddOutcome {
-//     AlreadyExists(RxId),
-//     Created(RxId),
-// }
+/// The result of adding a prescription.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RxAddOutcome {
+    /// A prescription with the same normalized name already existed for that person
+    AlreadyExists(RxId),
+    /// A new prescription was added
+    Created(RxId),
+}
+
+impl RxAddOutcome {
+    /// The ID of the prescription, whether new or existing.
+    pub fn id(&self) -> RxId {
+        match self {
+            RxAddOutcome::AlreadyExists(id) | RxAddOutcome::Created(id) => *id,
+        }
+    }
+}
+
+/// Normalize an rx name for detecting duplicates: trimmed, with whitespace collapsed, in lowercase.
+pub fn normalize_rx_name(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Find the active prescription of a person (or of nobody) with the given normalized name.
+async fn find_rx_by_normalized_name(
+    db: &impl ConnectionTrait,
+    person: Option<PersonId>,
+    normalized_name: &str,
+) -> Result<Option<RxId>, Error> {
+    let person_condition = match person {
+        Some(person) => rx_info::Column::PersonId.eq(person.0),
+        None => rx_info::Column::PersonId.is_null(),
+    };
+    let existing = rx_info::Entity::find()
+        .filter(person_condition)
+        .filter(rx_info::Column::NormalizedName.eq(normalized_name))
+        .one(db)
+        .await?;
+    Ok(existing.map(|rx| RxId::from(rx.rx_id)))
+}
 
-/// Add a new prescription, receiving the ID.
-pub async fn add_rx(db: &impl ConnectionTrait, name: &str) -> Result<RxId, Error> {
+async fn insert_rx(
+    db: &impl ConnectionTrait,
+    person: Option<PersonId>,
+    name: &str,
+) -> Result<RxAddOutcome, Error> {
     let name = name.trim();
     if name.is_empty() {
         return Err(Error::EmptyRxName);
     }
-    let rx = rx_info::ActiveModel {
+    let normalized_name = normalize_rx_name(name);
+    if let Some(existing) = find_rx_by_normalized_name(db, person, &normalized_name).await? {
+        return Ok(RxAddOutcome::AlreadyExists(existing));
+    }
+    let mut rx = rx_info::ActiveModel {
         rx_name: Set(name.to_owned()),
+        normalized_name: Set(Some(normalized_name)),
         ..Default::default()
     };
-    let res = rx_info::Entity::insert(rx)
-        // .on_conflict(
-        //     OnConflict::column(rx_info::Column::RxName)
-        //         .do_nothing()
-        //         .to_owned(),
-        // )
-        .exec(db)
-        .await?;
-    Ok(RxId(res.last_insert_id))
+    if let Some(person) = person {
+        rx.person_id = Set(Some(person.0));
+    }
+    let res = rx_info::Entity::insert(rx).exec(db).await?;
+    Ok(RxAddOutcome::Created(RxId(res.last_insert_id)))
+}
+
+/// Add a new prescription not associated with any person.
+///
+/// If one with the same name, ignoring case and whitespace, already exists and is not retired,
+/// receive its ID instead.
+pub async fn add_rx(db: &impl ConnectionTrait, name: &str) -> Result<RxAddOutcome, Error> {
+    insert_rx(db, None, name).await
+}
+
+/// Add a new prescription for a person.
+///
+/// Prescriptions for different people are separate, even if they have the same name.
+/// If the person already has one with the same name, ignoring case and whitespace,
+/// that is not retired, receive its ID instead.
+pub async fn add_person_rx(
+    db: &impl ConnectionTrait,
+    person: PersonId,
+    name: &str,
+) -> Result<RxAddOutcome, Error> {
+    insert_rx(db, Some(person), name).await
+}
+
+/// When and why an rx was permanently retired, e.g. because it was stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Retirement {
+    pub date: TimeDate,
+    pub reason: String,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -69,20 +145,59 @@ pub struct KnownRx {
     pub id: RxId,
     pub name: String,
     pub hidden: bool,
+    pub person: Option<PersonId>,
+    pub retired: Option<Retirement>,
+    /// The pharmacy the rx is usually filled at
+    pub pharmacy: Option<PharmacyId>,
+    /// The current prescriber of the rx, who renews it
+    pub prescriber: Option<PrescriberId>,
+    /// Refills and expiry of the current script
+    pub authorization: Authorization,
+    /// How fast the rx is used, if known
+    pub dose: Option<DoseRate>,
+    /// For a critical rx, alert when fewer than this many days are on hand
+    pub critical_days: Option<u32>,
 }
 
 impl From<rx_info::Model> for KnownRx {
     fn from(value: rx_info::Model) -> Self {
+        let authorization = Authorization::from(&value);
+        let dose = DoseRate::from_columns(value.dose_units, value.dose_every_days);
         KnownRx {
             id: RxId::from(value.rx_id),
             name: value.rx_name,
             hidden: value.hidden,
+            person: value.person_id.map(PersonId::from),
+            retired: value.retired_date.map(|date| Retirement {
+                date,
+                reason: value.retired_reason.unwrap_or_default(),
+            }),
+            pharmacy: value.pharmacy_id.map(PharmacyId::from),
+            prescriber: value.prescriber_id.map(PrescriberId::from),
+            authorization,
+            dose,
+            critical_days: value
+                .critical_days
+                .and_then(|days| u32::try_from(days).ok()),
         }
     }
 }
 
-pub async fn list_rx(db: &impl ConnectionTrait) -> Result<Vec<KnownRx>, sea_orm::DbErr> {
-    let result = rx_info::Entity::find()
+/// Build a query for prescriptions, optionally only those of a single person.
+fn find_rx(person: Option<PersonId>) -> Select<rx_info::Entity> {
+    let query = rx_info::Entity::find();
+    match person {
+        Some(person) => query.filter(rx_info::Column::PersonId.eq(person.0)),
+        None => query,
+    }
+}
+
+/// List prescriptions that are not hidden, optionally only those of a single person.
+pub async fn list_rx(
+    db: &impl ConnectionTrait,
+    person: Option<PersonId>,
+) -> Result<Vec<KnownRx>, sea_orm::DbErr> {
+    let result = find_rx(person)
         .filter(rx_info::Column::Hidden.eq(false))
         .all(db)
         .await?;
@@ -90,8 +205,12 @@ pub async fn list_rx(db: &impl ConnectionTrait) -> Result<Vec<KnownRx>, sea_orm:
     Ok(v)
 }
 
-pub async fn list_all_rx(db: &impl ConnectionTrait) -> Result<Vec<KnownRx>, sea_orm::DbErr> {
-    let result = rx_info::Entity::find().all(db).await?;
+/// List all prescriptions, including hidden ones, optionally only those of a single person.
+pub async fn list_all_rx(
+    db: &impl ConnectionTrait,
+    person: Option<PersonId>,
+) -> Result<Vec<KnownRx>, sea_orm::DbErr> {
+    let result = find_rx(person).all(db).await?;
     let v: Vec<KnownRx> = result.into_iter().map(KnownRx::from).collect();
     Ok(v)
 }
@@ -104,14 +223,106 @@ pub async fn get_rx(
     Ok(rx.map(KnownRx::from))
 }
 
+/// The pharmacy an rx is usually filled at, if any.
+pub(crate) async fn get_default_pharmacy(
+    db: &impl ConnectionTrait,
+    id: RxId,
+) -> Result<Option<PharmacyId>, Error> {
+    let rx = rx_info::Entity::find_by_id(id.0)
+        .one(db)
+        .await?
+        .ok_or(Error::NoSuchRx(id))?;
+    Ok(rx.pharmacy_id.map(PharmacyId::from))
+}
+
+/// Change the name of a prescription, e.g. when the dose changes.
+///
+/// Fails if another active prescription of the same person already has that name,
+/// ignoring case and whitespace.
+pub async fn rename_rx(db: &impl ConnectionTrait, id: RxId, name: &str) -> Result<(), Error> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(Error::EmptyRxName);
+    }
+    let rx = rx_info::Entity::find_by_id(id.0)
+        .one(db)
+        .await?
+        .ok_or(Error::NoSuchRx(id))?;
+    let person = rx.person_id.map(PersonId::from);
+    let retired = rx.retired_date.is_some();
+    let normalized_name = normalize_rx_name(name);
+    if !retired {
+        match find_rx_by_normalized_name(db, person, &normalized_name).await? {
+            Some(existing) if existing != id => return Err(Error::DuplicateRxName(existing)),
+            _ => {}
+        }
+    }
+    let mut rx: rx_info::ActiveModel = rx.into();
+    rx.rx_name = Set(name.to_owned());
+    // Retired prescriptions do not take part in duplicate detection
+    if !retired {
+        rx.normalized_name = Set(Some(normalized_name));
+    }
+    rx.update(db).await?;
+    Ok(())
+}
+
+/// Hide or unhide a prescription.
+///
+/// A retired prescription stays hidden.
+pub async fn set_rx_hidden(db: &impl ConnectionTrait, id: RxId, hidden: bool) -> Result<(), Error> {
+    let rx = rx_info::Entity::find_by_id(id.0)
+        .one(db)
+        .await?
+        .ok_or(Error::NoSuchRx(id))?;
+    if !hidden && rx.retired_date.is_some() {
+        return Err(Error::RxRetired(id));
+    }
+    rx_info::Entity::update_many()
+        .col_expr(rx_info::Column::Hidden, Expr::value(hidden))
+        .filter(rx_info::Column::RxId.eq(id.0))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// Permanently retire a prescription, e.g. because it was stopped, recording when and why.
+///
+/// A retired prescription is hidden, and cannot be unhidden or retired again.
+/// Its name is free to be used by a new prescription.
+pub async fn retire_rx(
+    db: &impl ConnectionTrait,
+    id: RxId,
+    date: TimeDate,
+    reason: &str,
+) -> Result<(), Error> {
+    let rx = rx_info::Entity::find_by_id(id.0)
+        .one(db)
+        .await?
+        .ok_or(Error::NoSuchRx(id))?;
+    if rx.retired_date.is_some() {
+        return Err(Error::RxRetired(id));
+    }
+    let mut rx: rx_info::ActiveModel = rx.into();
+    rx.hidden = Set(true);
+    rx.retired_date = Set(Some(date));
+    rx.retired_reason = Set(Some(reason.trim().to_owned()));
+    rx.normalized_name = Set(None);
+    rx.update(db).await?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
 
     use migration::{Migrator, MigratorTrait};
-    use sea_orm::{Database, DatabaseBackend, MockDatabase, MockExecResult, Transaction};
+    use sea_orm::{
+        Database, DatabaseBackend, DatabaseConnection, MockDatabase, MockExecResult, Transaction,
+    };
+    use time::{Date, Month};
 
     use super::*;
-    use crate::Error;
+    use crate::{person::add_person, Error};
 
     // async fn setup_schema(db: &impl ConnectionTrait) -> Result<(), Error> {
     //     let schema = Schema::new(DatabaseBackend::Sqlite);
@@ -134,27 +345,234 @@ mod test {
     async fn test_operation() -> Result<(), Error> {
         let db = Database::connect("sqlite::memory:").await?;
         Migrator::up(&db, None).await?;
-        assert!(list_rx(&db).await?.is_empty());
-        let amox_id = add_rx(&db, "amoxicillin").await?;
+        assert!(list_rx(&db, None).await?.is_empty());
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
 
         let amox_data = get_rx(&db, amox_id).await?;
         assert!(amox_data.is_some());
         let amox_data = amox_data.unwrap();
         assert_eq!(amox_data.name, "amoxicillin");
         assert_eq!(amox_data.id, amox_id);
-        assert_eq!(amox_data.hidden, false);
+        assert!(!amox_data.hidden);
 
-        let pred_id = add_rx(&db, "prednisone").await?;
+        let pred_id = add_rx(&db, "prednisone").await?.id();
 
-        let rxs = list_rx(&db).await?;
+        let rxs = list_rx(&db, None).await?;
         assert_eq!(rxs.len(), 2);
         assert!(rxs.contains(&amox_data));
         assert!(rxs
             .iter()
             .any(|e| e.name == "prednisone" && e.id == pred_id));
+
+        set_rx_hidden(&db, amox_id, true).await?;
+        assert!(get_rx(&db, amox_id).await?.unwrap().hidden);
+        assert_eq!(list_rx(&db, None).await?.len(), 1);
+        assert_eq!(list_all_rx(&db, None).await?.len(), 2);
+        set_rx_hidden(&db, amox_id, false).await?;
+        assert_eq!(list_rx(&db, None).await?.len(), 2);
+        assert_eq!(
+            set_rx_hidden(&db, RxId(42), true).await,
+            Err(Error::NoSuchRx(RxId(42)))
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_rename_and_retire() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::March, 4).unwrap();
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin 250mg").await?.id();
+        let pred_id = add_rx(&db, "prednisone").await?.id();
+
+        rename_rx(&db, amox_id, " amoxicillin 500mg ").await?;
+        assert_eq!(
+            get_rx(&db, amox_id).await?.unwrap().name,
+            "amoxicillin 500mg"
+        );
+        assert_eq!(rename_rx(&db, amox_id, " ").await, Err(Error::EmptyRxName));
+        assert_eq!(
+            rename_rx(&db, RxId(42), "ibuprofen").await,
+            Err(Error::NoSuchRx(RxId(42)))
+        );
+
+        assert_eq!(get_rx(&db, pred_id).await?.unwrap().retired, None);
+        retire_rx(&db, pred_id, date, "Course finished ").await?;
+        let pred = get_rx(&db, pred_id).await?.unwrap();
+        assert!(pred.hidden);
+        assert_eq!(
+            pred.retired,
+            Some(Retirement {
+                date,
+                reason: "Course finished".to_owned()
+            })
+        );
+        assert_eq!(list_rx(&db, None).await?.len(), 1);
+        assert_eq!(list_all_rx(&db, None).await?.len(), 2);
+
+        assert_eq!(
+            set_rx_hidden(&db, pred_id, false).await,
+            Err(Error::RxRetired(pred_id))
+        );
+        assert_eq!(
+            retire_rx(&db, pred_id, date, "again").await,
+            Err(Error::RxRetired(pred_id))
+        );
+        assert_eq!(
+            retire_rx(&db, RxId(42), date, "").await,
+            Err(Error::NoSuchRx(RxId(42)))
+        );
+        // Renaming a retired prescription is still allowed
+        rename_rx(&db, pred_id, "prednisone 10mg").await?;
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_person_rx() -> Result<(), Error> {
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let alice = add_person(&db, "Alice").await?;
+        let bob = add_person(&db, "Bob").await?;
+
+        let alice_amox = add_person_rx(&db, alice, "amoxicillin").await?.id();
+        let bob_amox = add_person_rx(&db, bob, "amoxicillin").await?.id();
+        let unassigned = add_rx(&db, "ibuprofen").await?.id();
+        assert_ne!(alice_amox, bob_amox);
+
+        let alice_rxs = list_rx(&db, Some(alice)).await?;
+        assert_eq!(alice_rxs.len(), 1);
+        assert_eq!(alice_rxs[0].id, alice_amox);
+        assert_eq!(alice_rxs[0].person, Some(alice));
+
+        let bob_rxs = list_all_rx(&db, Some(bob)).await?;
+        assert_eq!(bob_rxs.len(), 1);
+        assert_eq!(bob_rxs[0].id, bob_amox);
+
+        assert_eq!(list_all_rx(&db, None).await?.len(), 3);
+        assert_eq!(get_rx(&db, unassigned).await?.unwrap().person, None);
         Ok(())
     }
 
+    #[async_std::test]
+    async fn test_duplicate_rx() -> Result<(), Error> {
+        let date = Date::from_calendar_date(2023, Month::March, 11).unwrap();
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let alice = add_person(&db, "Alice").await?;
+
+        let amox_id = add_rx(&db, "Amoxicillin 500mg").await?.id();
+        assert_eq!(
+            add_rx(&db, " amoxicillin   500MG ").await?,
+            RxAddOutcome::AlreadyExists(amox_id)
+        );
+        // Other people are separate
+        let alice_amox = add_person_rx(&db, alice, "amoxicillin 500mg").await?;
+        assert!(matches!(alice_amox, RxAddOutcome::Created(id) if id != amox_id));
+        assert_eq!(
+            add_person_rx(&db, alice, "AMOXICILLIN 500mg").await?,
+            RxAddOutcome::AlreadyExists(alice_amox.id())
+        );
+        // Hidden prescriptions still count
+        set_rx_hidden(&db, amox_id, true).await?;
+        assert_eq!(
+            add_rx(&db, "amoxicillin 500mg").await?,
+            RxAddOutcome::AlreadyExists(amox_id)
+        );
+
+        let pred_id = add_rx(&db, "prednisone").await?.id();
+        assert_eq!(
+            rename_rx(&db, pred_id, "AMOXICILLIN 500mg").await,
+            Err(Error::DuplicateRxName(amox_id))
+        );
+        // Changing only the case is fine
+        rename_rx(&db, pred_id, "Prednisone").await?;
+
+        // Retired prescriptions free up their name
+        retire_rx(&db, amox_id, date, "").await?;
+        let new_amox = add_rx(&db, "amoxicillin 500mg").await?;
+        assert!(matches!(new_amox, RxAddOutcome::Created(id) if id != amox_id));
+        rename_rx(&db, amox_id, "prednisone").await?;
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_normalize_existing_names() -> Result<(), Error> {
+        // Migrate up to just before normalized names, with some duplicates already present
+        let db = Database::connect("sqlite::memory:").await?;
+        let steps = Migrator::steps_before("m20230311_000001_normalized_rx_name").unwrap();
+        Migrator::up(&db, Some(steps)).await?;
+        let mut ids = vec![];
+        for name in ["Amoxicillin", "amoxicillin ", "prednisone"] {
+            let res = rx_info::Entity::insert(rx_info::ActiveModel {
+                rx_name: Set(name.to_owned()),
+                ..Default::default()
+            })
+            .exec(&db)
+            .await?;
+            ids.push(RxId::from(res.last_insert_id));
+        }
+
+        Migrator::up(&db, None).await?;
+        // The first of the duplicates is the one found
+        assert_eq!(
+            add_rx(&db, "AMOXICILLIN").await?,
+            RxAddOutcome::AlreadyExists(ids[0])
+        );
+        assert_eq!(
+            add_rx(&db, "Prednisone").await?,
+            RxAddOutcome::AlreadyExists(ids[2])
+        );
+        Ok(())
+    }
+
+    /// Mock database for adding an rx that does not already exist.
+    fn make_insert_mock_db() -> DatabaseConnection {
+        MockDatabase::new(DatabaseBackend::Postgres)
+            // No existing rx with the same name, then the inserted row
+            .append_query_results(vec![
+                vec![],
+                vec![rx_info::Model {
+                    rx_id: 5,
+                    rx_name: "fake".to_owned(),
+                    hidden: false,
+                    person_id: None,
+                    retired_date: None,
+                    retired_reason: None,
+                    normalized_name: None,
+                    pharmacy_id: None,
+                    prescriber_id: None,
+                    refills_remaining: None,
+                    script_expires: None,
+                    refills_counted_from: None,
+                    original_fill_pending: false,
+                    dose_units: None,
+                    dose_every_days: None,
+                    critical_days: None,
+                }],
+            ])
+            .append_exec_results(vec![MockExecResult {
+                last_insert_id: 5,
+                rows_affected: 1,
+            }])
+            .into_connection()
+    }
+
+    /// The queries expected for adding an rx without a person that does not already exist.
+    fn expected_insert_log(name: &str, normalized_name: &str) -> Vec<Transaction> {
+        vec![
+            Transaction::from_sql_and_values(
+                DatabaseBackend::Postgres,
+                r#"SELECT "rx_info"."rx_id", "rx_info"."rx_name", "rx_info"."hidden", "rx_info"."person_id", "rx_info"."retired_date", "rx_info"."retired_reason", "rx_info"."normalized_name", "rx_info"."pharmacy_id", "rx_info"."prescriber_id", "rx_info"."refills_remaining", "rx_info"."script_expires", "rx_info"."refills_counted_from", "rx_info"."original_fill_pending", "rx_info"."dose_units", "rx_info"."dose_every_days", "rx_info"."critical_days" FROM "rx_info" WHERE "rx_info"."person_id" IS NULL AND "rx_info"."normalized_name" = $1 LIMIT $2"#,
+                vec![normalized_name.into(), 1u64.into()],
+            ),
+            Transaction::from_sql_and_values(
+                DatabaseBackend::Postgres,
+                r#"INSERT INTO "rx_info" ("rx_name", "normalized_name") VALUES ($1, $2) RETURNING "rx_id""#,
+                vec![name.into(), normalized_name.into()],
+            ),
+        ]
+    }
+
     #[async_std::test]
     async fn test_add_rx() -> Result<(), Error> {
         // Check input verification
@@ -167,52 +585,22 @@ mod test {
         //     empty_results
         // };
         // Check normal operation
-        let db = MockDatabase::new(DatabaseBackend::Postgres)
-            // TODO not sure why this is also needed
-            .append_query_results(vec![vec![rx_info::Model {
-                rx_id: 5,
-                rx_name: "fake".to_owned(),
-                hidden: false,
-            }]])
-            .append_exec_results(vec![MockExecResult {
-                last_insert_id: 5,
-                rows_affected: 1,
-            }])
-            .into_connection();
+        let db = make_insert_mock_db();
         let result = add_rx(&db, "amoxicillin").await;
-        assert_eq!(result, Ok(RxId(5)));
+        assert_eq!(result, Ok(RxAddOutcome::Created(RxId(5))));
         let log = db.into_transaction_log();
         // println!("{:?}", log);
-        assert_eq!(
-            log,
-            vec![Transaction::from_sql_and_values(
-                DatabaseBackend::Postgres,
-                r#"INSERT INTO "rx_info" ("rx_name") VALUES ($1) RETURNING "rx_id""#,
-                vec!["amoxicillin".into()]
-            )]
-        );
+        assert_eq!(log, expected_insert_log("amoxicillin", "amoxicillin"));
 
         // check trimming
-        let db = MockDatabase::new(DatabaseBackend::Postgres)
-            // TODO not sure why this is also needed
-            .append_query_results(vec![vec![rx_info::Model {
-                rx_id: 5,
-                rx_name: "fake".to_owned(),
-                hidden: false,
-            }]])
-            .append_exec_results(vec![MockExecResult {
-                last_insert_id: 5,
-                rows_affected: 1,
-            }])
-            .into_connection();
-        assert_eq!(add_rx(&db, "  amoxicillin  ").await, Ok(RxId(5)));
+        let db = make_insert_mock_db();
+        assert_eq!(
+            add_rx(&db, "  amoxicillin  ").await,
+            Ok(RxAddOutcome::Created(RxId(5)))
+        );
         assert_eq!(
             db.into_transaction_log(),
-            vec![Transaction::from_sql_and_values(
-                DatabaseBackend::Postgres,
-                r#"INSERT INTO "rx_info" ("rx_name") VALUES ($1) RETURNING "rx_id""#,
-                vec!["amoxicillin".into()]
-            )]
+            expected_insert_log("amoxicillin", "amoxicillin")
         );
         Ok(())
     }
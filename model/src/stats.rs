@@ -0,0 +1,310 @@
+// Copyright 2023, Ryan Pavlik <ryan@ryanpavlik.com>
+// SPDX-License-Identifier: GPL3+
+
+//! Adherence and gap statistics for an rx, from its pick-ups and days supply.
+
+use sea_orm::{prelude::TimeDate, ConnectionTrait};
+
+use crate::{
+    days_supply::{days_supply_on, list_days_supply, DaysSupply},
+    events::list_events,
+    fill_request::{FillRequest, FillRequestQuery},
+    CloseReason, Error, EventType, RxId,
+};
+
+/// Statistics for an rx over a window of days.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RxStats {
+    /// First day of the window
+    pub start: TimeDate,
+    /// Last day of the window, inclusive
+    pub end: TimeDate,
+    /// Number of pick-ups in the window
+    pub pickups: usize,
+    /// Fraction of days in the window with supply on hand, from 0 to 1.
+    ///
+    /// Supply picked up early is used once the previous supply runs out,
+    /// and supply picked up before the window counts toward the start of it.
+    pub proportion_of_days_covered: Option<f64>,
+    /// Total days supply picked up in the window, divided by the days in the window.
+    ///
+    /// May be more than 1 if picked up early.
+    pub medication_possession_ratio: Option<f64>,
+    /// Longest run of days in the window without supply on hand
+    pub longest_gap_days: Option<u32>,
+    /// Average number of days from one pick-up in the window to the next
+    pub average_days_between_pickups: Option<f64>,
+}
+
+impl RxStats {
+    /// Number of days in the window.
+    pub fn window_days(&self) -> u32 {
+        window_days(self.start, self.end)
+    }
+}
+
+fn window_days(start: TimeDate, end: TimeDate) -> u32 {
+    u32::try_from((end - start).whole_days() + 1).unwrap_or_default()
+}
+
+/// Mark which days of the window have supply on hand, given all pick-up dates in order.
+fn covered_days(
+    pickups: &[TimeDate],
+    days_supply: &[DaysSupply],
+    start: TimeDate,
+    end: TimeDate,
+) -> Option<Vec<bool>> {
+    let mut covered = vec![false; window_days(start, end) as usize];
+    let mut next_uncovered: Option<TimeDate> = None;
+    for &pickup in pickups.iter().filter(|&&pickup| pickup <= end) {
+        let supply = days_supply_on(days_supply, pickup)?;
+        let supply_start = match next_uncovered {
+            Some(date) if date > pickup => date,
+            _ => pickup,
+        };
+        let supply_end = supply_start + supply.duration();
+        let mut date = supply_start.max(start);
+        while date < supply_end && date <= end {
+            covered[(date - start).whole_days() as usize] = true;
+            date = date.next_day()?;
+        }
+        next_uncovered = Some(supply_end);
+    }
+    Some(covered)
+}
+
+fn longest_gap(covered: &[bool]) -> u32 {
+    let mut longest = 0;
+    let mut current = 0;
+    for &day in covered {
+        if day {
+            current = 0;
+        } else {
+            current += 1;
+            longest = longest.max(current);
+        }
+    }
+    longest
+}
+
+/// Compute statistics from the pick-up dates of an rx (in order) and its days supply history,
+/// over the window from `start` to `end` inclusive.
+///
+/// Pick-ups before the window only count toward days covered.
+/// Statistics that need a days supply are `None` if none is known.
+pub fn compute_stats(
+    pickups: &[TimeDate],
+    days_supply: &[DaysSupply],
+    start: TimeDate,
+    end: TimeDate,
+) -> Result<RxStats, Error> {
+    if end < start {
+        return Err(Error::InvalidDateRange(start, end));
+    }
+    let days = f64::from(window_days(start, end));
+    let in_window: Vec<TimeDate> = pickups
+        .iter()
+        .copied()
+        .filter(|pickup| (start..=end).contains(pickup))
+        .collect();
+
+    let covered = covered_days(pickups, days_supply, start, end);
+    let proportion_of_days_covered = covered
+        .as_ref()
+        .map(|covered| covered.iter().filter(|&&day| day).count() as f64 / days);
+    let longest_gap_days = covered.as_deref().map(longest_gap);
+
+    let medication_possession_ratio = in_window
+        .iter()
+        .map(|&pickup| days_supply_on(days_supply, pickup).map(|supply| supply.days))
+        .sum::<Option<u32>>()
+        .map(|total| f64::from(total) / days);
+
+    let average_days_between_pickups = if in_window.len() > 1 {
+        let span = (in_window[in_window.len() - 1] - in_window[0]).whole_days();
+        Some(span as f64 / (in_window.len() - 1) as f64)
+    } else {
+        None
+    };
+
+    Ok(RxStats {
+        start,
+        end,
+        pickups: in_window.len(),
+        proportion_of_days_covered,
+        medication_possession_ratio,
+        longest_gap_days,
+        average_days_between_pickups,
+    })
+}
+
+/// How the fill requests of an rx made in a window turned out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RequestOutcomes {
+    pub open: usize,
+    pub completed: usize,
+    pub superseded: usize,
+    pub cancelled: usize,
+}
+
+impl RequestOutcomes {
+    /// Count a request by why it was closed, if it was.
+    pub fn add(&mut self, close_reason: Option<CloseReason>) {
+        match close_reason {
+            None => self.open += 1,
+            Some(CloseReason::Completed) => self.completed += 1,
+            Some(CloseReason::Superseded) => self.superseded += 1,
+            Some(CloseReason::Cancelled) => self.cancelled += 1,
+        }
+    }
+}
+
+/// Count the outcomes of the fill requests of an rx that started in the window from `start` to `end` inclusive.
+///
+/// A request starts when it was requested, or filled or picked up if it was not requested first.
+pub async fn request_outcomes(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    start: TimeDate,
+    end: TimeDate,
+) -> Result<RequestOutcomes, Error> {
+    if end < start {
+        return Err(Error::InvalidDateRange(start, end));
+    }
+    let mut outcomes = RequestOutcomes::default();
+    for request in FillRequestQuery::new().rx([rx]).list(db).await? {
+        let started = request
+            .date_requested()
+            .or(*request.date_filled())
+            .or(*request.date_picked_up());
+        if started.is_some_and(|date| (start..=end).contains(&date)) {
+            outcomes.add(request.close_reason());
+        }
+    }
+    Ok(outcomes)
+}
+
+/// Compute statistics for an rx over the window from `start` to `end` inclusive.
+pub async fn rx_stats(
+    db: &impl ConnectionTrait,
+    rx: RxId,
+    start: TimeDate,
+    end: TimeDate,
+) -> Result<RxStats, Error> {
+    let pickups: Vec<TimeDate> = list_events(db, rx, ..=end)
+        .await?
+        .into_iter()
+        .filter(|event| event.event_type == EventType::PickUp)
+        .map(|event| event.date)
+        .collect();
+    let days_supply = list_days_supply(db, rx).await?;
+    compute_stats(&pickups, &days_supply, start, end)
+}
+
+#[cfg(test)]
+mod test {
+
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::Database;
+    use time::{Date, Duration, Month};
+
+    use super::*;
+    use crate::{
+        days_supply::set_days_supply,
+        fill_request::{cancel_fill_request, record_fill_request, record_pickup},
+        rx::add_rx,
+    };
+
+    #[test]
+    fn test_compute_stats() {
+        let start = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let day = |n| start + Duration::days(n);
+        let end = day(89);
+        let thirty_days = [DaysSupply {
+            effective_date: start,
+            days: 30,
+        }];
+
+        // Picked up 10 days before the window, so covered for the first 20 days,
+        // then 5 days early at day 15 (covered through day 49), then late at day 60.
+        let pickups = [day(-10), day(15), day(60)];
+        let stats = compute_stats(&pickups, &thirty_days, start, end).unwrap();
+        assert_eq!(stats.window_days(), 90);
+        assert_eq!(stats.pickups, 2);
+        assert_eq!(stats.proportion_of_days_covered, Some(80.0 / 90.0));
+        assert_eq!(stats.medication_possession_ratio, Some(60.0 / 90.0));
+        assert_eq!(stats.longest_gap_days, Some(10));
+        assert_eq!(stats.average_days_between_pickups, Some(45.0));
+
+        // Nothing picked up
+        let stats = compute_stats(&[], &thirty_days, start, end).unwrap();
+        assert_eq!(stats.proportion_of_days_covered, Some(0.0));
+        assert_eq!(stats.longest_gap_days, Some(90));
+        assert_eq!(stats.average_days_between_pickups, None);
+
+        // No days supply known
+        let stats = compute_stats(&pickups, &[], start, end).unwrap();
+        assert_eq!(stats.proportion_of_days_covered, None);
+        assert_eq!(stats.medication_possession_ratio, None);
+        assert_eq!(stats.longest_gap_days, None);
+        assert_eq!(stats.average_days_between_pickups, Some(45.0));
+
+        assert_eq!(
+            compute_stats(&pickups, &thirty_days, end, start),
+            Err(Error::InvalidDateRange(end, start))
+        );
+    }
+
+    #[async_std::test]
+    async fn test_rx_stats() -> Result<(), Error> {
+        let start = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let day = |n| start + Duration::days(n);
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+        set_days_supply(&db, amox_id, 30, start).await?;
+
+        record_pickup(&db, amox_id, Some(day(0)), day(0)).await?;
+        record_pickup(&db, amox_id, Some(day(30)), day(30)).await?;
+        // After the window, ignored
+        record_pickup(&db, amox_id, Some(day(60)), day(60)).await?;
+
+        let stats = rx_stats(&db, amox_id, start, day(59)).await?;
+        assert_eq!(stats.pickups, 2);
+        assert_eq!(stats.proportion_of_days_covered, Some(1.0));
+        assert_eq!(stats.medication_possession_ratio, Some(1.0));
+        assert_eq!(stats.longest_gap_days, Some(0));
+        assert_eq!(stats.average_days_between_pickups, Some(30.0));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_request_outcomes() -> Result<(), Error> {
+        let start = Date::from_calendar_date(2023, Month::January, 1).unwrap();
+        let day = |n| start + Duration::days(n);
+        let db = Database::connect("sqlite::memory:").await?;
+        Migrator::up(&db, None).await?;
+        let amox_id = add_rx(&db, "amoxicillin").await?.id();
+
+        // Before the window, ignored
+        record_pickup(&db, amox_id, Some(day(-10)), day(-10)).await?;
+        record_fill_request(&db, amox_id, day(1)).await?;
+        record_fill_request(&db, amox_id, day(3)).await?;
+        record_pickup(&db, amox_id, Some(day(4)), day(5)).await?;
+        record_fill_request(&db, amox_id, day(20)).await?;
+        cancel_fill_request(&db, amox_id, day(21), "").await?;
+        record_fill_request(&db, amox_id, day(25)).await?;
+
+        let outcomes = request_outcomes(&db, amox_id, start, day(29)).await?;
+        assert_eq!(
+            outcomes,
+            RequestOutcomes {
+                open: 1,
+                completed: 1,
+                superseded: 1,
+                cancelled: 1,
+            }
+        );
+        Ok(())
+    }
+}